@@ -2,41 +2,121 @@ use std::{path::PathBuf, sync::Mutex};
 use tauri::{command, AppHandle, Manager, State};
 use tauri_plugin_log::log::{self};
 
-use crate::utils::{create_dir_internal, get_full_path};
+use crate::backup::{self, BackupScheduler};
+use crate::error::HollowError;
+use crate::lock::RealmLock;
+use crate::migrations;
+use crate::permissions::{ensure_app, Sessions};
+use crate::search::SearchIndex;
+use crate::trash;
+use crate::utils::{create_dir_internal, get_realm_root, Recovery};
+use crate::watcher::CardWatchers;
 
 #[derive(Default, Debug)]
 pub struct AppData {
     pub realm_location: Option<String>,
+    /// Set by `start_realm`, mutating commands reject with `ReadOnly`
+    pub read_only: bool,
+    /// Caller tokens of the main window's page
+    pub sessions: Sessions,
 }
 
+/// Opens the realm at `location`. With `read_only` nothing in the realm is
+/// written: the realm isn't locked, migrated or backed up and mutating
+/// commands fail, which also allows opening a realm locked by another process.
 #[command]
-pub fn start_realm(
+pub async fn start_realm(
     location: String,
+    read_only: Option<bool>,
+    caller: String,
     state: State<'_, Mutex<AppData>>,
     app: AppHandle,
-) -> Result<(), String> {
+) -> Result<(), HollowError> {
+    ensure_app(&caller, &state)?;
+    let read_only = read_only.unwrap_or(false);
+    // Checked before locking, which creates `.hollow/` for the lock file. Not
+    // `realm.json` either, realms from before it existed still need migrating.
+    let is_new = !PathBuf::from(&location).join(".hollow").exists();
+    {
+        let lock_state = app.state::<Mutex<RealmLock>>();
+        let mut realm_lock = lock_state.lock().unwrap();
+        if read_only {
+            realm_lock.release();
+        } else {
+            // Lock before switching so a realm in use elsewhere leaves the current one open
+            realm_lock.acquire(&PathBuf::from(&location), &app)?;
+        }
+    }
     {
         let mut app_data = state.lock().unwrap();
         app_data.realm_location = Some(location.clone());
+        app_data.read_only = read_only;
     }
+    // Watchers belong to the previous realm's cards
+    app.state::<Mutex<CardWatchers>>().lock().unwrap().clear();
+    app.state::<Mutex<SearchIndex>>().lock().unwrap().clear();
 
-    let dot_dir = get_full_path(".hollow", &state)?;
-    if !dot_dir.exists() {
-        create_dir_internal(".hollow", &state)?;
-        create_dir_internal("vault", &state)?;
-        create_dir_internal("plugins", &state)?;
-        create_dir_internal("main", &state)?;
-    }
-    {
-        let assets_scope = app.asset_protocol_scope();
-        let full_path = PathBuf::from(&location);
-        let _ = assets_scope.allow_directory(full_path, true);
+    if read_only {
+        app.state::<Mutex<BackupScheduler>>().lock().unwrap().stop();
+        let realm_root = get_realm_root(&state)?;
+        if migrations::schema_version(&realm_root, Recovery::InMemory)? < migrations::SCHEMA_VERSION
+        {
+            log::warn!("Opened an outdated realm read-only, it won't be migrated");
+        }
+    } else {
+        if is_new {
+            create_dir_internal(".hollow", &state)?;
+            create_dir_internal("vault", &state)?;
+            create_dir_internal("plugins", &state)?;
+            create_dir_internal("main", &state)?;
+            migrations::write_info(&get_realm_root(&state)?, migrations::SCHEMA_VERSION)?;
+        }
+        // Archived outside the realm so exports and backups don't include it
+        let realm_root = get_realm_root(&state)?;
+        let archive_dir = app
+            .path()
+            .app_data_dir()
+            .map_err(|e| HollowError::Internal(format!("Could not get app data directory: {}", e)))?
+            .join("migrations")
+            .join(backup::realm_folder_name(&realm_root));
+        let handle = app.clone();
+        tauri::async_runtime::spawn_blocking(move || {
+            migrations::migrate(&realm_root, &archive_dir, &|progress| {
+                migrations::emit_progress(&handle, progress)
+            })
+        })
+        .await
+        .map_err(|e| HollowError::Internal(e.to_string()))??;
+        if let Err(e) = trash::purge_expired(get_realm_root(&state)?) {
+            log::warn!("Could not purge trash: {}", e);
+        }
+        app.state::<Mutex<BackupScheduler>>()
+            .lock()
+            .unwrap()
+            .start(get_realm_root(&state)?);
     }
 
-    log::info!("Initialized realm data");
+    log::info!("Initialized realm data (read-only: {})", read_only);
     Ok(())
 }
 
+/// Resets everything tied to the open realm. Its files stop being served by
+/// the `realm` protocol along with `realm_location`.
+pub fn close_realm(app: &AppHandle, state: &State<'_, Mutex<AppData>>) {
+    let location = {
+        let mut app_data = state.lock().unwrap();
+        app_data.read_only = false;
+        app_data.realm_location.take()
+    };
+    app.state::<Mutex<RealmLock>>().lock().unwrap().release();
+    app.state::<Mutex<BackupScheduler>>().lock().unwrap().stop();
+    app.state::<Mutex<CardWatchers>>().lock().unwrap().clear();
+    app.state::<Mutex<SearchIndex>>().lock().unwrap().clear();
+    if let Some(location) = location {
+        log::info!("Closed realm {}", location);
+    }
+}
+
 #[command]
 pub fn get_version() -> String {
     env!("CARGO_PKG_VERSION").to_string()
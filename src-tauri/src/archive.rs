@@ -0,0 +1,571 @@
+use crate::cards::build_glob_set;
+use crate::error::HollowError;
+use crate::lock::LOCK_FILE;
+use crate::permissions::ensure_app;
+use crate::utils::{get_realm_root, is_internal_file, temp_path, validate_path};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tauri::{command, AppHandle, Emitter, State};
+use tauri_plugin_log::log;
+use walkdir::WalkDir;
+use zip::{result::ZipError, write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+pub const ARCHIVE_PROGRESS_EVENT: &str = "realm-archive-progress";
+const MANIFEST_NAME: &str = "hollow-manifest.json";
+/// Bump when the archive layout changes, newer archives are refused on import
+const FORMAT_VERSION: u32 = 1;
+const REALM_DIRS: [&str; 4] = [".hollow", "main", "vault", "plugins"];
+const LEGACY_MIGRATIONS_DIR: &str = ".hollow/migrations/";
+
+#[derive(Serialize, Deserialize)]
+struct ManifestFile {
+    /// Realm-relative, using `/`
+    path: String,
+    size: u64,
+    sha256: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ArchiveManifest {
+    format_version: u32,
+    app_version: String,
+    /// Milliseconds since the unix epoch
+    created: u64,
+    files: Vec<ManifestFile>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ExportOptions {
+    /// Globs matched against realm-relative paths like `main/notebook/**`
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct ArchiveProgress {
+    /// "export" or "import"
+    operation: &'static str,
+    done: usize,
+    total: usize,
+    path: String,
+}
+
+#[derive(Serialize)]
+pub struct ArchiveSummary {
+    /// The archive for exports, the new realm for imports
+    pub path: PathBuf,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+pub fn zip_error(path: &Path, error: ZipError) -> HollowError {
+    match error {
+        ZipError::Io(e) => HollowError::io(path, e),
+        e => HollowError::parse(path, e),
+    }
+}
+
+pub fn emit_progress(app: &AppHandle, progress: ArchiveProgress) {
+    if let Err(e) = app.emit(ARCHIVE_PROGRESS_EVENT, progress) {
+        log::error!("Failed to emit archive progress: {}", e);
+    }
+}
+
+/// Copies `reader` into `writer`, returning the size and sha256 of what passed through.
+fn copy_hashing(reader: &mut impl Read, writer: &mut impl Write) -> std::io::Result<(u64, String)> {
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    let mut size = 0;
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        writer.write_all(&buffer[..read])?;
+        size += read as u64;
+    }
+    Ok((size, format!("{:x}", hasher.finalize())))
+}
+
+fn collect_files(
+    realm_root: &Path,
+    options: &ExportOptions,
+) -> Result<Vec<(String, PathBuf)>, HollowError> {
+    let include = build_glob_set(&options.include)?;
+    let exclude = build_glob_set(&options.exclude)?;
+    let mut files = Vec::new();
+
+    for dir in REALM_DIRS {
+        let root = realm_root.join(dir);
+        if !root.exists() {
+            continue;
+        }
+        for entry in WalkDir::new(&root).sort_by_file_name() {
+            let entry = entry.map_err(|e| {
+                let path = e.path().unwrap_or(&root).to_path_buf();
+                HollowError::io(path, e.into())
+            })?;
+            let name = entry.file_name().to_string_lossy();
+            // Symlinks aren't followed, they could point outside the realm
+            if !entry.file_type().is_file() || is_internal_file(&name) {
+                continue;
+            }
+            let Ok(relative) = entry.path().strip_prefix(realm_root) else {
+                continue;
+            };
+            let relative = relative.to_string_lossy().replace('\\', "/");
+            // The lock only means something to the process holding it, and
+            // older versions kept whole realm archives from migrations here
+            if relative == LOCK_FILE || relative.starts_with(LEGACY_MIGRATIONS_DIR) {
+                continue;
+            }
+            if include.as_ref().is_some_and(|set| !set.is_match(&relative))
+                || exclude.as_ref().is_some_and(|set| set.is_match(&relative))
+            {
+                continue;
+            }
+            files.push((relative, entry.into_path()));
+        }
+    }
+    Ok(files)
+}
+
+fn write_archive(
+    realm_root: &Path,
+    destination: &Path,
+    options: &ExportOptions,
+    progress: &dyn Fn(ArchiveProgress),
+) -> Result<ArchiveSummary, HollowError> {
+    let files = collect_files(realm_root, options)?;
+    let file = File::create(destination).map_err(|e| HollowError::io(destination, e))?;
+    let mut zip = ZipWriter::new(BufWriter::new(file));
+    let mut manifest = ArchiveManifest {
+        format_version: FORMAT_VERSION,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        created: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default(),
+        files: Vec::with_capacity(files.len()),
+    };
+
+    for (done, (relative, path)) in files.iter().enumerate() {
+        let mut source = File::open(path).map_err(|e| HollowError::io(path, e))?;
+        let large = source.metadata().is_ok_and(|m| m.len() >= u32::MAX as u64);
+        let entry_options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .large_file(large);
+        zip.start_file(relative.as_str(), entry_options)
+            .map_err(|e| zip_error(destination, e))?;
+        let (size, sha256) =
+            copy_hashing(&mut source, &mut zip).map_err(|e| HollowError::io(path, e))?;
+        manifest.files.push(ManifestFile {
+            path: relative.clone(),
+            size,
+            sha256,
+        });
+        progress(ArchiveProgress {
+            operation: "export",
+            done: done + 1,
+            total: files.len(),
+            path: relative.clone(),
+        });
+    }
+
+    let content =
+        serde_json::to_vec_pretty(&manifest).map_err(|e| HollowError::Internal(e.to_string()))?;
+    zip.start_file(MANIFEST_NAME, SimpleFileOptions::default())
+        .map_err(|e| zip_error(destination, e))?;
+    zip.write_all(&content)
+        .map_err(|e| HollowError::io(destination, e))?;
+    let writer = zip.finish().map_err(|e| zip_error(destination, e))?;
+    let file = writer
+        .into_inner()
+        .map_err(|e| HollowError::io(destination, e.into_error()))?;
+    file.sync_all()
+        .map_err(|e| HollowError::io(destination, e))?;
+
+    Ok(ArchiveSummary {
+        path: destination.to_path_buf(),
+        files: manifest.files.len(),
+        bytes: manifest.files.iter().map(|f| f.size).sum(),
+    })
+}
+
+/// Packs the realm into a zip at `destination`, written through a temp file
+/// so a failed export never leaves a partial archive.
+pub fn export(
+    realm_root: &Path,
+    destination: &Path,
+    options: &ExportOptions,
+    progress: &dyn Fn(ArchiveProgress),
+) -> Result<ArchiveSummary, HollowError> {
+    if !destination.is_absolute() {
+        return Err(HollowError::InvalidInput(
+            "Export to an absolute path".to_string(),
+        ));
+    }
+    let staged = temp_path(destination);
+    let summary = write_archive(realm_root, &staged, options, progress).inspect_err(|_| {
+        let _ = fs::remove_file(&staged);
+    })?;
+    fs::rename(&staged, destination).map_err(|e| HollowError::io(destination, e))?;
+    log::info!(
+        "Exported {} files to {}",
+        summary.files,
+        destination.display()
+    );
+    Ok(ArchiveSummary {
+        path: destination.to_path_buf(),
+        ..summary
+    })
+}
+
+fn read_manifest(
+    zip: &mut ZipArchive<File>,
+    archive: &Path,
+) -> Result<ArchiveManifest, HollowError> {
+    let entry = zip
+        .by_name(MANIFEST_NAME)
+        .map_err(|_| HollowError::parse(archive, "Not a realm archive, the manifest is missing"))?;
+    let manifest: ArchiveManifest =
+        serde_json::from_reader(entry).map_err(|e| HollowError::parse(archive, e))?;
+    if manifest.format_version > FORMAT_VERSION {
+        return Err(HollowError::InvalidInput(format!(
+            "The archive was made by a newer version ({})",
+            manifest.app_version
+        )));
+    }
+    Ok(manifest)
+}
+
+/// Extracts every file and checks it against the manifest.
+fn extract_verified(
+    zip: &mut ZipArchive<File>,
+    archive: &Path,
+    manifest: &ArchiveManifest,
+    staging: &Path,
+    progress: &dyn Fn(ArchiveProgress),
+) -> Result<(), HollowError> {
+    let mut expected: HashMap<&str, &ManifestFile> = manifest
+        .files
+        .iter()
+        .map(|file| (file.path.as_str(), file))
+        .collect();
+    let total = expected.len();
+
+    for index in 0..zip.len() {
+        let mut entry = zip.by_index(index).map_err(|e| zip_error(archive, e))?;
+        let name = entry.name().to_string();
+        if entry.is_dir() || name == MANIFEST_NAME {
+            continue;
+        }
+        let Some(file) = expected.remove(name.as_str()) else {
+            return Err(HollowError::parse(
+                archive,
+                format!("{} is not in the manifest", name),
+            ));
+        };
+        validate_path(&name)?;
+        if !REALM_DIRS
+            .iter()
+            .any(|dir| name.starts_with(&format!("{}/", dir)))
+        {
+            return Err(HollowError::parse(
+                archive,
+                format!("{} is outside the realm folders", name),
+            ));
+        }
+
+        let target = staging.join(&name);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(|e| HollowError::io(parent, e))?;
+        }
+        let mut output = File::create(&target).map_err(|e| HollowError::io(&target, e))?;
+        // The zip headers can lie about sizes, a byte past the manifest's is enough to reject
+        let mut limited = (&mut entry).take(file.size + 1);
+        let (size, sha256) =
+            copy_hashing(&mut limited, &mut output).map_err(|e| HollowError::io(&target, e))?;
+        if size > file.size {
+            return Err(HollowError::parse(
+                archive,
+                format!("{} is larger than the manifest says", name),
+            ));
+        }
+        if size != file.size || sha256 != file.sha256 {
+            return Err(HollowError::parse(
+                archive,
+                format!("Checksum mismatch for {}", name),
+            ));
+        }
+        progress(ArchiveProgress {
+            operation: "import",
+            done: total - expected.len(),
+            total,
+            path: name,
+        });
+    }
+
+    if let Some(missing) = expected.keys().next() {
+        return Err(HollowError::parse(
+            archive,
+            format!("{} is missing from the archive", missing),
+        ));
+    }
+    for dir in REALM_DIRS {
+        let dir = staging.join(dir);
+        fs::create_dir_all(&dir).map_err(|e| HollowError::io(&dir, e))?;
+    }
+    Ok(())
+}
+
+/// Unpacks an archive into a new realm at `location`, which must not exist or
+/// be empty. Nothing appears there unless every file verifies.
+pub fn import(
+    archive: &Path,
+    location: &Path,
+    progress: &dyn Fn(ArchiveProgress),
+) -> Result<ArchiveSummary, HollowError> {
+    if !location.is_absolute() {
+        return Err(HollowError::InvalidInput(
+            "Import into an absolute path".to_string(),
+        ));
+    }
+    if location.exists() {
+        let empty = fs::read_dir(location)
+            .map_err(|e| HollowError::io(location, e))?
+            .next()
+            .is_none();
+        if !empty {
+            return Err(HollowError::AlreadyExists(location.to_path_buf()));
+        }
+    }
+
+    let file = File::open(archive).map_err(|e| HollowError::io(archive, e))?;
+    let mut zip = ZipArchive::new(file).map_err(|e| zip_error(archive, e))?;
+    let manifest = read_manifest(&mut zip, archive)?;
+
+    if let Some(parent) = location.parent() {
+        fs::create_dir_all(parent).map_err(|e| HollowError::io(parent, e))?;
+    }
+    let staging = temp_path(location);
+    let result =
+        extract_verified(&mut zip, archive, &manifest, &staging, progress).and_then(|_| {
+            if location.exists() {
+                fs::remove_dir(location).map_err(|e| HollowError::io(location, e))?;
+            }
+            fs::rename(&staging, location).map_err(|e| HollowError::io(location, e))
+        });
+    if let Err(e) = result {
+        if let Err(e) = fs::remove_dir_all(&staging) {
+            log::warn!("Could not clean up {}: {}", staging.display(), e);
+        }
+        return Err(e);
+    }
+
+    log::info!(
+        "Imported {} files into {}",
+        manifest.files.len(),
+        location.display()
+    );
+    Ok(ArchiveSummary {
+        path: location.to_path_buf(),
+        files: manifest.files.len(),
+        bytes: manifest.files.iter().map(|f| f.size).sum(),
+    })
+}
+
+/// Exports the open realm to a zip archive, reporting progress through
+/// `realm-archive-progress` events.
+#[command]
+pub async fn realm_export(
+    destination: String,
+    options: Option<ExportOptions>,
+    app: AppHandle,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<ArchiveSummary, HollowError> {
+    ensure_app(&caller, &state)?;
+    let realm_root = get_realm_root(&state)?;
+    if Path::new(&destination).starts_with(&realm_root) {
+        return Err(HollowError::InvalidInput(
+            "Export to a path outside the realm".to_string(),
+        ));
+    }
+    tauri::async_runtime::spawn_blocking(move || {
+        export(
+            &realm_root,
+            Path::new(&destination),
+            &options.unwrap_or_default(),
+            &|progress| emit_progress(&app, progress),
+        )
+    })
+    .await
+    .map_err(|e| HollowError::Internal(e.to_string()))?
+}
+
+/// Creates a realm at `location` from an exported archive. The realm isn't
+/// opened, call `start_realm` with the location afterwards.
+#[command]
+pub async fn realm_import(
+    archive: String,
+    location: String,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+    app: AppHandle,
+) -> Result<ArchiveSummary, HollowError> {
+    ensure_app(&caller, &state)?;
+    tauri::async_runtime::spawn_blocking(move || {
+        import(Path::new(&archive), Path::new(&location), &|progress| {
+            emit_progress(&app, progress)
+        })
+    })
+    .await
+    .map_err(|e| HollowError::Internal(e.to_string()))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(realm_root: &Path, relative: &str, contents: &str) {
+        let path = realm_root.join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    /// An archive whose manifest claims `contents` for every entry
+    fn write_zip(path: &Path, entries: &[(&str, &str)], claimed: &str) {
+        let mut zip = ZipWriter::new(File::create(path).unwrap());
+        let mut files = Vec::new();
+        for (name, contents) in entries {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+            files.push(ManifestFile {
+                path: name.to_string(),
+                size: claimed.len() as u64,
+                sha256: format!("{:x}", Sha256::digest(claimed)),
+            });
+        }
+        let manifest = ArchiveManifest {
+            format_version: FORMAT_VERSION,
+            app_version: "test".to_string(),
+            created: 0,
+            files,
+        };
+        zip.start_file(MANIFEST_NAME, SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(&serde_json::to_vec(&manifest).unwrap())
+            .unwrap();
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    fn round_trips_a_realm() {
+        let dir = tempfile::tempdir().unwrap();
+        let realm = dir.path().join("realm");
+        write(&realm, "main/notebook/a/note.md", "# Note");
+        write(&realm, "vault/photo.png", "png");
+        write(&realm, ".hollow/settings.json", "{}");
+        write(&realm, LOCK_FILE, "held");
+        write(&realm, "main/notebook/a/note.md.hollow-bak", "old");
+        write(&realm, "other/ignored.txt", "outside");
+
+        let archive = dir.path().join("realm.zip");
+        let summary = export(&realm, &archive, &ExportOptions::default(), &|_| {}).unwrap();
+        assert_eq!(summary.files, 3);
+        assert_eq!(summary.bytes, 11);
+
+        let copy = dir.path().join("copy");
+        let summary = import(&archive, &copy, &|_| {}).unwrap();
+        assert_eq!(summary.files, 3);
+        assert_eq!(
+            fs::read_to_string(copy.join("main/notebook/a/note.md")).unwrap(),
+            "# Note"
+        );
+        assert_eq!(
+            fs::read_to_string(copy.join("vault/photo.png")).unwrap(),
+            "png"
+        );
+        assert!(!copy.join(LOCK_FILE).exists());
+        assert!(!copy.join("other").exists());
+        assert!(copy.join("plugins").is_dir());
+
+        // Only into an empty location
+        assert!(matches!(
+            import(&archive, &copy, &|_| {}),
+            Err(HollowError::AlreadyExists(_))
+        ));
+    }
+
+    #[test]
+    fn exports_only_the_matching_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let realm = dir.path().join("realm");
+        write(&realm, "main/notebook/a/note.md", "note");
+        write(&realm, "main/notebook/a/draft.md", "draft");
+        write(&realm, "main/kanban/b/board.json", "{}");
+        let options = ExportOptions {
+            include: vec!["main/notebook/**".to_string()],
+            exclude: vec!["**/draft.md".to_string()],
+        };
+        let archive = dir.path().join("notes.zip");
+        assert_eq!(
+            export(&realm, &archive, &options, &|_| {}).unwrap().files,
+            1
+        );
+        assert!(export(&realm, Path::new("relative.zip"), &options, &|_| {}).is_err());
+    }
+
+    #[test]
+    fn rejects_checksum_mismatches() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("bad.zip");
+        write_zip(&archive, &[("main/a/b/x.md", "tampered")], "original");
+        let location = dir.path().join("realm");
+
+        let error = import(&archive, &location, &|_| {})
+            .map(|_| ())
+            .unwrap_err();
+        assert!(error.to_string().contains("Checksum mismatch"), "{}", error);
+        // Nothing is left behind, not even the staging folder
+        assert!(!location.exists());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn rejects_entries_larger_than_the_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("large.zip");
+        write_zip(
+            &archive,
+            &[("main/a/b/x.md", "much longer than claimed")],
+            "short",
+        );
+        let error = import(&archive, &dir.path().join("realm"), &|_| {})
+            .map(|_| ())
+            .unwrap_err();
+        assert!(error.to_string().contains("larger than"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_entries_outside_the_realm_folders() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("outside.zip");
+        write_zip(&archive, &[("elsewhere/x.md", "x")], "x");
+        let error = import(&archive, &dir.path().join("realm"), &|_| {})
+            .map(|_| ())
+            .unwrap_err();
+        assert!(error.to_string().contains("outside"), "{}", error);
+    }
+}
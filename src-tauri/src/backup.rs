@@ -0,0 +1,460 @@
+use crate::archive::{self, emit_progress, ArchiveSummary, ExportOptions};
+use crate::error::HollowError;
+use crate::permissions::ensure_app;
+use crate::utils::{
+    ensure_writable, get_realm_root, read_json_recovering, recovery, validate_path, write_atomic,
+    Recovery,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Mutex,
+    },
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tauri::{command, AppHandle, State};
+use tauri_plugin_log::log;
+
+const BACKUP_PREFIX: &str = "backup-";
+const BACKUP_EXTENSION: &str = ".zip";
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default, rename_all = "camelCase")]
+pub struct BackupSettings {
+    pub enabled: bool,
+    /// Absolute folder outside the realm, each realm gets a subfolder named
+    /// after it and a hash of its location
+    pub directory: Option<String>,
+    pub interval_minutes: u64,
+    /// The newest backup of each of the last `keep_daily` days is kept
+    pub keep_daily: usize,
+    /// Likewise for weeks, on top of the daily ones
+    pub keep_weekly: usize,
+}
+
+impl Default for BackupSettings {
+    fn default() -> Self {
+        BackupSettings {
+            enabled: false,
+            directory: None,
+            interval_minutes: 60,
+            keep_daily: 7,
+            keep_weekly: 4,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct BackupInfo {
+    /// File name of the archive
+    pub id: String,
+    /// Milliseconds since the unix epoch
+    pub created: u64,
+    pub size: u64,
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+fn settings_path(realm_root: &Path) -> PathBuf {
+    realm_root.join(".hollow").join("backup.json")
+}
+
+pub fn load_settings(realm_root: &Path, recovery: Recovery) -> Result<BackupSettings, HollowError> {
+    Ok(read_json_recovering(&settings_path(realm_root), recovery)?.unwrap_or_default())
+}
+
+/// Folder name for files kept outside a realm, its name and a hash of its
+/// location so realms with the same folder name don't prune each other's files.
+pub fn realm_folder_name(realm_root: &Path) -> String {
+    let realm_name = realm_root
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| "realm".to_string());
+    let canonical = fs::canonicalize(realm_root).unwrap_or_else(|_| realm_root.to_path_buf());
+    let hash = format!(
+        "{:x}",
+        Sha256::digest(canonical.to_string_lossy().as_bytes())
+    );
+    format!("{}-{}", realm_name, &hash[..12])
+}
+
+fn backup_dir(realm_root: &Path, settings: &BackupSettings) -> Result<PathBuf, HollowError> {
+    let directory = settings
+        .directory
+        .as_deref()
+        .ok_or_else(|| HollowError::InvalidInput("No backup folder configured".to_string()))?;
+    Ok(Path::new(directory).join(realm_folder_name(realm_root)))
+}
+
+/// Newest first.
+fn list_backups(dir: &Path) -> Result<Vec<BackupInfo>, HollowError> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(HollowError::io(dir, e)),
+    };
+    let mut backups: Vec<BackupInfo> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let id = entry.file_name().to_string_lossy().to_string();
+            let created = id
+                .strip_prefix(BACKUP_PREFIX)?
+                .strip_suffix(BACKUP_EXTENSION)?
+                .parse()
+                .ok()?;
+            let size = entry.metadata().map(|m| m.len()).unwrap_or_default();
+            Some(BackupInfo { id, created, size })
+        })
+        .collect();
+    backups.sort_by_key(|b| std::cmp::Reverse(b.created));
+    Ok(backups)
+}
+
+/// Keeps the newest backup per day for `keep_daily` days and per week for
+/// `keep_weekly` weeks. The newest backup always survives.
+fn prune(dir: &Path, settings: &BackupSettings) -> Result<(), HollowError> {
+    let backups = list_backups(dir)?;
+    let mut days = HashSet::new();
+    let mut weeks = HashSet::new();
+    for (index, backup) in backups.iter().enumerate() {
+        let day = backup.created / DAY_MS;
+        // Day 0 was a Thursday, shift so weeks start on Monday
+        let week = (day + 3) / 7;
+        let daily = days.len() < settings.keep_daily && days.insert(day);
+        let weekly = weeks.len() < settings.keep_weekly && weeks.insert(week);
+        if index == 0 || daily || weekly {
+            continue;
+        }
+        let path = dir.join(&backup.id);
+        if let Err(e) = fs::remove_file(&path) {
+            log::warn!("Could not remove old backup {}: {}", path.display(), e);
+        }
+    }
+    Ok(())
+}
+
+pub fn take_backup(
+    realm_root: &Path,
+    settings: &BackupSettings,
+) -> Result<BackupInfo, HollowError> {
+    let dir = backup_dir(realm_root, settings)?;
+    fs::create_dir_all(&dir).map_err(|e| HollowError::io(&dir, e))?;
+    let created = now_millis();
+    let id = format!("{}{}{}", BACKUP_PREFIX, created, BACKUP_EXTENSION);
+    let summary = archive::export(
+        realm_root,
+        &dir.join(&id),
+        &ExportOptions::default(),
+        &|_| {},
+    )?;
+    if let Err(e) = prune(&dir, settings) {
+        log::warn!("Could not prune backups: {}", e);
+    }
+    log::info!("Backed up {} files to {}", summary.files, dir.display());
+    Ok(BackupInfo {
+        id,
+        created,
+        size: fs::metadata(&summary.path)
+            .map(|m| m.len())
+            .unwrap_or_default(),
+    })
+}
+
+/// Backs up the open realm on a background thread. Dropping the sender
+/// stops the thread.
+#[derive(Default)]
+pub struct BackupScheduler {
+    stop: Option<mpsc::Sender<()>>,
+}
+
+/// Time until the first backup of a session, the rest of the interval since
+/// the newest backup or none at all without one.
+fn first_wait(newest: Option<u64>, interval: Duration, now: u64) -> Duration {
+    newest.map_or(Duration::ZERO, |created| {
+        interval.saturating_sub(Duration::from_millis(now.saturating_sub(created)))
+    })
+}
+
+fn run_schedule(realm_root: PathBuf, settings: BackupSettings, stop: mpsc::Receiver<()>) {
+    let interval = Duration::from_secs(settings.interval_minutes.max(1) * 60);
+    // Pick up where the previous session left off instead of backing up on every launch
+    let newest = backup_dir(&realm_root, &settings)
+        .and_then(|dir| list_backups(&dir))
+        .ok()
+        .and_then(|backups| backups.first().map(|b| b.created));
+    let mut wait = first_wait(newest, interval, now_millis());
+
+    // Anything but a timeout means the scheduler was stopped
+    while let Err(RecvTimeoutError::Timeout) = stop.recv_timeout(wait) {
+        if let Err(e) = take_backup(&realm_root, &settings) {
+            log::error!("Scheduled backup failed: {}", e);
+        }
+        wait = interval;
+    }
+}
+
+impl BackupScheduler {
+    /// Restarts the schedule for `realm_root` with its saved settings.
+    pub fn start(&mut self, realm_root: PathBuf) {
+        self.stop();
+        // Only writable realms are scheduled
+        let settings = match load_settings(&realm_root, Recovery::Restore) {
+            Ok(settings) => settings,
+            Err(e) => {
+                log::warn!("Backups disabled, could not read settings: {}", e);
+                return;
+            }
+        };
+        if !settings.enabled || settings.directory.is_none() {
+            return;
+        }
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || run_schedule(realm_root, settings, receiver));
+        self.stop = Some(sender);
+    }
+
+    pub fn stop(&mut self) {
+        self.stop = None;
+    }
+}
+
+#[command]
+pub fn backup_get_settings(
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<BackupSettings, HollowError> {
+    ensure_app(&caller, &state)?;
+    load_settings(&get_realm_root(&state)?, recovery(&state))
+}
+
+#[command]
+pub fn backup_set_settings(
+    settings: BackupSettings,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+    scheduler: State<'_, Mutex<BackupScheduler>>,
+) -> Result<(), HollowError> {
+    ensure_writable(&state)?;
+    ensure_app(&caller, &state)?;
+    let realm_root = get_realm_root(&state)?;
+    if let Some(directory) = &settings.directory {
+        let directory = Path::new(directory);
+        if !directory.is_absolute() || directory.starts_with(&realm_root) {
+            return Err(HollowError::InvalidInput(
+                "Backups need an absolute folder outside the realm".to_string(),
+            ));
+        }
+    }
+    let content =
+        serde_json::to_vec_pretty(&settings).map_err(|e| HollowError::Internal(e.to_string()))?;
+    write_atomic(&settings_path(&realm_root), &content)?;
+    scheduler.lock().unwrap().start(realm_root);
+    Ok(())
+}
+
+#[command]
+pub async fn backup_now(
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<BackupInfo, HollowError> {
+    ensure_app(&caller, &state)?;
+    let realm_root = get_realm_root(&state)?;
+    let settings = load_settings(&realm_root, recovery(&state))?;
+    tauri::async_runtime::spawn_blocking(move || take_backup(&realm_root, &settings))
+        .await
+        .map_err(|e| HollowError::Internal(e.to_string()))?
+}
+
+#[command]
+pub fn backup_list(
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<Vec<BackupInfo>, HollowError> {
+    ensure_app(&caller, &state)?;
+    let realm_root = get_realm_root(&state)?;
+    list_backups(&backup_dir(
+        &realm_root,
+        &load_settings(&realm_root, recovery(&state))?,
+    )?)
+}
+
+/// Restores a backup as a new realm at `location`, the open realm is left as is.
+#[command]
+pub async fn backup_restore(
+    id: String,
+    location: String,
+    caller: String,
+    app: AppHandle,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<ArchiveSummary, HollowError> {
+    ensure_app(&caller, &state)?;
+    validate_path(&id)?;
+    if !id.starts_with(BACKUP_PREFIX) || !id.ends_with(BACKUP_EXTENSION) {
+        return Err(HollowError::InvalidInput(format!("Unknown backup {}", id)));
+    }
+    let realm_root = get_realm_root(&state)?;
+    let archive =
+        backup_dir(&realm_root, &load_settings(&realm_root, recovery(&state))?)?.join(&id);
+    tauri::async_runtime::spawn_blocking(move || {
+        archive::import(&archive, Path::new(&location), &|progress| {
+            emit_progress(&app, progress)
+        })
+    })
+    .await
+    .map_err(|e| HollowError::Internal(e.to_string()))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR_MS: u64 = 60 * 60 * 1000;
+    /// A Monday, weeks start there
+    const MONDAY: u64 = 20_003;
+
+    fn backup_at(dir: &Path, created: u64) -> String {
+        let id = format!("{}{}{}", BACKUP_PREFIX, created, BACKUP_EXTENSION);
+        fs::write(dir.join(&id), "").unwrap();
+        id
+    }
+
+    fn at(day: u64, hour: u64) -> u64 {
+        (MONDAY + day) * DAY_MS + hour * HOUR_MS
+    }
+
+    fn survivors(dir: &Path) -> Vec<u64> {
+        list_backups(dir)
+            .unwrap()
+            .into_iter()
+            .map(|b| b.created)
+            .collect()
+    }
+
+    /// Two backups a day for six weeks, Monday of week 0 to Sunday of week 5
+    fn six_weeks(dir: &Path) {
+        for day in 0..42 {
+            backup_at(dir, at(day, 9));
+            backup_at(dir, at(day, 18));
+        }
+    }
+
+    #[test]
+    fn keeps_the_newest_backup_of_recent_days_and_weeks() {
+        let dir = tempfile::tempdir().unwrap();
+        six_weeks(dir.path());
+        fs::write(dir.path().join("notes.txt"), "").unwrap();
+        let settings = BackupSettings {
+            keep_daily: 3,
+            keep_weekly: 2,
+            ..Default::default()
+        };
+        prune(dir.path(), &settings).unwrap();
+
+        // Sunday to Friday of the last week, then the Sunday before it
+        assert_eq!(
+            survivors(dir.path()),
+            [at(41, 18), at(40, 18), at(39, 18), at(34, 18)]
+        );
+        assert!(dir.path().join("notes.txt").exists());
+    }
+
+    #[test]
+    fn weeks_reach_further_back_than_days() {
+        let dir = tempfile::tempdir().unwrap();
+        six_weeks(dir.path());
+        let settings = BackupSettings {
+            keep_daily: 1,
+            keep_weekly: 6,
+            ..Default::default()
+        };
+        prune(dir.path(), &settings).unwrap();
+        assert_eq!(
+            survivors(dir.path()),
+            [
+                at(41, 18),
+                at(34, 18),
+                at(27, 18),
+                at(20, 18),
+                at(13, 18),
+                at(6, 18)
+            ]
+        );
+    }
+
+    #[test]
+    fn the_newest_backup_always_survives() {
+        let dir = tempfile::tempdir().unwrap();
+        six_weeks(dir.path());
+        let settings = BackupSettings {
+            keep_daily: 0,
+            keep_weekly: 0,
+            ..Default::default()
+        };
+        prune(dir.path(), &settings).unwrap();
+        assert_eq!(survivors(dir.path()), [at(41, 18)]);
+    }
+
+    #[test]
+    fn the_schedule_resumes_from_the_newest_backup() {
+        let interval = Duration::from_secs(60 * 60);
+        let now = at(10, 12);
+        assert_eq!(first_wait(None, interval, now), Duration::ZERO);
+        assert_eq!(
+            first_wait(Some(now - 15 * 60 * 1000), interval, now),
+            Duration::from_secs(45 * 60)
+        );
+        assert_eq!(
+            first_wait(Some(now - 2 * HOUR_MS), interval, now),
+            Duration::ZERO
+        );
+        // A backup from the future, say after a clock change, waits a full interval
+        assert_eq!(first_wait(Some(now + HOUR_MS), interval, now), interval);
+    }
+
+    #[test]
+    fn the_scheduler_backs_up_enabled_realms_until_stopped() {
+        let dir = tempfile::tempdir().unwrap();
+        let realm_root = dir.path().join("realm");
+        fs::create_dir_all(realm_root.join("main")).unwrap();
+        let mut settings = BackupSettings {
+            directory: Some(dir.path().join("backups").to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let save = |settings: &BackupSettings| {
+            let content = serde_json::to_vec(settings).unwrap();
+            write_atomic(&settings_path(&realm_root), &content).unwrap();
+        };
+        let backups = backup_dir(&realm_root, &settings).unwrap();
+
+        save(&settings);
+        let mut scheduler = BackupScheduler::default();
+        scheduler.start(realm_root.clone());
+        assert!(scheduler.stop.is_none());
+
+        settings.enabled = true;
+        save(&settings);
+        scheduler.start(realm_root.clone());
+        assert!(scheduler.stop.is_some());
+        // Without an earlier backup the first one is taken right away
+        let mut waited = 0;
+        while list_backups(&backups).unwrap().is_empty() && waited < 100 {
+            thread::sleep(Duration::from_millis(50));
+            waited += 1;
+        }
+        scheduler.stop();
+        assert!(scheduler.stop.is_none());
+        assert_eq!(list_backups(&backups).unwrap().len(), 1);
+    }
+}
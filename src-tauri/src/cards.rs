@@ -1,21 +1,103 @@
-use crate::utils::{get_full_path, validate_path};
-use serde::Serialize;
-use std::{fs, path::PathBuf, sync::Mutex};
-use tauri::{command, State};
+use crate::error::HollowError;
+use crate::history::{diff_text, history_dir, CardHistory, CardVersion, DiffLine};
+use crate::links::{open_graph, LinkGraph, NoteRewrite};
+use crate::permissions::{ensure_card_access, Access};
+use crate::trash::move_to_trash;
+use crate::utils::{
+    backup_path, ensure_writable, get_full_path, get_realm_root, is_internal_file, is_read_only,
+    read_recovering, read_to_string_recovering, recovery, temp_path, validate_path, write_atomic,
+};
+use crate::watcher::CardWatchers;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use filetime::{set_file_times, FileTime};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tauri::{command, ipc::Response, AppHandle, State};
 use tauri_plugin_log::log;
+use walkdir::WalkDir;
 
 #[derive(Serialize)]
 pub struct CardDirEntry {
     pub name: String,
     pub is_dir: bool,
+    pub is_symlink: bool,
+    pub size: u64,
+    /// Milliseconds since the unix epoch, `None` where the platform doesn't track it
+    pub created: Option<u64>,
+    pub modified: Option<u64>,
+    pub mime: Option<String>,
 }
 
-fn resolve_card_path(
+fn to_millis(time: std::io::Result<SystemTime>) -> Option<u64> {
+    time.ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_millis() as u64)
+}
+
+fn card_dir_entry(name: String, path: &Path) -> Result<CardDirEntry, HollowError> {
+    let link_meta = fs::symlink_metadata(path).map_err(|e| HollowError::io(path, e))?;
+    // Report what a symlink points at, falling back to the link itself when it's dangling
+    let meta = fs::metadata(path).unwrap_or_else(|_| link_meta.clone());
+    let mime = if meta.is_dir() {
+        None
+    } else {
+        mime_guess::from_path(path).first_raw().map(String::from)
+    };
+
+    Ok(CardDirEntry {
+        name,
+        is_dir: meta.is_dir(),
+        is_symlink: link_meta.file_type().is_symlink(),
+        size: meta.len(),
+        created: to_millis(meta.created()),
+        modified: to_millis(meta.modified()),
+        mime,
+    })
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CardWalkOptions {
+    /// Depth 1 is the direct children of the walked path, `None` walks everything
+    pub max_depth: Option<usize>,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub include_hidden: bool,
+}
+
+pub fn build_glob_set(patterns: &[String]) -> Result<Option<GlobSet>, HollowError> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| {
+                HollowError::InvalidInput(format!("Invalid glob pattern {}: {}", pattern, e))
+            })?;
+        builder.add(glob);
+    }
+    builder
+        .build()
+        .map(Some)
+        .map_err(|e| HollowError::InvalidInput(e.to_string()))
+}
+
+pub fn resolve_card_path(
     tool_name: &str,
     card_name: &str,
     path: &str,
     state: &State<'_, Mutex<crate::app::AppData>>,
-) -> Result<PathBuf, String> {
+) -> Result<PathBuf, HollowError> {
     validate_path(tool_name)?;
     validate_path(card_name)?;
 
@@ -26,10 +108,380 @@ fn resolve_card_path(
         validate_path(path)?;
     }
 
-    let realm_path = get_full_path("main", state)?;
-    let full_path = realm_path.join(tool_name).join(card_name).join(path);
+    let relative = Path::new("main").join(tool_name).join(card_name).join(path);
+    get_full_path(relative, state)
+}
+
+/// A path inside some card, used where a command spans two cards.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CardLocation {
+    pub tool_name: String,
+    pub card_name: String,
+    #[serde(default)]
+    pub path: String,
+}
+
+/// What copy and move do when the target already exists.
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum CollisionStrategy {
+    #[default]
+    Fail,
+    Overwrite,
+    /// Picks the first free `name (n)`
+    Rename,
+}
+
+fn free_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() && !path.is_dir() => {
+            (stem.to_string(), format!(".{}", extension))
+        }
+        _ => (name.to_string(), String::new()),
+    };
+    (1..)
+        .map(|n| path.with_file_name(format!("{} ({}){}", stem, n, extension)))
+        .find(|candidate| !candidate.exists())
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+fn resolve_collision(target: PathBuf, strategy: CollisionStrategy) -> Result<PathBuf, HollowError> {
+    if !target.exists() {
+        return Ok(target);
+    }
+    match strategy {
+        CollisionStrategy::Fail => Err(HollowError::AlreadyExists(target)),
+        CollisionStrategy::Overwrite => Ok(target),
+        CollisionStrategy::Rename => Ok(free_path(&target)),
+    }
+}
+
+fn remove_path(path: &Path) -> std::io::Result<()> {
+    if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// Copies files and folders with their timestamps. Symlinks and internal files
+/// inside folders are skipped.
+fn copy_tree(source: &Path, target: &Path) -> std::io::Result<()> {
+    let metadata = fs::metadata(source)?;
+    if metadata.is_dir() {
+        fs::create_dir(target)?;
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            if entry.file_type()?.is_symlink() || is_internal_file(&name.to_string_lossy()) {
+                continue;
+            }
+            copy_tree(&entry.path(), &target.join(&name))?;
+        }
+    } else {
+        fs::copy(source, target)?;
+    }
+    // Folders get their times last, adding children would bump them again
+    set_file_times(
+        target,
+        FileTime::from_last_access_time(&metadata),
+        FileTime::from_last_modification_time(&metadata),
+    )
+}
+
+/// Renames `source` onto `target`. An existing target is set aside first and
+/// put back if the rename fails, so either the old or the new one is in place.
+fn replace_path(source: &Path, target: &Path) -> Result<(), HollowError> {
+    let aside = if target.exists() {
+        let aside = temp_path(target);
+        fs::rename(target, &aside).map_err(|e| HollowError::io(target, e))?;
+        Some(aside)
+    } else {
+        None
+    };
+
+    if let Err(e) = fs::rename(source, target) {
+        if let Some(aside) = &aside {
+            if let Err(e) = fs::rename(aside, target) {
+                log::error!("Could not put back {}: {}", target.display(), e);
+            }
+        }
+        return Err(HollowError::io(source, e));
+    }
+
+    if let Some(aside) = aside {
+        if let Err(e) = remove_path(&aside) {
+            log::warn!("Could not remove replaced {}: {}", aside.display(), e);
+        }
+    }
+    Ok(())
+}
+
+/// Copies into a hidden staging path next to the target, nothing is visible
+/// at the target until the copy is complete.
+fn copy_path(source: &Path, target: &Path) -> Result<(), HollowError> {
+    let staged = temp_path(target);
+    if let Err(e) = copy_tree(source, &staged) {
+        if let Err(e) = remove_path(&staged) {
+            log::warn!("Could not clean up {}: {}", staged.display(), e);
+        }
+        return Err(HollowError::io(source, e));
+    }
+    replace_path(&staged, target).inspect_err(|_| {
+        let _ = remove_path(&staged);
+    })
+}
+
+/// Moves across devices in steps that can each be undone: the copy is staged
+/// first, then the source is set aside and only deleted once the copy is in
+/// place. A failure before that leaves the source where it was.
+fn move_by_copy(source: &Path, target: &Path) -> Result<(), HollowError> {
+    let staged = temp_path(target);
+    let discard_staged = || {
+        if let Err(e) = remove_path(&staged) {
+            log::warn!("Could not clean up {}: {}", staged.display(), e);
+        }
+    };
+    if let Err(e) = copy_tree(source, &staged) {
+        discard_staged();
+        return Err(HollowError::io(source, e));
+    }
+
+    let aside = temp_path(source);
+    if let Err(e) = fs::rename(source, &aside) {
+        discard_staged();
+        return Err(HollowError::io(source, e));
+    }
+    if let Err(e) = replace_path(&staged, target) {
+        discard_staged();
+        if let Err(e) = fs::rename(&aside, source) {
+            log::error!("Could not put back {}: {}", source.display(), e);
+        }
+        return Err(e);
+    }
+
+    // The source is already out of sight, a leftover only takes up space
+    if let Err(e) = remove_path(&aside) {
+        log::warn!("Could not remove moved {}: {}", aside.display(), e);
+    }
+    Ok(())
+}
+
+fn crosses_devices(e: &HollowError) -> bool {
+    matches!(e, HollowError::Io { source, .. } if source.kind() == std::io::ErrorKind::CrossesDevices)
+}
+
+/// Moves `source` onto `target` along with its backup, by copy when the
+/// rename would cross devices.
+fn move_path(source: &Path, target: &Path) -> Result<(), HollowError> {
+    // Within the realm a rename only fails this way when a mount point is crossed
+    match replace_path(source, target) {
+        Err(e) if crosses_devices(&e) => {
+            log::info!("Moving {} by copy: {}", source.display(), e);
+            move_by_copy(source, target)?;
+        }
+        result => result?,
+    }
+
+    let backup = backup_path(source);
+    if backup.exists() {
+        if let Err(e) = fs::rename(&backup, backup_path(target)) {
+            log::warn!("Could not move backup {}: {}", backup.display(), e);
+        }
+    }
+    Ok(())
+}
+
+/// Resolves both ends of a copy or move, returning the target that avoids collisions.
+fn resolve_transfer(
+    from: &CardLocation,
+    to: &CardLocation,
+    on_collision: Option<CollisionStrategy>,
+    state: &State<'_, Mutex<crate::app::AppData>>,
+) -> Result<(PathBuf, PathBuf), HollowError> {
+    let source = resolve_card_path(&from.tool_name, &from.card_name, &from.path, state)?;
+    let target = resolve_card_path(&to.tool_name, &to.card_name, &to.path, state)?;
+    let target = prepare_transfer(&source, target, on_collision.unwrap_or_default())?;
+    Ok((source, target))
+}
+
+/// Checks that `source` exists and can go to `target`, creating the target's
+/// parent folders.
+fn prepare_transfer(
+    source: &Path,
+    target: PathBuf,
+    on_collision: CollisionStrategy,
+) -> Result<PathBuf, HollowError> {
+    fs::metadata(source).map_err(|e| HollowError::io(source, e))?;
+
+    if target.starts_with(source) || source.starts_with(&target) {
+        return Err(HollowError::InvalidInput(
+            "Source and target can't contain each other".to_string(),
+        ));
+    }
+
+    let target = resolve_collision(target, on_collision)?;
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent).map_err(|e| HollowError::io(parent, e))?;
+    }
+    Ok(target)
+}
+
+/// Describes where a transfer ended up, the card itself may have been renamed.
+fn card_location(
+    tool_name: &str,
+    target: &Path,
+    state: &State<'_, Mutex<crate::app::AppData>>,
+) -> Result<CardLocation, HollowError> {
+    let tool_root = get_full_path(Path::new("main").join(tool_name), state)?;
+    location_in_tool(tool_name, &tool_root, target)
+}
+
+fn location_in_tool(
+    tool_name: &str,
+    tool_root: &Path,
+    target: &Path,
+) -> Result<CardLocation, HollowError> {
+    let relative = target
+        .strip_prefix(tool_root)
+        .map_err(|_| HollowError::Internal("Target left its tool".to_string()))?;
+    let mut components = relative.iter();
+    let card_name = components
+        .next()
+        .map(|c| c.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let path = components
+        .map(|c| c.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    Ok(CardLocation {
+        tool_name: tool_name.to_string(),
+        card_name,
+        path,
+    })
+}
+
+fn open_history(
+    tool_name: &str,
+    card_name: &str,
+    state: &State<'_, Mutex<crate::app::AppData>>,
+) -> Result<CardHistory, HollowError> {
+    validate_path(tool_name)?;
+    validate_path(card_name)?;
+    CardHistory::open(
+        get_full_path(history_dir(tool_name, card_name), state)?,
+        recovery(state),
+    )
+}
 
-    Ok(full_path)
+/// Writes `contents` to `target_path` and records it in the card's history.
+/// What was on disk is recorded first when history doesn't have it yet, so
+/// the first save and external edits can be restored too. History is best
+/// effort, a failed snapshot never fails the write itself.
+fn write_with_history(
+    tool_name: &str,
+    card_name: &str,
+    path: &str,
+    target_path: &Path,
+    contents: &[u8],
+    state: &State<'_, Mutex<crate::app::AppData>>,
+) -> Result<(), HollowError> {
+    let warn = |e: HollowError| {
+        log::warn!(
+            "Could not snapshot {}/{}/{}: {}",
+            tool_name,
+            card_name,
+            path,
+            e
+        )
+    };
+    let mut history = open_history(tool_name, card_name, state).map_err(warn).ok();
+    if let Some(history) = &mut history {
+        if let Ok(previous) = fs::read(target_path) {
+            if let Err(e) = history.snapshot(path, &previous) {
+                warn(e);
+            }
+        }
+    }
+    write_atomic(target_path, contents)?;
+    if let Some(history) = &mut history {
+        if let Err(e) = history.snapshot(path, contents) {
+            warn(e);
+        }
+    }
+    Ok(())
+}
+
+/// Writes the link rewrites of a rename, putting back the notes already
+/// rewritten when one fails.
+fn rewrite_notes(
+    tool_name: &str,
+    card_name: &str,
+    rewrites: &[NoteRewrite],
+    state: &State<'_, Mutex<crate::app::AppData>>,
+) -> Result<(), HollowError> {
+    for (index, rewrite) in rewrites.iter().enumerate() {
+        let result =
+            resolve_card_path(tool_name, card_name, &rewrite.path, state).and_then(|note_path| {
+                write_with_history(
+                    tool_name,
+                    card_name,
+                    &rewrite.path,
+                    &note_path,
+                    rewrite.contents.as_bytes(),
+                    state,
+                )
+            });
+        let Err(e) = result else {
+            continue;
+        };
+        for done in &rewrites[..index] {
+            let restored = resolve_card_path(tool_name, card_name, &done.path, state)
+                .and_then(|note_path| write_atomic(&note_path, done.original.as_bytes()));
+            if let Err(e) = restored {
+                log::error!("Could not restore {}: {}", done.path, e);
+            }
+        }
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Keeps the link graph current for notes, best effort like `snapshot`.
+fn update_links(
+    tool_name: &str,
+    card_name: &str,
+    path: &str,
+    state: &State<'_, Mutex<crate::app::AppData>>,
+) {
+    if !path.ends_with(".md") {
+        return;
+    }
+    let result = open_graph(tool_name, card_name, state).and_then(|mut graph| graph.update(path));
+    if let Err(e) = result {
+        log::warn!("Could not update links of {}: {}", path, e);
+    }
+}
+
+/// Re-reads the notes a copy or move brought into or took out of a card.
+fn refresh_links(tool_name: &str, card_name: &str, state: &State<'_, Mutex<crate::app::AppData>>) {
+    let result = open_graph(tool_name, card_name, state).and_then(|mut graph| graph.refresh());
+    if let Err(e) = result {
+        log::warn!(
+            "Could not refresh links of {}/{}: {}",
+            tool_name,
+            card_name,
+            e
+        );
+    }
+}
+
+/// Carries the indexed links of moved notes over instead of reading them again.
+fn follow_move(graph: &mut LinkGraph, old: &str, new: &str) -> Result<(), HollowError> {
+    graph.rename(old, new)?;
+    graph.refresh()
 }
 
 #[command]
@@ -37,19 +489,21 @@ pub fn card_read_dir(
     tool_name: String,
     card_name: String,
     path: String,
+    caller: String,
     state: State<'_, Mutex<crate::app::AppData>>,
-) -> Result<Vec<CardDirEntry>, String> {
+) -> Result<Vec<CardDirEntry>, HollowError> {
+    ensure_card_access(&caller, &tool_name, Access::Read, &state)?;
     let target_path = resolve_card_path(&tool_name, &card_name, &path, &state)?;
 
     let mut entries = Vec::new();
     if target_path.exists() {
-        for entry in fs::read_dir(&target_path).map_err(|e| e.to_string())? {
-            let entry = entry.map_err(|e| e.to_string())?;
-            let file_type = entry.file_type().map_err(|e| e.to_string())?;
-            entries.push(CardDirEntry {
-                name: entry.file_name().to_string_lossy().to_string(),
-                is_dir: file_type.is_dir(),
-            });
+        for entry in fs::read_dir(&target_path).map_err(|e| HollowError::io(&target_path, e))? {
+            let entry = entry.map_err(|e| HollowError::io(&target_path, e))?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if is_internal_file(&name) {
+                continue;
+            }
+            entries.push(card_dir_entry(name, &entry.path())?);
         }
     } else {
         log::warn!("Path does not exist: {}", target_path.display());
@@ -57,15 +511,130 @@ pub fn card_read_dir(
     Ok(entries)
 }
 
+/// Flat listing of everything under `path`, entry names are relative paths using `/`.
+#[command]
+pub fn card_walk(
+    tool_name: String,
+    card_name: String,
+    path: String,
+    options: Option<CardWalkOptions>,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<Vec<CardDirEntry>, HollowError> {
+    ensure_card_access(&caller, &tool_name, Access::Read, &state)?;
+    let root = resolve_card_path(&tool_name, &card_name, &path, &state)?;
+    let options = options.unwrap_or_default();
+    let include = build_glob_set(&options.include)?;
+    let exclude = build_glob_set(&options.exclude)?;
+
+    let mut entries = Vec::new();
+    if !root.exists() {
+        log::warn!("Path does not exist: {}", root.display());
+        return Ok(entries);
+    }
+
+    let relative = |path: &Path| {
+        path.strip_prefix(&root)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .replace('\\', "/")
+    };
+
+    let mut walker = WalkDir::new(&root).min_depth(1).sort_by_file_name();
+    if let Some(depth) = options.max_depth {
+        walker = walker.max_depth(depth);
+    }
+    // Filtering here prunes whole directories instead of just their entry
+    let walker = walker.into_iter().filter_entry(|entry| {
+        let name = entry.file_name().to_string_lossy();
+        if is_internal_file(&name) || (!options.include_hidden && name.starts_with('.')) {
+            return false;
+        }
+        !exclude
+            .as_ref()
+            .is_some_and(|set| set.is_match(relative(entry.path())))
+    });
+
+    for entry in walker {
+        let entry = entry.map_err(|e| {
+            let path = e.path().unwrap_or(&root).to_path_buf();
+            HollowError::io(path, e.into())
+        })?;
+        let rel_path = relative(entry.path());
+        if include.as_ref().is_some_and(|set| !set.is_match(&rel_path)) {
+            continue;
+        }
+        entries.push(card_dir_entry(rel_path, entry.path())?);
+    }
+    Ok(entries)
+}
+
+#[command]
+pub fn card_stat(
+    tool_name: String,
+    card_name: String,
+    path: String,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<CardDirEntry, HollowError> {
+    ensure_card_access(&caller, &tool_name, Access::Read, &state)?;
+    let target_path = resolve_card_path(&tool_name, &card_name, &path, &state)?;
+    let name = target_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    card_dir_entry(name, &target_path)
+}
+
 #[command]
 pub fn card_read_file(
     tool_name: String,
     card_name: String,
     path: String,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<String, HollowError> {
+    ensure_card_access(&caller, &tool_name, Access::Read, &state)?;
+    let target_path = resolve_card_path(&tool_name, &card_name, &path, &state)?;
+    read_to_string_recovering(&target_path, recovery(&state))
+}
+
+#[command]
+pub fn card_read_file_bytes(
+    tool_name: String,
+    card_name: String,
+    path: String,
+    caller: String,
     state: State<'_, Mutex<crate::app::AppData>>,
-) -> Result<String, String> {
+) -> Result<Response, HollowError> {
+    ensure_card_access(&caller, &tool_name, Access::Read, &state)?;
     let target_path = resolve_card_path(&tool_name, &card_name, &path, &state)?;
-    fs::read_to_string(target_path).map_err(|e| e.to_string())
+    let bytes = read_recovering(&target_path, recovery(&state))?;
+    Ok(Response::new(bytes))
+}
+
+#[command]
+pub fn card_read_file_range(
+    tool_name: String,
+    card_name: String,
+    path: String,
+    offset: u64,
+    length: u64,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<Response, HollowError> {
+    ensure_card_access(&caller, &tool_name, Access::Read, &state)?;
+    let target_path = resolve_card_path(&tool_name, &card_name, &path, &state)?;
+    let mut file = fs::File::open(&target_path).map_err(|e| HollowError::io(&target_path, e))?;
+    file.seek(SeekFrom::Start(offset))
+        .map_err(|e| HollowError::io(&target_path, e))?;
+
+    // Reading past the end is not an error, the range is just shorter
+    let mut bytes = Vec::new();
+    file.take(length)
+        .read_to_end(&mut bytes)
+        .map_err(|e| HollowError::io(&target_path, e))?;
+    Ok(Response::new(bytes))
 }
 
 #[command]
@@ -74,15 +643,43 @@ pub fn card_write_file(
     card_name: String,
     path: String,
     contents: String,
+    caller: String,
     state: State<'_, Mutex<crate::app::AppData>>,
-) -> Result<(), String> {
+) -> Result<(), HollowError> {
+    ensure_writable(&state)?;
+    ensure_card_access(&caller, &tool_name, Access::Write, &state)?;
     let target_path = resolve_card_path(&tool_name, &card_name, &path, &state)?;
+    write_with_history(
+        &tool_name,
+        &card_name,
+        &path,
+        &target_path,
+        contents.as_bytes(),
+        &state,
+    )?;
+    update_links(&tool_name, &card_name, &path, &state);
+    Ok(())
+}
 
-    if let Some(parent) = target_path.parent() {
-        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
-    }
-
-    fs::write(target_path, contents).map_err(|e| e.to_string())
+/// `contents` is base64 encoded, so any binary payload survives the JSON IPC.
+#[command]
+pub fn card_write_file_bytes(
+    tool_name: String,
+    card_name: String,
+    path: String,
+    contents: String,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<(), HollowError> {
+    ensure_writable(&state)?;
+    ensure_card_access(&caller, &tool_name, Access::Write, &state)?;
+    let target_path = resolve_card_path(&tool_name, &card_name, &path, &state)?;
+    let bytes = STANDARD
+        .decode(contents)
+        .map_err(|e| HollowError::InvalidInput(format!("Invalid base64 contents: {}", e)))?;
+    write_with_history(&tool_name, &card_name, &path, &target_path, &bytes, &state)?;
+    update_links(&tool_name, &card_name, &path, &state);
+    Ok(())
 }
 
 #[command]
@@ -90,15 +687,18 @@ pub fn card_remove(
     tool_name: String,
     card_name: String,
     path: String,
+    caller: String,
     state: State<'_, Mutex<crate::app::AppData>>,
-) -> Result<(), String> {
+) -> Result<(), HollowError> {
+    ensure_writable(&state)?;
+    ensure_card_access(&caller, &tool_name, Access::Write, &state)?;
     let target_path = resolve_card_path(&tool_name, &card_name, &path, &state)?;
 
     if target_path.exists() {
-        if target_path.is_dir() {
-            fs::remove_dir_all(target_path).map_err(|e| e.to_string())?;
-        } else {
-            fs::remove_file(target_path).map_err(|e| e.to_string())?;
+        move_to_trash(get_realm_root(&state)?, &target_path)?;
+        let backup = backup_path(&target_path);
+        if backup.exists() {
+            fs::remove_file(&backup).map_err(|e| HollowError::io(&backup, e))?;
         }
     }
     Ok(())
@@ -109,8 +709,10 @@ pub fn card_exists(
     tool_name: String,
     card_name: String,
     path: String,
+    caller: String,
     state: State<'_, Mutex<crate::app::AppData>>,
-) -> Result<bool, String> {
+) -> Result<bool, HollowError> {
+    ensure_card_access(&caller, &tool_name, Access::Read, &state)?;
     let target_path = resolve_card_path(&tool_name, &card_name, &path, &state)?;
     Ok(target_path.exists())
 }
@@ -120,26 +722,386 @@ pub fn card_mkdir(
     tool_name: String,
     card_name: String,
     path: String,
+    caller: String,
     state: State<'_, Mutex<crate::app::AppData>>,
-) -> Result<(), String> {
+) -> Result<(), HollowError> {
+    ensure_writable(&state)?;
+    ensure_card_access(&caller, &tool_name, Access::Write, &state)?;
     let target_path = resolve_card_path(&tool_name, &card_name, &path, &state)?;
-    fs::create_dir_all(target_path).map_err(|e| e.to_string())
+    fs::create_dir_all(&target_path).map_err(|e| HollowError::io(&target_path, e))
 }
 
+/// With `rewrite_links`, wiki and markdown links pointing at the renamed notes
+/// are updated to match.
 #[command]
 pub fn card_rename(
     tool_name: String,
     card_name: String,
     path: String,
     new_path: String,
+    rewrite_links: Option<bool>,
+    caller: String,
     state: State<'_, Mutex<crate::app::AppData>>,
-) -> Result<(), String> {
+) -> Result<(), HollowError> {
+    ensure_writable(&state)?;
+    ensure_card_access(&caller, &tool_name, Access::Write, &state)?;
     let old_target = resolve_card_path(&tool_name, &card_name, &path, &state)?;
     let new_target = resolve_card_path(&tool_name, &card_name, &new_path, &state)?;
 
+    // Planned against the old layout, links resolve to the notes being moved
+    let rewrites = match rewrite_links {
+        Some(true) => {
+            let mut graph = open_graph(&tool_name, &card_name, &state)?;
+            graph.refresh()?;
+            graph.rewrites_for_rename(&path, &new_path)?
+        }
+        _ => Vec::new(),
+    };
+
     if let Some(parent) = new_target.parent() {
-        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        fs::create_dir_all(parent).map_err(|e| HollowError::io(parent, e))?;
+    }
+
+    fs::rename(&old_target, &new_target).map_err(|e| HollowError::io(&old_target, e))?;
+
+    let old_backup = backup_path(&old_target);
+    if old_backup.exists() {
+        if let Err(e) = fs::rename(&old_backup, backup_path(&new_target)) {
+            log::warn!("Could not move backup {}: {}", old_backup.display(), e);
+        }
     }
 
-    fs::rename(old_target, new_target).map_err(|e| e.to_string())
+    let move_history = |from: &str, to: &str| {
+        let result = open_history(&tool_name, &card_name, &state)
+            .and_then(|mut history| history.rename(from, to));
+        if let Err(e) = result {
+            log::warn!("Could not move history of {}: {}", from, e);
+        }
+    };
+    move_history(&path, &new_path);
+
+    // Either every link is rewritten or the rename is undone
+    if let Err(e) = rewrite_notes(&tool_name, &card_name, &rewrites, &state) {
+        log::warn!("Undoing rename of {} after failed link rewrite", path);
+        move_history(&new_path, &path);
+        let new_backup = backup_path(&new_target);
+        if new_backup.exists() {
+            let _ = fs::rename(&new_backup, &old_backup);
+        }
+        fs::rename(&new_target, &old_target).map_err(|e| HollowError::io(&new_target, e))?;
+        return Err(e);
+    }
+    let result = open_graph(&tool_name, &card_name, &state)
+        .and_then(|mut graph| follow_move(&mut graph, &path, &new_path));
+    if let Err(e) = result {
+        log::warn!("Could not update links after renaming {}: {}", path, e);
+    }
+    Ok(())
+}
+
+#[command]
+pub fn card_watch(
+    tool_name: String,
+    card_name: String,
+    app: AppHandle,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+    watchers: State<'_, Mutex<CardWatchers>>,
+) -> Result<(), HollowError> {
+    ensure_card_access(&caller, &tool_name, Access::Read, &state)?;
+    let card_root = resolve_card_path(&tool_name, &card_name, "", &state)?;
+    watchers
+        .lock()
+        .unwrap()
+        .watch(app, &tool_name, &card_name, card_root, is_read_only(&state))
+}
+
+#[command]
+pub fn card_unwatch(
+    tool_name: String,
+    card_name: String,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+    watchers: State<'_, Mutex<CardWatchers>>,
+) -> Result<(), HollowError> {
+    ensure_card_access(&caller, &tool_name, Access::Read, &state)?;
+    watchers.lock().unwrap().unwatch(&tool_name, &card_name);
+    Ok(())
+}
+
+#[command]
+pub fn card_history_list(
+    tool_name: String,
+    card_name: String,
+    path: String,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<Vec<CardVersion>, HollowError> {
+    ensure_card_access(&caller, &tool_name, Access::Read, &state)?;
+    Ok(open_history(&tool_name, &card_name, &state)?.list(&path))
+}
+
+/// Diffs version `from` against version `to`, or against the current file when `to` is unset.
+#[command]
+pub fn card_history_diff(
+    tool_name: String,
+    card_name: String,
+    path: String,
+    from: String,
+    to: Option<String>,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<Vec<DiffLine>, HollowError> {
+    ensure_card_access(&caller, &tool_name, Access::Read, &state)?;
+    let history = open_history(&tool_name, &card_name, &state)?;
+    let old = history.read(&path, &from)?;
+    let new = match to {
+        Some(to) => history.read(&path, &to)?,
+        None => read_recovering(
+            &resolve_card_path(&tool_name, &card_name, &path, &state)?,
+            recovery(&state),
+        )?,
+    };
+    diff_text(&old, &new)
+}
+
+#[command]
+pub fn card_history_restore(
+    tool_name: String,
+    card_name: String,
+    path: String,
+    version: String,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<(), HollowError> {
+    ensure_writable(&state)?;
+    ensure_card_access(&caller, &tool_name, Access::Write, &state)?;
+    let target_path = resolve_card_path(&tool_name, &card_name, &path, &state)?;
+    let contents = open_history(&tool_name, &card_name, &state)?.read(&path, &version)?;
+    write_with_history(
+        &tool_name,
+        &card_name,
+        &path,
+        &target_path,
+        &contents,
+        &state,
+    )?;
+    update_links(&tool_name, &card_name, &path, &state);
+    Ok(())
+}
+
+/// Copies a file or folder, possibly into another card or tool. Timestamps are
+/// kept and a failed copy leaves nothing behind.
+#[command]
+pub fn card_copy(
+    from: CardLocation,
+    to: CardLocation,
+    on_collision: Option<CollisionStrategy>,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<CardLocation, HollowError> {
+    ensure_writable(&state)?;
+    ensure_card_access(&caller, &from.tool_name, Access::Read, &state)?;
+    ensure_card_access(&caller, &to.tool_name, Access::Write, &state)?;
+    let (source, target) = resolve_transfer(&from, &to, on_collision, &state)?;
+    copy_path(&source, &target)?;
+    let location = card_location(&to.tool_name, &target, &state)?;
+    refresh_links(&location.tool_name, &location.card_name, &state);
+    Ok(location)
+}
+
+/// Moves a file or folder, possibly into another card or tool. History follows
+/// moves within a card, the link graphs of both cards are kept current.
+#[command]
+pub fn card_move(
+    from: CardLocation,
+    to: CardLocation,
+    on_collision: Option<CollisionStrategy>,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<CardLocation, HollowError> {
+    ensure_writable(&state)?;
+    ensure_card_access(&caller, &from.tool_name, Access::Write, &state)?;
+    ensure_card_access(&caller, &to.tool_name, Access::Write, &state)?;
+    let (source, target) = resolve_transfer(&from, &to, on_collision, &state)?;
+    move_path(&source, &target)?;
+
+    let location = card_location(&to.tool_name, &target, &state)?;
+    if from.tool_name == location.tool_name && from.card_name == location.card_name {
+        let result = open_history(&from.tool_name, &from.card_name, &state)
+            .and_then(|mut history| history.rename(&from.path, &location.path));
+        if let Err(e) = result {
+            log::warn!("Could not move history of {}: {}", from.path, e);
+        }
+        let result = open_graph(&from.tool_name, &from.card_name, &state)
+            .and_then(|mut graph| follow_move(&mut graph, &from.path, &location.path));
+        if let Err(e) = result {
+            log::warn!("Could not update links after moving {}: {}", from.path, e);
+        }
+    } else {
+        refresh_links(&from.tool_name, &from.card_name, &state);
+        refresh_links(&location.tool_name, &location.card_name, &state);
+    }
+    Ok(location)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn collisions_fail_overwrite_or_rename() {
+        let dir = tempfile::tempdir().unwrap();
+        let note = dir.path().join("note.md");
+        fs::write(&note, "a").unwrap();
+
+        assert!(matches!(
+            resolve_collision(note.clone(), CollisionStrategy::Fail),
+            Err(HollowError::AlreadyExists(_))
+        ));
+        assert_eq!(
+            resolve_collision(note.clone(), CollisionStrategy::Overwrite).unwrap(),
+            note
+        );
+        let renamed = resolve_collision(note.clone(), CollisionStrategy::Rename).unwrap();
+        assert_eq!(renamed, dir.path().join("note (1).md"));
+        fs::write(&renamed, "b").unwrap();
+        assert_eq!(
+            resolve_collision(note.clone(), CollisionStrategy::Rename).unwrap(),
+            dir.path().join("note (2).md")
+        );
+
+        // Folders keep dots in their name
+        let folder = dir.path().join("v1.2");
+        fs::create_dir(&folder).unwrap();
+        assert_eq!(free_path(&folder), dir.path().join("v1.2 (1)"));
+        let free = dir.path().join("free.md");
+        assert_eq!(
+            resolve_collision(free.clone(), CollisionStrategy::Fail).unwrap(),
+            free
+        );
+    }
+
+    #[test]
+    fn transfers_need_a_source_outside_the_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().join("folder");
+        fs::create_dir(&folder).unwrap();
+
+        let result = prepare_transfer(&folder, folder.join("inner"), CollisionStrategy::Fail);
+        assert!(matches!(result, Err(HollowError::InvalidInput(_))));
+        let result = prepare_transfer(
+            &dir.path().join("missing"),
+            dir.path().join("target"),
+            CollisionStrategy::Fail,
+        );
+        assert!(matches!(result, Err(HollowError::NotFound(_))));
+    }
+
+    #[test]
+    fn moves_across_tools() {
+        let dir = tempfile::tempdir().unwrap();
+        let main = dir.path().join("main");
+        let source = main.join("notes").join("a").join("x.md");
+        fs::create_dir_all(source.parent().unwrap()).unwrap();
+        fs::write(&source, "moved").unwrap();
+        fs::write(backup_path(&source), "backup").unwrap();
+
+        let tool_root = main.join("tasks");
+        let target = prepare_transfer(
+            &source,
+            tool_root.join("b").join("sub").join("x.md"),
+            CollisionStrategy::Fail,
+        )
+        .unwrap();
+        move_path(&source, &target).unwrap();
+
+        assert!(!source.exists() && !backup_path(&source).exists());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "moved");
+        assert_eq!(fs::read_to_string(backup_path(&target)).unwrap(), "backup");
+        let location = location_in_tool("tasks", &tool_root, &target).unwrap();
+        assert_eq!(
+            (location.card_name.as_str(), location.path.as_str()),
+            ("b", "sub/x.md")
+        );
+        assert!(location_in_tool("notes", &main.join("notes"), &target).is_err());
+    }
+
+    #[test]
+    fn a_failed_replace_puts_the_target_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("target.md");
+        fs::write(&target, "old").unwrap();
+
+        assert!(replace_path(&dir.path().join("missing.md"), &target).is_err());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "old");
+        assert_eq!(names(dir.path()), ["target.md"]);
+
+        let source = dir.path().join("source.md");
+        fs::write(&source, "new").unwrap();
+        replace_path(&source, &target).unwrap();
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new");
+        assert_eq!(names(dir.path()), ["target.md"]);
+    }
+
+    #[test]
+    fn copies_keep_times_and_skip_internal_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source");
+        fs::create_dir(&source).unwrap();
+        let file = source.join("x.md");
+        fs::write(&file, "x").unwrap();
+        fs::write(backup_path(&file), "backup").unwrap();
+        let modified = FileTime::from_unix_time(1_000_000, 0);
+        set_file_times(&file, modified, modified).unwrap();
+
+        let target = dir.path().join("target");
+        copy_path(&source, &target).unwrap();
+        assert_eq!(names(&target), ["x.md"]);
+        let metadata = fs::metadata(target.join("x.md")).unwrap();
+        assert_eq!(FileTime::from_last_modification_time(&metadata), modified);
+        // Nothing staged is left next to the copy
+        assert_eq!(names(dir.path()), ["source", "target"]);
+
+        assert!(copy_path(&dir.path().join("missing"), &dir.path().join("other")).is_err());
+        assert_eq!(names(dir.path()), ["source", "target"]);
+    }
+
+    #[test]
+    fn moved_notes_keep_their_links() {
+        let dir = tempfile::tempdir().unwrap();
+        let card_root = dir.path().join("card");
+        fs::create_dir(&card_root).unwrap();
+        fs::write(card_root.join("a.md"), "See [[b]]").unwrap();
+        fs::write(card_root.join("b.md"), "Target").unwrap();
+        let mut graph = LinkGraph::open(dir.path().join("links"), card_root.clone()).unwrap();
+        graph.refresh().unwrap();
+
+        let target = prepare_transfer(
+            &card_root.join("b.md"),
+            card_root.join("sub").join("b.md"),
+            CollisionStrategy::Fail,
+        )
+        .unwrap();
+        move_path(&card_root.join("b.md"), &target).unwrap();
+        follow_move(&mut graph, "b.md", "sub/b.md").unwrap();
+
+        let refs = graph.link_refs();
+        assert_eq!(refs.len(), 1);
+        assert_eq!(refs[0].resolved.as_deref(), Some("sub/b.md"));
+
+        // Notes moved out of the card drop their links on the next refresh
+        fs::remove_file(card_root.join("a.md")).unwrap();
+        graph.refresh().unwrap();
+        assert!(graph.link_refs().is_empty());
+    }
 }
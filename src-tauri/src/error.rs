@@ -0,0 +1,153 @@
+use crate::utils::PathError;
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+/// Error returned by every command. It reaches the front end as
+/// `{ code, message, path }` so the UI can branch on `code` instead of parsing
+/// `message`.
+#[derive(Debug)]
+pub enum HollowError {
+    RealmNotInitialized,
+    /// Another process holds the realm lock, with its pid
+    RealmLocked(u32),
+    /// The realm was opened read-only
+    ReadOnly,
+    NotFound(PathBuf),
+    AlreadyExists(PathBuf),
+    PermissionDenied(PathBuf),
+    /// A plugin called a command it has no grant for
+    NotGranted {
+        plugin: String,
+        permission: &'static str,
+    },
+    /// The `caller` token is missing, made up or from an earlier page load
+    UnknownCaller,
+    InvalidPath(PathError),
+    InvalidInput(String),
+    Parse {
+        path: Option<PathBuf>,
+        message: String,
+    },
+    Network(String),
+    PartialFailure(String),
+    Io {
+        path: Option<PathBuf>,
+        source: io::Error,
+    },
+    Internal(String),
+}
+
+impl HollowError {
+    /// Keeps the io error kinds the UI cares about as their own variants.
+    pub fn io(path: impl Into<PathBuf>, source: io::Error) -> Self {
+        let path = path.into();
+        match source.kind() {
+            io::ErrorKind::NotFound => HollowError::NotFound(path),
+            io::ErrorKind::AlreadyExists => HollowError::AlreadyExists(path),
+            io::ErrorKind::PermissionDenied => HollowError::PermissionDenied(path),
+            _ => HollowError::Io {
+                path: Some(path),
+                source,
+            },
+        }
+    }
+
+    pub fn parse(path: impl Into<PathBuf>, message: impl fmt::Display) -> Self {
+        HollowError::Parse {
+            path: Some(path.into()),
+            message: message.to_string(),
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            HollowError::RealmNotInitialized => "realm_not_initialized",
+            HollowError::RealmLocked(_) => "realm_locked",
+            HollowError::ReadOnly => "read_only",
+            HollowError::NotFound(_) => "not_found",
+            HollowError::AlreadyExists(_) => "already_exists",
+            HollowError::PermissionDenied(_) => "permission_denied",
+            HollowError::NotGranted { .. } => "not_granted",
+            HollowError::UnknownCaller => "unknown_caller",
+            HollowError::InvalidPath(_) => "invalid_path",
+            HollowError::InvalidInput(_) => "invalid_input",
+            HollowError::Parse { .. } => "parse",
+            HollowError::Network(_) => "network",
+            HollowError::PartialFailure(_) => "partial_failure",
+            HollowError::Io { .. } => "io",
+            HollowError::Internal(_) => "internal",
+        }
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            HollowError::NotFound(path)
+            | HollowError::AlreadyExists(path)
+            | HollowError::PermissionDenied(path)
+            | HollowError::InvalidPath(PathError::OutsideRealm(path))
+            | HollowError::InvalidPath(PathError::Unresolvable(path)) => Some(path),
+            HollowError::Parse { path, .. } | HollowError::Io { path, .. } => path.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for HollowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HollowError::RealmNotInitialized => write!(f, "Realm location not initialized yet"),
+            HollowError::ReadOnly => write!(f, "Realm is open read-only"),
+            HollowError::RealmLocked(pid) => {
+                write!(f, "Realm is open in another Hollow process (pid {})", pid)
+            }
+            HollowError::NotFound(path) => write!(f, "Not found: {}", path.display()),
+            HollowError::AlreadyExists(path) => write!(f, "Already exists: {}", path.display()),
+            HollowError::PermissionDenied(path) => {
+                write!(f, "Permission denied: {}", path.display())
+            }
+            HollowError::NotGranted { plugin, permission } => {
+                write!(f, "Plugin {} has not been granted {}", plugin, permission)
+            }
+            HollowError::UnknownCaller => write!(f, "The caller's token isn't known"),
+            HollowError::InvalidPath(e) => write!(f, "{}", e),
+            HollowError::InvalidInput(message)
+            | HollowError::Network(message)
+            | HollowError::PartialFailure(message)
+            | HollowError::Internal(message) => write!(f, "{}", message),
+            HollowError::Parse {
+                path: Some(path),
+                message,
+            } => write!(f, "Failed to parse {}: {}", path.display(), message),
+            HollowError::Parse {
+                path: None,
+                message,
+            } => write!(f, "Failed to parse: {}", message),
+            HollowError::Io {
+                path: Some(path),
+                source,
+            } => write!(f, "{}: {}", path.display(), source),
+            HollowError::Io { path: None, source } => write!(f, "{}", source),
+        }
+    }
+}
+
+impl std::error::Error for HollowError {}
+
+impl From<PathError> for HollowError {
+    fn from(e: PathError) -> Self {
+        HollowError::InvalidPath(e)
+    }
+}
+
+impl Serialize for HollowError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("HollowError", 3)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.serialize_field("path", &self.path().map(|p| p.to_string_lossy()))?;
+        state.end()
+    }
+}
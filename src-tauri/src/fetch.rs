@@ -0,0 +1,133 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::{Method, Url};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use tauri::{command, State};
+
+use crate::error::HollowError;
+use crate::permissions::{ensure_granted, Permission};
+
+/// Largest response handed back to a plugin
+const MAX_RESPONSE_BYTES: u64 = 32 * 1024 * 1024;
+
+/// What a plugin's `fetch` asks for. Bodies are base64 encoded, like the
+/// binary card commands.
+#[derive(Deserialize)]
+pub struct FetchRequest {
+    pub method: String,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    #[serde(default)]
+    pub body: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FetchResponse {
+    /// Where the request ended up after redirects
+    pub url: String,
+    pub status: u16,
+    pub status_text: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+/// Plugins only reach http(s) URLs.
+fn parse_url(url: &str) -> Result<Url, HollowError> {
+    let parsed = Url::parse(url)
+        .map_err(|e| HollowError::InvalidInput(format!("Invalid URL {}: {}", url, e)))?;
+    match parsed.scheme() {
+        "http" | "https" => Ok(parsed),
+        scheme => Err(HollowError::InvalidInput(format!(
+            "{} requests aren't allowed",
+            scheme
+        ))),
+    }
+}
+
+/// Requests a plugin's frame makes through its `fetch`. The frame itself
+/// can't reach the network, plugins need the `network` grant.
+#[command]
+pub async fn plugin_fetch(
+    url: String,
+    request: FetchRequest,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<FetchResponse, HollowError> {
+    ensure_granted(&caller, Permission::Network, &state)?;
+    let parsed = parse_url(&url)?;
+    let method = Method::from_bytes(request.method.to_uppercase().as_bytes())
+        .map_err(|_| HollowError::InvalidInput(format!("Invalid method {}", request.method)))?;
+    let mut builder = reqwest::Client::new().request(method, parsed);
+    for (name, value) in request.headers {
+        builder = builder.header(name, value);
+    }
+    if let Some(body) = request.body {
+        let bytes = STANDARD
+            .decode(body)
+            .map_err(|e| HollowError::InvalidInput(format!("Invalid base64 body: {}", e)))?;
+        builder = builder.body(bytes);
+    }
+
+    let too_large = || {
+        HollowError::Network(format!(
+            "{} is larger than {} MB",
+            url,
+            MAX_RESPONSE_BYTES / 1024 / 1024
+        ))
+    };
+    let mut response = builder
+        .send()
+        .await
+        .map_err(|e| HollowError::Network(format!("Failed to fetch {}: {}", url, e)))?;
+    if response
+        .content_length()
+        .is_some_and(|length| length > MAX_RESPONSE_BYTES)
+    {
+        return Err(too_large());
+    }
+    let status = response.status();
+    let headers = response
+        .headers()
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect();
+    let final_url = response.url().to_string();
+    let mut body = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| HollowError::Network(format!("Failed to read bytes from {}: {}", url, e)))?
+    {
+        if (body.len() + chunk.len()) as u64 > MAX_RESPONSE_BYTES {
+            return Err(too_large());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(FetchResponse {
+        url: final_url,
+        status: status.as_u16(),
+        status_text: status.canonical_reason().unwrap_or_default().to_string(),
+        headers,
+        body: STANDARD.encode(body),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plugins_only_fetch_http() {
+        assert!(parse_url("https://example.com/data.json").is_ok());
+        assert!(parse_url("http://localhost:8080/").is_ok());
+        for url in [
+            "file:///etc/passwd",
+            "ipc://localhost/plugin_code",
+            "realm://localhost/main.json",
+            "example.com",
+        ] {
+            assert!(parse_url(url).is_err(), "{}", url);
+        }
+    }
+}
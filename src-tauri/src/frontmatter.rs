@@ -0,0 +1,294 @@
+use crate::cards::resolve_card_path;
+use crate::error::HollowError;
+use crate::permissions::{ensure_card_access, Access};
+use crate::utils::{is_internal_file, read_to_string_recovering, recovery};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{collections::HashMap, sync::Mutex};
+use tauri::{command, State};
+use walkdir::WalkDir;
+
+/// Frontmatter split off a markdown file, following the `front-matter` package:
+/// a leading `---` line, yaml, then a closing `---` or `...` line.
+pub struct Frontmatter<'a> {
+    /// Trimmed yaml without the fences
+    pub raw: Option<&'a str>,
+    pub body: &'a str,
+    /// 1-based line the body starts on
+    pub body_begin: usize,
+    /// Byte offset of the body in the file
+    pub body_offset: usize,
+}
+
+pub fn split_frontmatter(text: &str) -> Frontmatter<'_> {
+    let no_frontmatter = Frontmatter {
+        raw: None,
+        body: text,
+        body_begin: 1,
+        body_offset: 0,
+    };
+    let start = if text.starts_with('\u{feff}') { 3 } else { 0 };
+    let mut lines = text[start..].split_inclusive('\n');
+    if lines.next().map(|l| l.trim_end_matches(['\r', '\n'])) != Some("---") {
+        return no_frontmatter;
+    }
+
+    let mut offset = start + text[start..].find('\n').map_or(text.len(), |i| i + 1);
+    let raw_start = offset;
+    // Line 1 is the opening fence
+    for (index, line) in lines.enumerate() {
+        let trimmed = line.trim_end();
+        if trimmed == "---" || trimmed == "..." {
+            let body_offset = offset + line.len();
+            return Frontmatter {
+                raw: Some(text[raw_start..offset].trim()),
+                body: &text[body_offset..],
+                body_begin: index + 3,
+                body_offset,
+            };
+        }
+        offset += line.len();
+    }
+    no_frontmatter
+}
+
+fn parse_attributes(raw: &str) -> Result<Map<String, Value>, String> {
+    if raw.trim().is_empty() {
+        return Ok(Map::new());
+    }
+    match serde_yaml::from_str::<Value>(raw).map_err(|e| e.to_string())? {
+        Value::Object(attributes) => Ok(attributes),
+        Value::Null => Ok(Map::new()),
+        _ => Err("Frontmatter is not a mapping".to_string()),
+    }
+}
+
+#[derive(Serialize)]
+pub struct NoteFrontmatter {
+    /// Relative to the scanned folder, using `/`
+    pub path: String,
+    /// File name without `.md`
+    pub title: String,
+    /// Everything in the frontmatter, notebooks use `id`, `title`, `tags` and `banner`
+    pub attributes: Map<String, Value>,
+    pub frontmatter: Option<String>,
+    pub body_begin: usize,
+    pub body_offset: usize,
+    /// Only filled with `include_body`
+    pub body: Option<String>,
+    /// Set when the yaml doesn't parse, the attributes are empty then
+    pub error: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct FrontmatterQuery {
+    /// Scan subfolders too
+    pub recursive: bool,
+    pub include_body: bool,
+    /// Attributes that must be set
+    pub has: Vec<String>,
+    /// Attribute values to match. Lists and comma-separated strings match when
+    /// one of their items does.
+    pub equals: HashMap<String, String>,
+}
+
+fn value_matches(value: &Value, expected: &str) -> bool {
+    match value {
+        Value::String(s) => s == expected || s.split(',').any(|item| item.trim() == expected),
+        Value::Array(items) => items.iter().any(|item| value_matches(item, expected)),
+        Value::Null => false,
+        other => serde_json::to_string(other).is_ok_and(|s| s == expected),
+    }
+}
+
+impl FrontmatterQuery {
+    fn matches(&self, attributes: &Map<String, Value>) -> bool {
+        self.has
+            .iter()
+            .all(|key| attributes.get(key).is_some_and(|v| !v.is_null()))
+            && self.equals.iter().all(|(key, expected)| {
+                attributes
+                    .get(key)
+                    .is_some_and(|v| value_matches(v, expected))
+            })
+    }
+}
+
+/// Parses the frontmatter of every `.md` file under `path` in one call,
+/// keeping the notes that match `query`.
+#[command]
+pub fn card_scan_frontmatter(
+    tool_name: String,
+    card_name: String,
+    path: String,
+    query: Option<FrontmatterQuery>,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<Vec<NoteFrontmatter>, HollowError> {
+    ensure_card_access(&caller, &tool_name, Access::Read, &state)?;
+    let root = resolve_card_path(&tool_name, &card_name, &path, &state)?;
+    let query = query.unwrap_or_default();
+    if !root.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut notes = Vec::new();
+    let walker = WalkDir::new(&root)
+        .min_depth(1)
+        .max_depth(if query.recursive { usize::MAX } else { 1 })
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| !entry.file_name().to_string_lossy().starts_with('.'));
+    for entry in walker {
+        let entry = entry.map_err(|e| {
+            let path = e.path().unwrap_or(&root).to_path_buf();
+            HollowError::io(path, e.into())
+        })?;
+        let name = entry.file_name().to_string_lossy();
+        if !entry.file_type().is_file() || !name.ends_with(".md") || is_internal_file(&name) {
+            continue;
+        }
+
+        let text = read_to_string_recovering(entry.path(), recovery(&state))?;
+        let split = split_frontmatter(&text);
+        let (attributes, error) = match split.raw.map(parse_attributes) {
+            Some(Ok(attributes)) => (attributes, None),
+            Some(Err(e)) => (Map::new(), Some(e)),
+            None => (Map::new(), None),
+        };
+        if !query.matches(&attributes) {
+            continue;
+        }
+
+        let relative = entry
+            .path()
+            .strip_prefix(&root)
+            .unwrap_or(entry.path())
+            .to_string_lossy()
+            .replace('\\', "/");
+        notes.push(NoteFrontmatter {
+            title: name.trim_end_matches(".md").to_string(),
+            path: relative,
+            attributes,
+            frontmatter: split.raw.map(str::to_string),
+            body_begin: split.body_begin,
+            body_offset: split.body_offset,
+            body: query.include_body.then(|| split.body.to_string()),
+            error,
+        });
+    }
+    Ok(notes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The body has to start at `body_offset` on line `body_begin`, links.rs
+    /// shifts link positions by both.
+    fn assert_body_position(text: &str, split: &Frontmatter) {
+        assert_eq!(&text[split.body_offset..], split.body);
+        assert_eq!(
+            text[..split.body_offset].matches('\n').count() + 1,
+            split.body_begin
+        );
+    }
+
+    #[test]
+    fn splits_yaml_from_the_body() {
+        let text = "---\ntitle: Roses\ntags: [garden]\n---\n# Roses\n";
+        let split = split_frontmatter(text);
+        assert_eq!(split.raw, Some("title: Roses\ntags: [garden]"));
+        assert_eq!(split.body, "# Roses\n");
+        assert_eq!(split.body_begin, 5);
+        assert_body_position(text, &split);
+
+        // `...` closes too and an empty block is still frontmatter
+        let split = split_frontmatter("---\na: 1\n...\nbody");
+        assert_eq!((split.raw, split.body), (Some("a: 1"), "body"));
+        let split = split_frontmatter("---\n---\nbody");
+        assert_eq!(
+            (split.raw, split.body, split.body_begin),
+            (Some(""), "body", 3)
+        );
+    }
+
+    #[test]
+    fn needs_both_fences() {
+        for text in [
+            "# No frontmatter\n---\n",
+            "----\na: 1\n---\n",
+            " ---\na: 1\n---\n",
+            "---\na: 1\nnever closed\n",
+            "---",
+            "",
+        ] {
+            let split = split_frontmatter(text);
+            assert!(split.raw.is_none(), "{:?}", text);
+            assert_eq!(split.body, text);
+            assert_eq!((split.body_begin, split.body_offset), (1, 0));
+        }
+    }
+
+    #[test]
+    fn handles_crlf_and_a_bom() {
+        let text = "---\r\na: 1\r\n---\r\nbody\r\n";
+        let split = split_frontmatter(text);
+        assert_eq!(split.raw, Some("a: 1"));
+        assert_eq!(split.body, "body\r\n");
+        assert_body_position(text, &split);
+
+        let text = "\u{feff}---\na: 1\n---\nbody";
+        let split = split_frontmatter(text);
+        assert_eq!(split.raw, Some("a: 1"));
+        assert_eq!(split.body, "body");
+        assert_eq!(split.body_offset, "\u{feff}---\na: 1\n---\n".len());
+        assert_body_position(text, &split);
+
+        // Without frontmatter the BOM stays part of the body
+        let split = split_frontmatter("\u{feff}# Title");
+        assert!(split.raw.is_none());
+        assert_eq!(split.body_offset, 0);
+    }
+
+    #[test]
+    fn allows_an_empty_body() {
+        for text in ["---\na: 1\n---", "---\na: 1\n---\n"] {
+            let split = split_frontmatter(text);
+            assert_eq!(split.raw, Some("a: 1"));
+            assert_eq!(split.body, "");
+            assert_eq!(split.body_offset, text.len());
+        }
+        assert_eq!(split_frontmatter("---\na: 1\n---\n").body_begin, 4);
+    }
+
+    #[test]
+    fn parses_mappings_only() {
+        let attributes = parse_attributes("title: Roses\ntags: [a, b]").unwrap();
+        assert_eq!(attributes["title"], "Roses");
+        assert_eq!(attributes["tags"], serde_json::json!(["a", "b"]));
+        assert!(parse_attributes("").unwrap().is_empty());
+        assert!(parse_attributes("~").unwrap().is_empty());
+        assert!(parse_attributes("- a\n- b").is_err());
+        assert!(parse_attributes("a: [unclosed").is_err());
+    }
+
+    #[test]
+    fn queries_match_lists_and_comma_separated_values() {
+        let attributes = parse_attributes("tags: [a, b]\ncsv: x, y\ncount: 3\nnone: ~").unwrap();
+        let query = |has: &[&str], equals: &[(&str, &str)]| FrontmatterQuery {
+            has: has.iter().map(|s| s.to_string()).collect(),
+            equals: equals
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            ..Default::default()
+        };
+        assert!(query(&["tags", "count"], &[]).matches(&attributes));
+        assert!(!query(&["none"], &[]).matches(&attributes));
+        assert!(!query(&["missing"], &[]).matches(&attributes));
+        assert!(query(&[], &[("tags", "b"), ("csv", "y"), ("count", "3")]).matches(&attributes));
+        assert!(!query(&[], &[("tags", "c")]).matches(&attributes));
+    }
+}
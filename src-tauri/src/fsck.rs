@@ -0,0 +1,386 @@
+use crate::error::HollowError;
+use crate::permissions::ensure_app;
+use crate::trash;
+use crate::utils::{
+    backup_path, ensure_writable, get_realm_root, is_internal_file, restore_backup, write_atomic,
+    Recovery,
+};
+use crate::versions;
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use tauri::{command, State};
+use tauri_plugin_log::log;
+use walkdir::WalkDir;
+
+/// Folders under `.hollow` holding indexes that are rebuilt on demand
+const DERIVED_DIRS: [&str; 2] = ["search", "links"];
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    /// File in `vault/` without an entry in `.hollow/vault.json`
+    UntrackedVaultFile,
+    /// Entry in `.hollow/vault.json` whose file is gone
+    MissingVaultFile,
+    /// Plugin folder without `index.js` or `manifest.json`
+    IncompletePlugin,
+    /// JSON store that doesn't parse
+    CorruptStore,
+    /// Folder in `main/` for a tool that isn't installed
+    OrphanedToolFolder,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RepairAction {
+    /// Moves the file to the realm trash, where it can be restored
+    MoveToTrash,
+    RemoveVaultEntry,
+    /// Replaces the store with its last good `.hollow-bak` copy
+    RestoreBackup,
+    /// Deletes a derived index so it's rebuilt
+    RemoveIndex,
+}
+
+#[derive(Serialize)]
+pub struct FsckIssue {
+    pub kind: IssueKind,
+    /// Relative to the realm, using `/`
+    pub path: String,
+    pub message: String,
+    /// None when fixing it needs the user to decide
+    pub repair: Option<RepairAction>,
+    pub repaired: bool,
+}
+
+#[derive(Serialize)]
+pub struct FsckReport {
+    pub issues: Vec<FsckIssue>,
+    pub repaired: usize,
+}
+
+fn relative(realm_root: &Path, path: &Path) -> String {
+    path.strip_prefix(realm_root)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+fn is_hidden(name: &str) -> bool {
+    name.starts_with('.') || is_internal_file(name)
+}
+
+/// Visible entries of `dir`, empty when it doesn't exist.
+fn list_dir(dir: &Path) -> Result<Vec<fs::DirEntry>, HollowError> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(HollowError::io(dir, e)),
+    };
+    let mut visible = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|e| HollowError::io(dir, e))?;
+        if !is_hidden(&entry.file_name().to_string_lossy()) {
+            visible.push(entry);
+        }
+    }
+    visible.sort_by_key(|entry| entry.file_name());
+    Ok(visible)
+}
+
+/// Parsed JSON store, None when it's missing or corrupt. Corrupt ones are
+/// reported by `check_stores`.
+fn read_store(path: &Path) -> Option<Value> {
+    let content = fs::read(path).ok()?;
+    serde_json::from_slice(&content).ok()
+}
+
+fn parses(path: &Path) -> Result<(), String> {
+    let content = fs::read(path).map_err(|e| e.to_string())?;
+    serde_json::from_slice::<Value>(&content)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+fn check_stores(realm_root: &Path, issues: &mut Vec<FsckIssue>) -> Result<(), HollowError> {
+    let dot_dir = realm_root.join(".hollow");
+    let mut stores = Vec::new();
+    let walker = WalkDir::new(&dot_dir)
+        .min_depth(1)
+        .sort_by_file_name()
+        .into_iter()
+        // Migration backups are archives, the trash holds user files
+        .filter_entry(|entry| {
+            let name = entry.file_name().to_string_lossy();
+            !(entry.depth() == 1 && (name == "migrations" || name == "trash"))
+        });
+    for entry in walker {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) if e.io_error().map(|e| e.kind()) == Some(std::io::ErrorKind::NotFound) => {
+                continue
+            }
+            Err(e) => {
+                let path = e.path().unwrap_or(&dot_dir).to_path_buf();
+                return Err(HollowError::io(path, e.into()));
+            }
+        };
+        let name = entry.file_name().to_string_lossy();
+        if entry.file_type().is_file() && name.ends_with(".json") && !is_hidden(&name) {
+            stores.push(entry.into_path());
+        }
+    }
+    for tool in list_dir(&realm_root.join("main"))? {
+        let config = tool.path().join("config.json");
+        if config.is_file() {
+            stores.push(config);
+        }
+    }
+
+    for store in stores {
+        let Err(error) = parses(&store) else {
+            continue;
+        };
+        let derived = store
+            .strip_prefix(&dot_dir)
+            .ok()
+            .and_then(|p| p.components().next())
+            .is_some_and(|first| DERIVED_DIRS.iter().any(|d| first.as_os_str() == *d));
+        let repair = if derived {
+            Some(RepairAction::RemoveIndex)
+        } else if parses(&backup_path(&store)).is_ok() {
+            Some(RepairAction::RestoreBackup)
+        } else {
+            None
+        };
+        issues.push(FsckIssue {
+            kind: IssueKind::CorruptStore,
+            path: relative(realm_root, &store),
+            message: format!("Could not parse: {}", error),
+            repair,
+            repaired: false,
+        });
+    }
+    Ok(())
+}
+
+fn check_vault(realm_root: &Path, issues: &mut Vec<FsckIssue>) -> Result<(), HollowError> {
+    // Without a store nothing is registered yet, which isn't worth flagging every file for
+    let Some(store) = read_store(&realm_root.join(".hollow").join("vault.json")) else {
+        return Ok(());
+    };
+    let tracked: HashSet<String> = store
+        .get("__root__")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|item| item.get("path")?.as_str())
+        .filter_map(|path| Some(Path::new(path).file_name()?.to_string_lossy().to_string()))
+        .collect();
+
+    let vault_dir = realm_root.join("vault");
+    let mut present = HashSet::new();
+    for entry in list_dir(&vault_dir)? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !tracked.contains(&name) {
+            issues.push(FsckIssue {
+                kind: IssueKind::UntrackedVaultFile,
+                path: relative(realm_root, &entry.path()),
+                message: "Not registered in the vault".to_string(),
+                repair: Some(RepairAction::MoveToTrash),
+                repaired: false,
+            });
+        }
+        present.insert(name);
+    }
+    let mut missing: Vec<&String> = tracked.difference(&present).collect();
+    missing.sort();
+    for name in missing {
+        issues.push(FsckIssue {
+            kind: IssueKind::MissingVaultFile,
+            path: format!("vault/{}", name),
+            message: "Registered in the vault but the file is gone".to_string(),
+            repair: Some(RepairAction::RemoveVaultEntry),
+            repaired: false,
+        });
+    }
+    Ok(())
+}
+
+fn check_plugins(realm_root: &Path, issues: &mut Vec<FsckIssue>) -> Result<(), HollowError> {
+    for plugin in list_dir(&realm_root.join("plugins"))? {
+        let path = plugin.path();
+        if !path.is_dir() {
+            continue;
+        }
+        // Reporting never writes, repairs are separate
+        let active = match versions::active_dir(&path, Recovery::InMemory) {
+            Ok(Some(active)) => active,
+            Ok(None) => {
+                issues.push(FsckIssue {
+                    kind: IssueKind::IncompletePlugin,
+                    path: relative(realm_root, &path),
+                    message: "No installed version is active".to_string(),
+                    repair: None,
+                    repaired: false,
+                });
+                continue;
+            }
+            Err(e) => {
+                issues.push(FsckIssue {
+                    kind: IssueKind::CorruptStore,
+                    path: relative(realm_root, &path.join(versions::ACTIVE_FILE)),
+                    message: e.to_string(),
+                    repair: None,
+                    repaired: false,
+                });
+                continue;
+            }
+        };
+        let missing: Vec<&str> = ["index.js", "manifest.json"]
+            .into_iter()
+            .filter(|file| !active.join(file).is_file())
+            .collect();
+        if !missing.is_empty() {
+            issues.push(FsckIssue {
+                kind: IssueKind::IncompletePlugin,
+                path: relative(realm_root, &active),
+                message: format!("Missing {}", missing.join(" and ")),
+                repair: None,
+                repaired: false,
+            });
+        }
+    }
+    Ok(())
+}
+
+fn check_tools(realm_root: &Path, issues: &mut Vec<FsckIssue>) -> Result<(), HollowError> {
+    // Installed tools are the keys of the main store
+    let Some(Value::Object(tools)) = read_store(&realm_root.join(".hollow").join("main.json"))
+    else {
+        return Ok(());
+    };
+    for folder in list_dir(&realm_root.join("main"))? {
+        let name = folder.file_name().to_string_lossy().to_string();
+        if folder.path().is_dir() && !tools.contains_key(&name) {
+            issues.push(FsckIssue {
+                kind: IssueKind::OrphanedToolFolder,
+                path: relative(realm_root, &folder.path()),
+                message: format!("No installed tool is named {}", name),
+                repair: None,
+                repaired: false,
+            });
+        }
+    }
+    Ok(())
+}
+
+pub fn check(realm_root: &Path) -> Result<Vec<FsckIssue>, HollowError> {
+    let mut issues = Vec::new();
+    check_stores(realm_root, &mut issues)?;
+    check_vault(realm_root, &mut issues)?;
+    check_plugins(realm_root, &mut issues)?;
+    check_tools(realm_root, &mut issues)?;
+    Ok(issues)
+}
+
+fn remove_vault_entries(realm_root: &Path, names: &HashSet<String>) -> Result<(), HollowError> {
+    let path = realm_root.join(".hollow").join("vault.json");
+    let mut store: Value =
+        serde_json::from_slice(&fs::read(&path).map_err(|e| HollowError::io(&path, e))?)
+            .map_err(|e| HollowError::parse(&path, e))?;
+    if let Some(items) = store.get_mut("__root__").and_then(Value::as_array_mut) {
+        items.retain(|item| {
+            let name = item
+                .get("path")
+                .and_then(Value::as_str)
+                .and_then(|p| Path::new(p).file_name())
+                .map(|n| n.to_string_lossy().to_string());
+            !name.is_some_and(|n| names.contains(&n))
+        });
+    }
+    let content =
+        serde_json::to_vec_pretty(&store).map_err(|e| HollowError::Internal(e.to_string()))?;
+    write_atomic(&path, &content)
+}
+
+fn repair_issue(realm_root: &Path, issue: &FsckIssue) -> Result<(), HollowError> {
+    let path: PathBuf = realm_root.join(&issue.path);
+    match issue.repair {
+        Some(RepairAction::MoveToTrash) => {
+            trash::move_to_trash(realm_root.to_path_buf(), &path).map(|_| ())
+        }
+        Some(RepairAction::RestoreBackup) => restore_backup(&path).map(|_| ()),
+        Some(RepairAction::RemoveIndex) => {
+            fs::remove_file(&path).map_err(|e| HollowError::io(&path, e))
+        }
+        // Done in one pass by `apply_repairs`
+        Some(RepairAction::RemoveVaultEntry) | None => Ok(()),
+    }
+}
+
+/// Applies every safe repair, marking the issues it fixed.
+pub fn apply_repairs(realm_root: &Path, issues: &mut [FsckIssue]) {
+    for issue in issues.iter_mut() {
+        if issue.repair.is_none() || issue.repair == Some(RepairAction::RemoveVaultEntry) {
+            continue;
+        }
+        match repair_issue(realm_root, issue) {
+            Ok(()) => issue.repaired = true,
+            Err(e) => log::warn!("Could not repair {}: {}", issue.path, e),
+        }
+    }
+
+    let missing: HashSet<String> = issues
+        .iter()
+        .filter(|issue| issue.repair == Some(RepairAction::RemoveVaultEntry))
+        .filter_map(|issue| issue.path.strip_prefix("vault/").map(str::to_string))
+        .collect();
+    if missing.is_empty() {
+        return;
+    }
+    match remove_vault_entries(realm_root, &missing) {
+        Ok(()) => {
+            for issue in issues.iter_mut() {
+                if issue.repair == Some(RepairAction::RemoveVaultEntry) {
+                    issue.repaired = true;
+                }
+            }
+        }
+        Err(e) => log::warn!("Could not update the vault store: {}", e),
+    }
+}
+
+/// Scans the open realm for inconsistent state. With `repair` every issue that
+/// has a safe fix is repaired, the front end should reload the realm afterwards
+/// since stores it holds in memory may have changed on disk.
+#[command]
+pub fn realm_fsck(
+    repair: Option<bool>,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<FsckReport, HollowError> {
+    ensure_app(&caller, &state)?;
+    let repair = repair.unwrap_or(false);
+    if repair {
+        ensure_writable(&state)?;
+    }
+    let realm_root = get_realm_root(&state)?;
+    let mut issues = check(&realm_root)?;
+    if repair {
+        apply_repairs(&realm_root, &mut issues);
+    }
+    let repaired = issues.iter().filter(|issue| issue.repaired).count();
+    log::info!(
+        "Realm check found {} issues, repaired {}",
+        issues.len(),
+        repaired
+    );
+    Ok(FsckReport { issues, repaired })
+}
@@ -0,0 +1,321 @@
+use crate::error::HollowError;
+use crate::utils::{read_json_recovering, write_atomic, Recovery};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use similar::{ChangeTag, TextDiff};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tauri_plugin_log::log;
+use uuid::Uuid;
+
+/// Versions kept per file, the newest one is never pruned
+const MAX_VERSIONS: usize = 50;
+const MAX_AGE_MS: u64 = 90 * 24 * 60 * 60 * 1000;
+
+/// `index.json` is read, changed and written back whole, so only one open
+/// history at a time.
+static INDEX_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CardVersion {
+    pub id: String,
+    pub hash: String,
+    pub size: u64,
+    /// Milliseconds since the unix epoch
+    pub created: u64,
+}
+
+#[derive(Serialize)]
+pub struct DiffLine {
+    /// "equal", "insert" or "delete"
+    pub tag: &'static str,
+    pub old_line: Option<usize>,
+    pub new_line: Option<usize>,
+    pub value: String,
+}
+
+/// Snapshots of one card, stored under `.hollow/history/<tool>/<card>`.
+/// Contents are kept once per hash in `objects/` and `index.json` maps each
+/// card-relative path to its versions, oldest first.
+pub struct CardHistory {
+    dir: PathBuf,
+    index: HashMap<String, Vec<CardVersion>>,
+    _guard: MutexGuard<'static, ()>,
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+fn history_key(path: &str) -> String {
+    path.trim_start_matches('/').replace('\\', "/")
+}
+
+impl CardHistory {
+    pub fn open(dir: PathBuf, recovery: Recovery) -> Result<Self, HollowError> {
+        let guard = INDEX_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let index = read_json_recovering(&dir.join("index.json"), recovery)?.unwrap_or_default();
+        Ok(CardHistory {
+            dir,
+            index,
+            _guard: guard,
+        })
+    }
+
+    fn object_path(&self, hash: &str) -> PathBuf {
+        self.dir.join("objects").join(hash)
+    }
+
+    fn save(&self) -> Result<(), HollowError> {
+        let content = serde_json::to_vec_pretty(&self.index)
+            .map_err(|e| HollowError::Internal(e.to_string()))?;
+        write_atomic(&self.dir.join("index.json"), &content)
+    }
+
+    pub fn snapshot(&mut self, path: &str, contents: &[u8]) -> Result<(), HollowError> {
+        let key = history_key(path);
+        let hash = format!("{:x}", Sha256::digest(contents));
+        let unchanged = self
+            .index
+            .get(&key)
+            .and_then(|versions| versions.last())
+            .is_some_and(|v| v.hash == hash);
+        if unchanged {
+            return Ok(());
+        }
+
+        let object = self.object_path(&hash);
+        if !object.exists() {
+            write_atomic(&object, contents)?;
+        }
+
+        let created = now_millis();
+        let versions = self.index.entry(key).or_default();
+        versions.push(CardVersion {
+            id: Uuid::new_v4().to_string(),
+            hash,
+            size: contents.len() as u64,
+            created,
+        });
+
+        let keep_from = versions.len().saturating_sub(MAX_VERSIONS);
+        let newest = versions.len() - 1;
+        let mut position = 0;
+        versions.retain(|v| {
+            let keep = position == newest
+                || (position >= keep_from && created.saturating_sub(v.created) <= MAX_AGE_MS);
+            position += 1;
+            keep
+        });
+
+        self.save()?;
+        self.collect_garbage();
+        Ok(())
+    }
+
+    /// Drops objects no version points at anymore.
+    fn collect_garbage(&self) {
+        let referenced: HashSet<&str> = self
+            .index
+            .values()
+            .flatten()
+            .map(|v| v.hash.as_str())
+            .collect();
+        let Ok(entries) = fs::read_dir(self.dir.join("objects")) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            // Backups and temp files of live objects have a suffix and go with them
+            let hash = name.trim_start_matches('.').split('.').next().unwrap_or("");
+            if !referenced.contains(hash) {
+                if let Err(e) = fs::remove_file(entry.path()) {
+                    log::warn!("Could not remove history object {}: {}", name, e);
+                }
+            }
+        }
+    }
+
+    /// Newest first.
+    pub fn list(&self, path: &str) -> Vec<CardVersion> {
+        let mut versions = self
+            .index
+            .get(&history_key(path))
+            .cloned()
+            .unwrap_or_default();
+        versions.reverse();
+        versions
+    }
+
+    pub fn read(&self, path: &str, id: &str) -> Result<Vec<u8>, HollowError> {
+        let version = self
+            .index
+            .get(&history_key(path))
+            .and_then(|versions| versions.iter().find(|v| v.id == id))
+            .ok_or_else(|| HollowError::InvalidInput(format!("Unknown version {}", id)))?;
+        let object = self.object_path(&version.hash);
+        fs::read(&object).map_err(|e| HollowError::io(&object, e))
+    }
+
+    /// Moves the history of `old` (a file or a whole folder) to `new`.
+    pub fn rename(&mut self, old: &str, new: &str) -> Result<(), HollowError> {
+        let old = history_key(old);
+        let new = history_key(new);
+        if old.is_empty() {
+            return Ok(());
+        }
+        let keys: Vec<String> = self
+            .index
+            .keys()
+            .filter(|k| **k == old || k.starts_with(&format!("{}/", old)))
+            .cloned()
+            .collect();
+        if keys.is_empty() {
+            return Ok(());
+        }
+        for key in keys {
+            if let Some(versions) = self.index.remove(&key) {
+                let renamed = format!("{}{}", new, &key[old.len()..]);
+                let target = self.index.entry(renamed).or_default();
+                target.extend(versions);
+                target.sort_by_key(|v| v.created);
+            }
+        }
+        self.save()
+    }
+}
+
+pub fn diff_text(old: &[u8], new: &[u8]) -> Result<Vec<DiffLine>, HollowError> {
+    let not_text = |_| HollowError::InvalidInput("Only text versions can be diffed".to_string());
+    let old = std::str::from_utf8(old).map_err(not_text)?;
+    let new = std::str::from_utf8(new).map_err(not_text)?;
+
+    Ok(TextDiff::from_lines(old, new)
+        .iter_all_changes()
+        .map(|change| DiffLine {
+            tag: match change.tag() {
+                ChangeTag::Equal => "equal",
+                ChangeTag::Insert => "insert",
+                ChangeTag::Delete => "delete",
+            },
+            old_line: change.old_index().map(|i| i + 1),
+            new_line: change.new_index().map(|i| i + 1),
+            value: change.value().to_string(),
+        })
+        .collect())
+}
+
+pub fn history_dir(tool_name: &str, card_name: &str) -> PathBuf {
+    Path::new(".hollow")
+        .join("history")
+        .join(tool_name)
+        .join(card_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn objects(history: &CardHistory) -> usize {
+        fs::read_dir(history.dir.join("objects")).unwrap().count()
+    }
+
+    #[test]
+    fn unchanged_contents_are_stored_once() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut history = CardHistory::open(tmp.path().to_path_buf(), Recovery::Restore).unwrap();
+        history.snapshot("a.md", b"one").unwrap();
+        history.snapshot("a.md", b"one").unwrap();
+        history.snapshot("/a.md", b"two").unwrap();
+        history.snapshot("b.md", b"one").unwrap();
+        assert_eq!(history.list("a.md").len(), 2);
+        // Both files share the object for "one"
+        assert_eq!(objects(&history), 2);
+
+        let versions = history.list("a.md");
+        assert_eq!(history.read("a.md", &versions[0].id).unwrap(), b"two");
+        assert_eq!(history.read("a.md", &versions[1].id).unwrap(), b"one");
+        assert!(history.read("b.md", &versions[0].id).is_err());
+    }
+
+    #[test]
+    fn keeps_the_newest_versions_within_age() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut history = CardHistory::open(tmp.path().to_path_buf(), Recovery::Restore).unwrap();
+        for i in 0..MAX_VERSIONS + 5 {
+            history.snapshot("a.md", i.to_string().as_bytes()).unwrap();
+        }
+        let versions = history.list("a.md");
+        assert_eq!(versions.len(), MAX_VERSIONS);
+        assert_eq!(
+            history
+                .read("a.md", &versions[MAX_VERSIONS - 1].id)
+                .unwrap(),
+            b"5"
+        );
+        // Pruned versions take their objects with them
+        assert_eq!(objects(&history), MAX_VERSIONS);
+
+        for version in history.index.get_mut("a.md").unwrap().iter_mut() {
+            version.created -= MAX_AGE_MS + 1;
+        }
+        history.snapshot("a.md", b"latest").unwrap();
+        let versions = history.list("a.md");
+        assert_eq!(versions.len(), 1);
+        assert_eq!(history.read("a.md", &versions[0].id).unwrap(), b"latest");
+        assert_eq!(objects(&history), 1);
+    }
+
+    #[test]
+    fn the_newest_version_outlives_max_age() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut history = CardHistory::open(tmp.path().to_path_buf(), Recovery::Restore).unwrap();
+        history.snapshot("a.md", b"one").unwrap();
+        history.index.get_mut("a.md").unwrap()[0].created = 0;
+        history.snapshot("a.md", b"one").unwrap();
+        assert_eq!(history.list("a.md").len(), 1);
+    }
+
+    #[test]
+    fn renames_carry_versions_along() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut history = CardHistory::open(tmp.path().to_path_buf(), Recovery::Restore).unwrap();
+        history.snapshot("dir/a.md", b"one").unwrap();
+        history.snapshot("dir-b.md", b"two").unwrap();
+        history.rename("dir", "moved").unwrap();
+        assert_eq!(history.list("moved/a.md").len(), 1);
+        assert!(history.list("dir/a.md").is_empty());
+        assert_eq!(history.list("dir-b.md").len(), 1);
+    }
+
+    #[test]
+    fn recovers_a_torn_index() {
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let mut history =
+                CardHistory::open(tmp.path().to_path_buf(), Recovery::Restore).unwrap();
+            history.snapshot("a.md", b"one").unwrap();
+            history.snapshot("a.md", b"two").unwrap();
+        }
+        fs::write(tmp.path().join("index.json"), "{\"a.md\":[").unwrap();
+        let history = CardHistory::open(tmp.path().to_path_buf(), Recovery::InMemory).unwrap();
+        assert_eq!(history.list("a.md").len(), 1);
+    }
+
+    #[test]
+    fn diffs_lines() {
+        let diff = diff_text(b"a\nb\n", b"a\nc\n").unwrap();
+        let tags: Vec<&str> = diff.iter().map(|line| line.tag).collect();
+        assert_eq!(tags, vec!["equal", "delete", "insert"]);
+        assert_eq!((diff[2].old_line, diff[2].new_line), (None, Some(2)));
+        assert!(diff_text(&[0xff], b"a").is_err());
+    }
+}
@@ -0,0 +1,375 @@
+use crate::archive::zip_error;
+use crate::error::HollowError;
+use crate::permissions::ensure_app;
+use crate::plugins::{check_plugin, PluginReport, Severity};
+use crate::signing::{self, TrustedKey};
+use crate::utils::{ensure_writable, get_full_path, temp_path, validate_path};
+use crate::versions;
+use std::{
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use tauri::{command, AppHandle, State};
+use tauri_plugin_log::log;
+use zip::ZipArchive;
+
+pub const PACKAGE_EXTENSION: &str = "hollowplugin";
+/// Size a package may download or unpack to, guards against zip bombs
+const MAX_PACKAGE_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Reads the plugin name from the package's `manifest.json`, which decides
+/// the folder it's installed into.
+fn package_name(zip: &mut ZipArchive<File>, archive: &Path) -> Result<String, HollowError> {
+    let entry = zip.by_name("manifest.json").map_err(|_| {
+        HollowError::parse(archive, "Not a plugin package, manifest.json is missing")
+    })?;
+    let manifest: serde_json::Value =
+        serde_json::from_reader(entry).map_err(|e| HollowError::parse(archive, e))?;
+    let name = manifest
+        .get("name")
+        .and_then(serde_json::Value::as_str)
+        .filter(|name| !name.trim().is_empty())
+        .ok_or_else(|| HollowError::parse(archive, "The manifest has no name"))?
+        .to_lowercase();
+    validate_path(&name)?;
+    if Path::new(&name).components().count() != 1 {
+        return Err(HollowError::parse(
+            archive,
+            format!("{} is not a valid plugin name", name),
+        ));
+    }
+    Ok(name)
+}
+
+/// Unpacks into `staging`, stopping once more than `limit` bytes come out.
+fn extract(
+    zip: &mut ZipArchive<File>,
+    archive: &Path,
+    staging: &Path,
+    limit: u64,
+) -> Result<(), HollowError> {
+    let mut unpacked = 0;
+    for index in 0..zip.len() {
+        let entry = zip.by_index(index).map_err(|e| zip_error(archive, e))?;
+        // Links could point anywhere once unpacked
+        if entry.is_symlink() {
+            return Err(HollowError::parse(
+                archive,
+                format!(
+                    "{} is a symlink, packages can't contain links",
+                    entry.name()
+                ),
+            ));
+        }
+        let name = entry.enclosed_name().ok_or_else(|| {
+            HollowError::parse(archive, format!("{} escapes the package", entry.name()))
+        })?;
+        // Also catches names that only become `..` once normalized
+        validate_path(&name.to_string_lossy())?;
+        let target = staging.join(&name);
+        if entry.is_dir() {
+            fs::create_dir_all(&target).map_err(|e| HollowError::io(&target, e))?;
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(|e| HollowError::io(parent, e))?;
+        }
+        let mut output = File::create(&target).map_err(|e| HollowError::io(&target, e))?;
+        // The declared size can lie, so cap what is actually read
+        let written = io::copy(&mut entry.take(limit - unpacked + 1), &mut output)
+            .map_err(|e| HollowError::io(&target, e))?;
+        unpacked += written;
+        if unpacked > limit {
+            return Err(HollowError::parse(
+                archive,
+                format!(
+                    "The package unpacks to more than {} MB",
+                    limit / 1024 / 1024
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Moves `staging` into `target`, putting the previous install back if
+/// anything fails on the way.
+fn swap_in(staging: &Path, target: &Path) -> Result<(), HollowError> {
+    let previous = target.exists().then(|| temp_path(target));
+    if let Some(previous) = &previous {
+        fs::rename(target, previous).map_err(|e| HollowError::io(target, e))?;
+    }
+    if let Err(e) = fs::rename(staging, target) {
+        if let Some(previous) = &previous {
+            if let Err(e) = fs::rename(previous, target) {
+                log::error!("Could not restore {}: {}", target.display(), e);
+            }
+        }
+        return Err(HollowError::io(target, e));
+    }
+    if let Some(previous) = previous {
+        if let Err(e) = fs::remove_dir_all(&previous) {
+            log::warn!("Could not clean up {}: {}", previous.display(), e);
+        }
+    }
+    Ok(())
+}
+
+/// Checks the plugin unpacked into `staging`, moves it to
+/// `plugins/<name>/<version>/` and makes that version active. Versions
+/// installed before stay next to it. `staging` is gone afterwards.
+pub fn install_staged(
+    plugins_dir: &Path,
+    name: &str,
+    staging: &Path,
+    trusted: &[TrustedKey],
+) -> Result<PluginReport, HollowError> {
+    let result = (|| {
+        let mut report = check_plugin(staging, name, trusted);
+        let version = match &report.manifest {
+            Some(manifest) if report.valid => manifest.version.clone(),
+            _ => {
+                let problems: Vec<String> = report
+                    .diagnostics
+                    .iter()
+                    .filter(|d| d.severity == Severity::Error)
+                    .map(|d| d.message.clone())
+                    .collect();
+                return Err(HollowError::InvalidInput(format!(
+                    "{} can't be installed: {}",
+                    name,
+                    problems.join(", ")
+                )));
+            }
+        };
+        validate_path(&version)?;
+        let plugin_dir = plugins_dir.join(name);
+        fs::create_dir_all(&plugin_dir).map_err(|e| HollowError::io(&plugin_dir, e))?;
+        swap_in(staging, &plugin_dir.join(&version))?;
+        versions::activate(&plugin_dir, &version)?;
+        report.path = Some(format!("plugins/{}/{}", name, version));
+        Ok(report)
+    })();
+    if result.is_err() && staging.exists() {
+        if let Err(e) = fs::remove_dir_all(staging) {
+            log::warn!("Could not clean up {}: {}", staging.display(), e);
+        }
+    }
+
+    let report = result?;
+    log::info!(
+        "Installed plugin {} (signed: {}, trusted: {})",
+        name,
+        report.signed,
+        report.trusted
+    );
+    Ok(report)
+}
+
+/// Installs a `.hollowplugin` package, a zip with `manifest.json` and
+/// `index.js` at its root plus any assets. The package is unpacked and
+/// checked aside, the plugin folder only changes once it passes.
+pub fn install(
+    plugins_dir: &Path,
+    archive: &Path,
+    trusted: &[TrustedKey],
+) -> Result<PluginReport, HollowError> {
+    let file = File::open(archive).map_err(|e| HollowError::io(archive, e))?;
+    let mut zip = ZipArchive::new(file).map_err(|e| zip_error(archive, e))?;
+    let name = package_name(&mut zip, archive)?;
+
+    fs::create_dir_all(plugins_dir).map_err(|e| HollowError::io(plugins_dir, e))?;
+    let staging = temp_path(&plugins_dir.join(&name));
+    if let Err(e) = extract(&mut zip, archive, &staging, MAX_PACKAGE_BYTES) {
+        if staging.exists() {
+            if let Err(e) = fs::remove_dir_all(&staging) {
+                log::warn!("Could not clean up {}: {}", staging.display(), e);
+            }
+        }
+        return Err(e);
+    }
+    install_staged(plugins_dir, &name, &staging, trusted)
+}
+
+/// Streams `url` into `destination`, giving up once it grows past
+/// `MAX_PACKAGE_BYTES` instead of holding the whole response in memory.
+async fn download(url: &str, destination: &Path) -> Result<(), HollowError> {
+    let too_large = || {
+        HollowError::Network(format!(
+            "{} is larger than {} MB",
+            url,
+            MAX_PACKAGE_BYTES / 1024 / 1024
+        ))
+    };
+    let mut response = reqwest::get(url)
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| HollowError::Network(format!("Failed to fetch {}: {}", url, e)))?;
+    if response
+        .content_length()
+        .is_some_and(|length| length > MAX_PACKAGE_BYTES)
+    {
+        return Err(too_large());
+    }
+
+    let mut output = File::create(destination).map_err(|e| HollowError::io(destination, e))?;
+    let mut received = 0;
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| HollowError::Network(format!("Failed to read bytes from {}: {}", url, e)))?
+    {
+        received += chunk.len() as u64;
+        if received > MAX_PACKAGE_BYTES {
+            return Err(too_large());
+        }
+        output
+            .write_all(&chunk)
+            .map_err(|e| HollowError::io(destination, e))?;
+    }
+    output
+        .sync_all()
+        .map_err(|e| HollowError::io(destination, e))
+}
+
+/// Installs a plugin package from a local path or an http(s) URL.
+#[command]
+pub async fn plugin_install(
+    source: String,
+    caller: String,
+    app: AppHandle,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<PluginReport, HollowError> {
+    ensure_writable(&state)?;
+    ensure_app(&caller, &state)?;
+    let plugins_dir = get_full_path("plugins", &state)?;
+    let trusted = signing::trusted_keys(&app)?;
+
+    let downloaded = if source.starts_with("https://") || source.starts_with("http://") {
+        let destination = temp_path(&plugins_dir.join(format!("download.{}", PACKAGE_EXTENSION)));
+        if let Err(e) = download(&source, &destination).await {
+            let _ = fs::remove_file(&destination);
+            return Err(e);
+        }
+        Some(destination)
+    } else {
+        None
+    };
+    let archive = downloaded.clone().unwrap_or_else(|| PathBuf::from(&source));
+
+    let result =
+        tauri::async_runtime::spawn_blocking(move || install(&plugins_dir, &archive, &trusted))
+            .await
+            .map_err(|e| HollowError::Internal(e.to_string()));
+
+    if let Some(downloaded) = downloaded {
+        if let Err(e) = fs::remove_file(&downloaded) {
+            log::warn!("Could not remove {}: {}", downloaded.display(), e);
+        }
+    }
+    result?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    fn package(path: &Path, files: &[(&str, &[u8])]) {
+        let mut zip = ZipWriter::new(File::create(path).unwrap());
+        for (name, contents) in files {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            io::Write::write_all(&mut zip, contents).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    fn unpack(archive: &Path, staging: &Path, limit: u64) -> Result<(), HollowError> {
+        let mut zip = ZipArchive::new(File::open(archive).unwrap()).unwrap();
+        extract(&mut zip, archive, staging, limit)
+    }
+
+    fn manifest(version: &str) -> Vec<u8> {
+        serde_json::json!({
+            "name": "Demo",
+            "version": version,
+            "author": "someone",
+            "description": "demo plugin",
+        })
+        .to_string()
+        .into_bytes()
+    }
+
+    #[test]
+    fn entries_stay_inside_the_package() {
+        let tmp = tempfile::tempdir().unwrap();
+        let staging = tmp.path().join("staging");
+        for name in ["../evil.js", "/etc/evil.js", "style/../../evil.js"] {
+            let archive = tmp.path().join("slip.zip");
+            package(&archive, &[(name, b"evil()")]);
+            assert!(
+                unpack(&archive, &staging, MAX_PACKAGE_BYTES).is_err(),
+                "{}",
+                name
+            );
+        }
+        assert!(!tmp.path().join("evil.js").exists());
+
+        let archive = tmp.path().join("link.zip");
+        let mut zip = ZipWriter::new(File::create(&archive).unwrap());
+        zip.add_symlink("index.js", "/etc/passwd", SimpleFileOptions::default())
+            .unwrap();
+        zip.finish().unwrap();
+        assert!(unpack(&archive, &staging, MAX_PACKAGE_BYTES).is_err());
+        assert!(fs::symlink_metadata(staging.join("index.js")).is_err());
+    }
+
+    #[test]
+    fn stops_at_the_size_cap() {
+        let tmp = tempfile::tempdir().unwrap();
+        let archive = tmp.path().join("bomb.zip");
+        package(&archive, &[("a.bin", &[0; 600]), ("b.bin", &[0; 600])]);
+        assert!(unpack(&archive, &tmp.path().join("small"), 1000).is_err());
+        assert!(fs::metadata(tmp.path().join("small/b.bin")).unwrap().len() <= 401);
+        assert!(unpack(&archive, &tmp.path().join("large"), 1200).is_ok());
+    }
+
+    #[test]
+    fn invalid_packages_leave_the_install_alone() {
+        let tmp = tempfile::tempdir().unwrap();
+        let plugins = tmp.path().join("plugins");
+        let good = tmp.path().join("good.hollowplugin");
+        package(
+            &good,
+            &[("manifest.json", &manifest("1.0.0")), ("index.js", b"v1")],
+        );
+        let report = install(&plugins, &good, &[]).unwrap();
+        assert_eq!(report.path.as_deref(), Some("plugins/demo/1.0.0"));
+
+        // Same version with a broken manifest, it must not replace the good one
+        let bad = tmp.path().join("bad.hollowplugin");
+        package(
+            &bad,
+            &[("manifest.json", &manifest("one")), ("index.js", b"v2")],
+        );
+        assert!(install(&plugins, &bad, &[]).is_err());
+        assert_eq!(
+            fs::read(plugins.join("demo/1.0.0/index.js")).unwrap(),
+            b"v1"
+        );
+        assert_eq!(
+            versions::active_version(&plugins.join("demo"), crate::utils::Recovery::Restore)
+                .unwrap()
+                .as_deref(),
+            Some("1.0.0")
+        );
+        let leftovers: Vec<_> = fs::read_dir(&plugins)
+            .unwrap()
+            .flatten()
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with('.'))
+            .collect();
+        assert!(leftovers.is_empty());
+    }
+}
@@ -2,14 +2,33 @@ use std::sync::Mutex;
 use tauri_plugin_log::log::{self};
 
 mod app;
+mod archive;
 mod auth;
+mod backup;
 mod cards;
 mod deeplink;
+mod error;
+mod fetch;
+mod frontmatter;
+mod fsck;
+mod history;
+mod installer;
+mod links;
+mod lock;
+mod migrations;
+mod permissions;
 mod plugins;
+mod protocol;
+mod realms;
+mod search;
+mod signing;
+mod trash;
 mod utils;
 mod vault;
+mod versions;
+mod watcher;
 
-// use tauri::Manager;
+use tauri::Manager;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -26,13 +45,15 @@ pub fn run() {
         .plugin(tauri_plugin_process::init())
         .plugin(tauri_plugin_updater::Builder::new().build())
         // static
-           .plugin(tauri_plugin_single_instance::init(|_app, argv, _cwd| {
+        .plugin(tauri_plugin_single_instance::init(|_app, argv, _cwd| {
             log::debug!("a new app instance was opened with {argv:?} and the deep link event was already triggered");
         }))
         // relative
-        .manage(Mutex::new(app::AppData {
-            realm_location: None,
-        }))
+        .manage(Mutex::new(app::AppData::default()))
+        .manage(Mutex::new(watcher::CardWatchers::default()))
+        .manage(Mutex::new(search::SearchIndex::default()))
+        .manage(Mutex::new(backup::BackupScheduler::default()))
+        .manage(Mutex::new(lock::RealmLock::default()))
         // .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_store::Builder::new().build())
         .plugin(tauri_plugin_deep_link::init())
@@ -49,8 +70,33 @@ pub fn run() {
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_log::Builder::new().build())
         .plugin(prevent)
+        .register_uri_scheme_protocol(protocol::REALM_SCHEME, protocol::handle)
+        // Tokens die with the page that was handed them
+        .on_page_load(|webview, payload| {
+            if payload.event() == tauri::webview::PageLoadEvent::Started {
+                let state = webview.state::<Mutex<app::AppData>>();
+                state.lock().unwrap().sessions.reset(webview.label());
+            }
+        })
         .invoke_handler(tauri::generate_handler![
             app::start_realm,
+            archive::realm_export,
+            archive::realm_import,
+            backup::backup_get_settings,
+            backup::backup_set_settings,
+            backup::backup_now,
+            backup::backup_list,
+            backup::backup_restore,
+            fsck::realm_fsck,
+            realms::realm_registry,
+            realms::realm_add,
+            realms::realm_remove,
+            realms::realm_rename,
+            realms::realm_relocate,
+            realms::realm_set_colors,
+            realms::realm_set_select_on_startup,
+            realms::realm_enter,
+            realms::open_realm_selector,
             //app::reload,
             app::get_version,
             app::get_platform,
@@ -60,21 +106,63 @@ pub fn run() {
             utils::read_file,
             utils::first_launch,
             plugins::get_unsigned_plugins,
-            plugins::add_plugin,
             plugins::remove_plugin,
+            plugins::plugin_code,
+            fetch::plugin_fetch,
+            installer::plugin_install,
+            versions::plugin_active_dir,
+            versions::plugin_versions,
+            versions::plugin_switch_version,
+            versions::plugin_rollback,
+            versions::plugin_prune,
+            versions::plugin_check_updates,
+            signing::plugin_trusted_keys,
+            signing::plugin_trust_key,
+            signing::plugin_untrust_key,
+            permissions::session_start,
+            permissions::plugin_permissions,
+            permissions::plugin_set_permissions,
             vault::vault_add,
             vault::vault_remove,
             vault::vault_add_url,
+            trash::trash_list,
+            trash::trash_restore,
+            trash::trash_empty,
+            trash::trash_set_retention,
+            search::search_cards,
+            search::search_rebuild,
             // auth::get_user,
             // auth::update_user_character,
             cards::card_read_dir,
+            cards::card_walk,
+            cards::card_stat,
             cards::card_read_file,
+            cards::card_read_file_bytes,
+            cards::card_read_file_range,
             cards::card_write_file,
+            cards::card_write_file_bytes,
             cards::card_remove,
             cards::card_exists,
             cards::card_mkdir,
             cards::card_rename,
+            cards::card_copy,
+            cards::card_move,
+            cards::card_watch,
+            cards::card_unwatch,
+            cards::card_history_list,
+            cards::card_history_diff,
+            cards::card_history_restore,
+            frontmatter::card_scan_frontmatter,
+            links::card_links,
+            links::card_backlinks,
+            links::card_orphan_notes,
+            links::card_broken_links,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::Exit = event {
+                app.state::<Mutex<lock::RealmLock>>().lock().unwrap().release();
+            }
+        });
 }
@@ -0,0 +1,681 @@
+use crate::cards::resolve_card_path;
+use crate::error::HollowError;
+use crate::frontmatter::split_frontmatter;
+use crate::permissions::{ensure_card_access, Access};
+use crate::utils::{
+    get_full_path, is_internal_file, is_read_only, percent_decode, read_to_string_recovering,
+    validate_path, write_atomic, Recovery,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::UNIX_EPOCH,
+};
+use tauri::{command, State};
+use tauri_plugin_log::log;
+use walkdir::WalkDir;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkKind {
+    /// `[[Note Title]]`, with optional `#heading` and `|alias`
+    Wiki,
+    /// `[text](relative/path.md)`
+    Markdown,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct RawLink {
+    kind: LinkKind,
+    /// As written, percent-decoded for markdown links
+    target: String,
+    line: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct IndexedNote {
+    modified: u64,
+    size: u64,
+    links: Vec<RawLink>,
+}
+
+#[derive(Serialize)]
+pub struct LinkRef {
+    /// Card-relative path of the note containing the link
+    pub source: String,
+    pub target: String,
+    pub kind: LinkKind,
+    /// 1-based line of the link in the source
+    pub line: usize,
+    /// Card-relative path of the linked note, `None` when the link is broken
+    pub resolved: Option<String>,
+}
+
+/// A note whose links change with a rename
+pub struct NoteRewrite {
+    /// Card-relative path of the note after the rename
+    pub path: String,
+    /// Contents before the rewrite, to put back if the rename is undone
+    pub original: String,
+    pub contents: String,
+}
+
+struct LinkSpan {
+    kind: LinkKind,
+    target: String,
+    /// Bytes of the link target in the file, as written
+    range: Range<usize>,
+    line: usize,
+}
+
+fn note_key(path: &str) -> String {
+    path.trim_start_matches('/').replace('\\', "/")
+}
+
+fn parent_dir(key: &str) -> &str {
+    key.rsplit_once('/').map_or("", |(dir, _)| dir)
+}
+
+fn without_md(key: &str) -> &str {
+    key.strip_suffix(".md").unwrap_or(key)
+}
+
+fn stem(key: &str) -> &str {
+    without_md(key.rsplit_once('/').map_or(key, |(_, name)| name))
+}
+
+/// Where `key` ends up when `old` (a note or folder) is renamed to `new`.
+fn moved(key: &str, old: &str, new: &str) -> Option<String> {
+    if key == old {
+        Some(new.to_string())
+    } else {
+        key.strip_prefix(old)
+            .filter(|rest| rest.starts_with('/'))
+            .map(|rest| format!("{}{}", new, rest))
+    }
+}
+
+/// Joins a relative link onto a folder, `None` when it climbs out of the card.
+fn join_relative(dir: &str, target: &str) -> Option<String> {
+    let mut parts: Vec<&str> = dir.split('/').filter(|p| !p.is_empty()).collect();
+    for part in target.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            part => parts.push(part),
+        }
+    }
+    Some(parts.join("/"))
+}
+
+fn relative_to(dir: &str, key: &str) -> String {
+    let from: Vec<&str> = dir.split('/').filter(|p| !p.is_empty()).collect();
+    let to: Vec<&str> = key.split('/').collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let mut parts = vec![".."; from.len() - common];
+    parts.extend(&to[common..]);
+    parts.join("/")
+}
+
+/// Finds the note path inside a markdown link destination, skipping urls,
+/// anchors and anything that isn't a `.md` file.
+fn markdown_target(destination: &str) -> Option<(Range<usize>, String)> {
+    let lead = destination.len() - destination.trim_start().len();
+    let rest = &destination[lead..];
+    let (start, url) = match rest.strip_prefix('<') {
+        Some(inner) => (lead + 1, &inner[..inner.find('>')?]),
+        None => (
+            lead,
+            &rest[..rest.find(char::is_whitespace).unwrap_or(rest.len())],
+        ),
+    };
+    let path = url.split('#').next().unwrap_or_default();
+    if path.is_empty() || path.contains("://") || path.starts_with("mailto:") {
+        return None;
+    }
+    let decoded = percent_decode(path);
+    if decoded.starts_with('/') || !decoded.to_lowercase().ends_with(".md") {
+        return None;
+    }
+    Some((start..start + path.len(), decoded))
+}
+
+fn scan_line(line: &str, offset: usize, line_number: usize, spans: &mut Vec<LinkSpan>) {
+    let bytes = line.as_bytes();
+    let mut in_code = false;
+    // Unclosed `[` of markdown link text, a `](` without one is plain text
+    let mut open_labels = 0usize;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'`' => {
+                in_code = !in_code;
+                i += 1;
+            }
+            _ if in_code => i += 1,
+            // `\[` is a literal bracket
+            b'\\' if bytes.get(i + 1).is_some_and(u8::is_ascii_punctuation) => i += 2,
+            b'[' if line[i..].starts_with("[[") => {
+                let start = i + 2;
+                let Some(length) = line[start..].find("]]") else {
+                    break;
+                };
+                let inner = &line[start..start + length];
+                let written = &inner[..inner.find(['|', '#']).unwrap_or(inner.len())];
+                let target = written.trim();
+                // Embedded attachments like ![[image.png]] aren't notes
+                let embedded_file = i > 0 && bytes[i - 1] == b'!' && target.contains('.');
+                if !target.is_empty() && !embedded_file {
+                    let target_start = offset + start + written.len() - written.trim_start().len();
+                    spans.push(LinkSpan {
+                        kind: LinkKind::Wiki,
+                        target: target.to_string(),
+                        range: target_start..target_start + target.len(),
+                        line: line_number,
+                    });
+                }
+                i = start + length + 2;
+            }
+            b'[' => {
+                open_labels += 1;
+                i += 1;
+            }
+            b']' if open_labels > 0 && line[i..].starts_with("](") => {
+                open_labels = 0;
+                let start = i + 2;
+                let Some(length) = line[start..].find(')') else {
+                    break;
+                };
+                if let Some((range, target)) = markdown_target(&line[start..start + length]) {
+                    spans.push(LinkSpan {
+                        kind: LinkKind::Markdown,
+                        target,
+                        range: offset + start + range.start..offset + start + range.end,
+                        line: line_number,
+                    });
+                }
+                i = start + length + 1;
+            }
+            b']' => {
+                open_labels = open_labels.saturating_sub(1);
+                i += 1;
+            }
+            _ => i += 1,
+        }
+    }
+}
+
+/// Links in the body of a note, ignoring frontmatter and code.
+fn scan_links(text: &str) -> Vec<LinkSpan> {
+    let split = split_frontmatter(text);
+    let mut spans = Vec::new();
+    let mut offset = split.body_offset;
+    let mut fence: Option<&str> = None;
+    for (index, line) in split.body.split_inclusive('\n').enumerate() {
+        let trimmed = line.trim_start();
+        match fence {
+            Some(marker) if trimmed.starts_with(marker) => fence = None,
+            Some(_) => {}
+            None if trimmed.starts_with("```") || trimmed.starts_with("~~~") => {
+                fence = Some(&trimmed[..3]);
+            }
+            None => scan_line(line, offset, split.body_begin + index, &mut spans),
+        }
+        offset += line.len();
+    }
+    spans
+}
+
+/// Looks up link targets among the notes of one card.
+struct Resolver<'a> {
+    notes: HashSet<&'a str>,
+    /// Lowercased title and lowercased path without `.md`, to notes
+    by_name: HashMap<String, Vec<&'a str>>,
+}
+
+impl<'a> Resolver<'a> {
+    fn new(keys: impl Iterator<Item = &'a String>) -> Self {
+        let mut notes = HashSet::new();
+        let mut by_name: HashMap<String, Vec<&str>> = HashMap::new();
+        for key in keys {
+            notes.insert(key.as_str());
+            by_name
+                .entry(stem(key).to_lowercase())
+                .or_default()
+                .push(key);
+            if key.contains('/') {
+                by_name
+                    .entry(without_md(key).to_lowercase())
+                    .or_default()
+                    .push(key);
+            }
+        }
+        for candidates in by_name.values_mut() {
+            candidates.sort_by_key(|key| (key.len(), *key));
+        }
+        Resolver { notes, by_name }
+    }
+
+    fn resolve(&self, source: &str, kind: LinkKind, target: &str) -> Option<String> {
+        match kind {
+            LinkKind::Markdown => join_relative(parent_dir(source), target)
+                .filter(|key| self.notes.contains(key.as_str())),
+            LinkKind::Wiki => {
+                let candidates = self.by_name.get(&without_md(target).to_lowercase())?;
+                // Titles shared by several notes prefer the source's own folder
+                let dir = parent_dir(source);
+                candidates
+                    .iter()
+                    .find(|key| parent_dir(key) == dir)
+                    .or(candidates.first())
+                    .map(|key| key.to_string())
+            }
+        }
+    }
+}
+
+/// Wiki and markdown links between the notes of one card, stored under
+/// `.hollow/links/<tool>/<card>`. Notes are re-read when their size or
+/// modification time changes and links are resolved when queried.
+pub struct LinkGraph {
+    dir: PathBuf,
+    card_root: PathBuf,
+    notes: HashMap<String, IndexedNote>,
+    /// Kept in memory only for read-only realms
+    read_only: bool,
+}
+
+fn modified_millis(metadata: &fs::Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+impl LinkGraph {
+    pub fn open(dir: PathBuf, card_root: PathBuf) -> Result<Self, HollowError> {
+        let index_path = dir.join("index.json");
+        // Only a cache, a broken index is rebuilt rather than recovered
+        let notes = match fs::read(&index_path) {
+            Ok(content) => serde_json::from_slice(&content).unwrap_or_else(|e| {
+                log::warn!("Rebuilding link index {}: {}", index_path.display(), e);
+                HashMap::new()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(HollowError::io(&index_path, e)),
+        };
+        Ok(LinkGraph {
+            dir,
+            card_root,
+            notes,
+            read_only: false,
+        })
+    }
+
+    fn recovery(&self) -> Recovery {
+        match self.read_only {
+            true => Recovery::InMemory,
+            false => Recovery::Restore,
+        }
+    }
+
+    fn save(&self) -> Result<(), HollowError> {
+        if self.read_only {
+            return Ok(());
+        }
+        let content =
+            serde_json::to_vec(&self.notes).map_err(|e| HollowError::Internal(e.to_string()))?;
+        write_atomic(&self.dir.join("index.json"), &content)
+    }
+
+    fn index_note(&self, path: &Path, metadata: &fs::Metadata) -> Result<IndexedNote, HollowError> {
+        let text = read_to_string_recovering(path, self.recovery())?;
+        Ok(IndexedNote {
+            modified: modified_millis(metadata),
+            size: metadata.len(),
+            links: scan_links(&text)
+                .into_iter()
+                .map(|span| RawLink {
+                    kind: span.kind,
+                    target: span.target,
+                    line: span.line,
+                })
+                .collect(),
+        })
+    }
+
+    /// Brings the graph in line with the notes on disk.
+    pub fn refresh(&mut self) -> Result<(), HollowError> {
+        let mut seen = HashSet::new();
+        let mut changed = false;
+        let walker = WalkDir::new(&self.card_root)
+            .min_depth(1)
+            .into_iter()
+            .filter_entry(|e| !e.file_name().to_string_lossy().starts_with('.'));
+        for entry in walker.flatten() {
+            let name = entry.file_name().to_string_lossy();
+            if !entry.file_type().is_file() || !name.ends_with(".md") || is_internal_file(&name) {
+                continue;
+            }
+            let Ok(relative) = entry.path().strip_prefix(&self.card_root) else {
+                continue;
+            };
+            let key = note_key(&relative.to_string_lossy());
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            let current = self.notes.get(&key).is_some_and(|note| {
+                note.modified == modified_millis(&metadata) && note.size == metadata.len()
+            });
+            if !current {
+                match self.index_note(entry.path(), &metadata) {
+                    Ok(note) => {
+                        self.notes.insert(key.clone(), note);
+                        changed = true;
+                    }
+                    Err(e) => {
+                        log::warn!("Skipping links of {}: {}", key, e);
+                        continue;
+                    }
+                }
+            }
+            seen.insert(key);
+        }
+
+        let before = self.notes.len();
+        self.notes.retain(|key, _| seen.contains(key));
+        if changed || self.notes.len() != before {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// Re-reads one note after it was written.
+    pub fn update(&mut self, path: &str) -> Result<(), HollowError> {
+        let key = note_key(path);
+        let full_path = self.card_root.join(&key);
+        let metadata = fs::metadata(&full_path).map_err(|e| HollowError::io(&full_path, e))?;
+        let note = self.index_note(&full_path, &metadata)?;
+        self.notes.insert(key, note);
+        self.save()
+    }
+
+    /// Moves the notes of `old` (a note or a whole folder) to `new`.
+    pub fn rename(&mut self, old: &str, new: &str) -> Result<(), HollowError> {
+        let (old, new) = (note_key(old), note_key(new));
+        let keys: Vec<String> = self
+            .notes
+            .keys()
+            .filter(|key| moved(key, &old, &new).is_some())
+            .cloned()
+            .collect();
+        if keys.is_empty() {
+            return Ok(());
+        }
+        for key in keys {
+            if let Some(note) = self.notes.remove(&key) {
+                self.notes
+                    .insert(moved(&key, &old, &new).unwrap_or(key), note);
+            }
+        }
+        self.save()
+    }
+
+    /// New contents for every note whose links change when `old` is renamed
+    /// to `new`, keyed by where each note lives after the rename.
+    pub fn rewrites_for_rename(
+        &self,
+        old: &str,
+        new: &str,
+    ) -> Result<Vec<NoteRewrite>, HollowError> {
+        let (old, new) = (note_key(old), note_key(new));
+        let resolver = Resolver::new(self.notes.keys());
+        let mut sources: Vec<&String> = self.notes.keys().collect();
+        sources.sort();
+
+        let mut rewrites = Vec::new();
+        for source in sources {
+            let source_moved = moved(source, &old, &new);
+            let affected = self.notes[source].links.iter().any(|link| {
+                (source_moved.is_some() && link.kind == LinkKind::Markdown)
+                    || resolver
+                        .resolve(source, link.kind, &link.target)
+                        .is_some_and(|target| moved(&target, &old, &new).is_some())
+            });
+            if !affected {
+                continue;
+            }
+
+            let text = read_to_string_recovering(&self.card_root.join(source), self.recovery())?;
+            let new_source = source_moved.unwrap_or_else(|| source.clone());
+            let mut edits = Vec::new();
+            for span in scan_links(&text) {
+                let Some(target) = resolver.resolve(source, span.kind, &span.target) else {
+                    continue;
+                };
+                let new_target = moved(&target, &old, &new);
+                let replacement = match (span.kind, new_target) {
+                    (LinkKind::Wiki, Some(new_target)) if span.target.contains('/') => {
+                        without_md(&new_target).to_string()
+                    }
+                    (LinkKind::Wiki, Some(new_target)) => stem(&new_target).to_string(),
+                    (LinkKind::Wiki, None) => continue,
+                    (LinkKind::Markdown, new_target) => {
+                        relative_to(parent_dir(&new_source), &new_target.unwrap_or(target))
+                            .replace(' ', "%20")
+                    }
+                };
+                if replacement != text[span.range.clone()] {
+                    edits.push((span.range, replacement));
+                }
+            }
+            if edits.is_empty() {
+                continue;
+            }
+
+            let mut contents = text.clone();
+            for (range, replacement) in edits.into_iter().rev() {
+                contents.replace_range(range, &replacement);
+            }
+            rewrites.push(NoteRewrite {
+                path: new_source,
+                original: text,
+                contents,
+            });
+        }
+        Ok(rewrites)
+    }
+
+    pub(crate) fn link_refs(&self) -> Vec<LinkRef> {
+        let resolver = Resolver::new(self.notes.keys());
+        let mut refs: Vec<LinkRef> = self
+            .notes
+            .iter()
+            .flat_map(|(source, note)| {
+                let resolver = &resolver;
+                note.links.iter().map(move |link| LinkRef {
+                    source: source.clone(),
+                    target: link.target.clone(),
+                    kind: link.kind,
+                    line: link.line,
+                    resolved: resolver.resolve(source, link.kind, &link.target),
+                })
+            })
+            .collect();
+        refs.sort_by(|a, b| (&a.source, a.line).cmp(&(&b.source, b.line)));
+        refs
+    }
+}
+
+pub fn links_dir(tool_name: &str, card_name: &str) -> PathBuf {
+    Path::new(".hollow")
+        .join("links")
+        .join(tool_name)
+        .join(card_name)
+}
+
+pub fn open_graph(
+    tool_name: &str,
+    card_name: &str,
+    state: &State<'_, Mutex<crate::app::AppData>>,
+) -> Result<LinkGraph, HollowError> {
+    validate_path(tool_name)?;
+    validate_path(card_name)?;
+    let mut graph = LinkGraph::open(
+        get_full_path(links_dir(tool_name, card_name), state)?,
+        resolve_card_path(tool_name, card_name, "", state)?,
+    )?;
+    graph.read_only = is_read_only(state);
+    Ok(graph)
+}
+
+fn refreshed_graph(
+    tool_name: &str,
+    card_name: &str,
+    state: &State<'_, Mutex<crate::app::AppData>>,
+) -> Result<LinkGraph, HollowError> {
+    let mut graph = open_graph(tool_name, card_name, state)?;
+    graph.refresh()?;
+    Ok(graph)
+}
+
+/// Links going out of the note at `path`.
+#[command]
+pub fn card_links(
+    tool_name: String,
+    card_name: String,
+    path: String,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<Vec<LinkRef>, HollowError> {
+    ensure_card_access(&caller, &tool_name, Access::Read, &state)?;
+    let key = note_key(&path);
+    let graph = refreshed_graph(&tool_name, &card_name, &state)?;
+    Ok(graph
+        .link_refs()
+        .into_iter()
+        .filter(|link| link.source == key)
+        .collect())
+}
+
+/// Links in other notes pointing at the note at `path`.
+#[command]
+pub fn card_backlinks(
+    tool_name: String,
+    card_name: String,
+    path: String,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<Vec<LinkRef>, HollowError> {
+    ensure_card_access(&caller, &tool_name, Access::Read, &state)?;
+    let key = note_key(&path);
+    let graph = refreshed_graph(&tool_name, &card_name, &state)?;
+    Ok(graph
+        .link_refs()
+        .into_iter()
+        .filter(|link| link.resolved.as_deref() == Some(key.as_str()) && link.source != key)
+        .collect())
+}
+
+/// Notes no other note links to.
+#[command]
+pub fn card_orphan_notes(
+    tool_name: String,
+    card_name: String,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<Vec<String>, HollowError> {
+    ensure_card_access(&caller, &tool_name, Access::Read, &state)?;
+    let graph = refreshed_graph(&tool_name, &card_name, &state)?;
+    let linked: HashSet<String> = graph
+        .link_refs()
+        .into_iter()
+        .filter_map(|link| link.resolved.filter(|target| *target != link.source))
+        .collect();
+    let mut orphans: Vec<String> = graph
+        .notes
+        .keys()
+        .filter(|key| !linked.contains(*key))
+        .cloned()
+        .collect();
+    orphans.sort();
+    Ok(orphans)
+}
+
+/// Links whose target note doesn't exist.
+#[command]
+pub fn card_broken_links(
+    tool_name: String,
+    card_name: String,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<Vec<LinkRef>, HollowError> {
+    ensure_card_access(&caller, &tool_name, Access::Read, &state)?;
+    let graph = refreshed_graph(&tool_name, &card_name, &state)?;
+    Ok(graph
+        .link_refs()
+        .into_iter()
+        .filter(|link| link.resolved.is_none())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn targets(text: &str) -> Vec<(String, &str)> {
+        scan_links(text)
+            .into_iter()
+            .map(|span| (span.target, &text[span.range]))
+            .collect()
+    }
+
+    #[test]
+    fn wiki_links_drop_anchor_and_alias() {
+        let text = "See [[ Garden Notes#Roses|roses]] and [[plans/2024]].\n";
+        assert_eq!(
+            targets(text),
+            vec![
+                ("Garden Notes".to_string(), "Garden Notes"),
+                ("plans/2024".to_string(), "plans/2024"),
+            ]
+        );
+        // Attachments are embedded, notes can be too
+        assert_eq!(targets("![[photo.png]] ![[Note]]").len(), 1);
+    }
+
+    #[test]
+    fn markdown_links_keep_only_note_paths() {
+        let text = "[a](../notes/My%20Note.md#top) [b](https://x.org/a.md) [c](#local) [d](<with space.md>)\n";
+        assert_eq!(
+            targets(text),
+            vec![
+                ("../notes/My Note.md".to_string(), "../notes/My%20Note.md"),
+                ("with space.md".to_string(), "with space.md"),
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_code_and_escaped_brackets() {
+        let text = "`[[Not]]` \\[[Escaped]] \\[x](a.md) [[Real]]\n```\n[[Fenced]]\n```\n";
+        assert_eq!(targets(text), vec![("Real".to_string(), "Real")]);
+    }
+
+    #[test]
+    fn frontmatter_shifts_lines_and_offsets() {
+        let text = "---\ntitle: [[Meta]]\n---\n# Title\n[[Body]]\n";
+        let spans = scan_links(text);
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].line, 5);
+        assert_eq!(&text[spans[0].range.clone()], "Body");
+    }
+}
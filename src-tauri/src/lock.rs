@@ -0,0 +1,281 @@
+use crate::error::HollowError;
+use crate::utils::temp_path;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tauri::{AppHandle, Emitter};
+use tauri_plugin_log::log;
+use uuid::Uuid;
+
+/// Relative to the realm root
+pub const LOCK_FILE: &str = ".hollow/realm.lock";
+pub const LOCK_LOST_EVENT: &str = "realm-lock-lost";
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+/// A lock whose heartbeat is older than this belongs to a process that's gone
+const STALE_AFTER_MS: u64 = 30_000;
+
+#[derive(Serialize, Deserialize)]
+struct LockInfo {
+    pid: u32,
+    /// Tells our lock apart from one a later process took over
+    token: String,
+    acquired: u64,
+    heartbeat: u64,
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+fn read_lock(path: &Path) -> Option<LockInfo> {
+    serde_json::from_slice(&fs::read(path).ok()?).ok()
+}
+
+fn serialize(info: &LockInfo) -> Result<Vec<u8>, HollowError> {
+    serde_json::to_vec(info).map_err(|e| HollowError::Internal(e.to_string()))
+}
+
+/// Fails with `AlreadyExists` when another process holds the file.
+fn create_lock(path: &Path, info: &LockInfo) -> Result<(), HollowError> {
+    let content = serialize(info)?;
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .map_err(|e| HollowError::io(path, e))?;
+    file.write_all(&content)
+        .and_then(|_| file.sync_all())
+        .map_err(|e| HollowError::io(path, e))
+}
+
+fn write_lock(path: &Path, info: &LockInfo) -> Result<(), HollowError> {
+    let staged = temp_path(path);
+    fs::write(&staged, serialize(info)?).map_err(|e| HollowError::io(&staged, e))?;
+    fs::rename(&staged, path).map_err(|e| {
+        let _ = fs::remove_file(&staged);
+        HollowError::io(path, e)
+    })
+}
+
+/// Unreadable locks fall back to the file's modification time, a process may
+/// have died halfway through writing one.
+fn is_stale(path: &Path, info: Option<&LockInfo>) -> bool {
+    let heartbeat = match info {
+        Some(info) => info.heartbeat,
+        None => fs::metadata(path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_millis() as u64),
+    };
+    now_millis().saturating_sub(heartbeat) > STALE_AFTER_MS
+}
+
+fn run_heartbeat(path: PathBuf, mut info: LockInfo, stop: mpsc::Receiver<()>, app: AppHandle) {
+    // Anything but a timeout means the lock was released
+    while let Err(RecvTimeoutError::Timeout) = stop.recv_timeout(HEARTBEAT_INTERVAL) {
+        if read_lock(&path).is_none_or(|current| current.token != info.token) {
+            log::error!("Lost the realm lock {}", path.display());
+            if let Err(e) = app.emit(LOCK_LOST_EVENT, path.to_string_lossy()) {
+                log::error!("Failed to emit lock loss: {}", e);
+            }
+            return;
+        }
+        info.heartbeat = now_millis();
+        if let Err(e) = write_lock(&path, &info) {
+            log::warn!("Could not refresh the realm lock: {}", e);
+        }
+    }
+}
+
+/// Creates the lock file for `info`, taking over a stale one.
+fn claim(path: &Path, info: &LockInfo) -> Result<(), HollowError> {
+    // A second attempt follows taking over a stale lock
+    for _ in 0..2 {
+        match create_lock(path, info) {
+            Ok(()) => return Ok(()),
+            Err(HollowError::AlreadyExists(_)) => {
+                let existing = read_lock(path);
+                if !is_stale(path, existing.as_ref()) {
+                    return Err(HollowError::RealmLocked(existing.map_or(0, |i| i.pid)));
+                }
+                log::warn!("Taking over stale realm lock {}", path.display());
+                // Only remove the lock that was judged stale, not one written since
+                let current = read_lock(path);
+                if current.map(|i| i.token) == existing.map(|i| i.token) {
+                    match fs::remove_file(path) {
+                        Err(e) if e.kind() != ErrorKind::NotFound => {
+                            return Err(HollowError::io(path, e))
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Err(HollowError::RealmLocked(
+        read_lock(path).map_or(0, |i| i.pid),
+    ))
+}
+
+/// Removes the lock file if it still carries `token`.
+fn release_lock(path: &Path, token: &str) {
+    // The file may belong to another process by now
+    if read_lock(path).is_none_or(|info| info.token != token) {
+        return;
+    }
+    if let Err(e) = fs::remove_file(path) {
+        log::warn!("Could not remove the realm lock: {}", e);
+    }
+}
+
+struct HeldLock {
+    path: PathBuf,
+    token: String,
+    /// Dropping it stops the heartbeat
+    _stop: mpsc::Sender<()>,
+}
+
+impl HeldLock {
+    fn create(path: PathBuf, app: &AppHandle) -> Result<Self, HollowError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| HollowError::io(parent, e))?;
+        }
+        let now = now_millis();
+        let info = LockInfo {
+            pid: process::id(),
+            token: Uuid::new_v4().to_string(),
+            acquired: now,
+            heartbeat: now,
+        };
+        claim(&path, &info)?;
+
+        let token = info.token.clone();
+        let (sender, receiver) = mpsc::channel();
+        let heartbeat_path = path.clone();
+        let app = app.clone();
+        thread::spawn(move || run_heartbeat(heartbeat_path, info, receiver, app));
+        Ok(HeldLock {
+            path,
+            token,
+            _stop: sender,
+        })
+    }
+
+    fn release(self) {
+        release_lock(&self.path, &self.token);
+    }
+}
+
+/// Exclusive hold on the open realm through `.hollow/realm.lock`, so two
+/// processes never write the same stores.
+#[derive(Default)]
+pub struct RealmLock {
+    held: Option<HeldLock>,
+}
+
+impl RealmLock {
+    /// Locks `realm_root`, then lets go of the previous realm. Keeps the
+    /// current lock when it's already for `realm_root`.
+    pub fn acquire(&mut self, realm_root: &Path, app: &AppHandle) -> Result<(), HollowError> {
+        let path = realm_root.join(LOCK_FILE);
+        if self.held.as_ref().is_some_and(|held| held.path == path) {
+            return Ok(());
+        }
+        let held = HeldLock::create(path, app)?;
+        self.release();
+        self.held = Some(held);
+        Ok(())
+    }
+
+    pub fn release(&mut self) {
+        if let Some(held) = self.held.take() {
+            held.release();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use filetime::{set_file_mtime, FileTime};
+
+    fn info(pid: u32, heartbeat: u64) -> LockInfo {
+        LockInfo {
+            pid,
+            token: Uuid::new_v4().to_string(),
+            acquired: heartbeat,
+            heartbeat,
+        }
+    }
+
+    #[test]
+    fn a_fresh_lock_refuses_a_second_claim() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("realm.lock");
+        let first = info(1, now_millis());
+        claim(&path, &first).unwrap();
+
+        assert!(matches!(
+            claim(&path, &info(2, now_millis())),
+            Err(HollowError::RealmLocked(1))
+        ));
+        assert_eq!(read_lock(&path).unwrap().token, first.token);
+    }
+
+    #[test]
+    fn takes_over_a_stale_heartbeat() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("realm.lock");
+        write_lock(&path, &info(1, now_millis() - STALE_AFTER_MS - 1000)).unwrap();
+
+        let second = info(2, now_millis());
+        claim(&path, &second).unwrap();
+        let current = read_lock(&path).unwrap();
+        assert_eq!((current.pid, current.token), (2, second.token));
+    }
+
+    #[test]
+    fn unreadable_locks_go_by_their_modification_time() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("realm.lock");
+        // Torn while the owner was writing it
+        fs::write(&path, "{\"pid\": 1, \"tok").unwrap();
+        assert!(matches!(
+            claim(&path, &info(2, now_millis())),
+            Err(HollowError::RealmLocked(0))
+        ));
+
+        let old = SystemTime::now() - Duration::from_millis(STALE_AFTER_MS * 2);
+        set_file_mtime(&path, FileTime::from_system_time(old)).unwrap();
+        let second = info(2, now_millis());
+        claim(&path, &second).unwrap();
+        assert_eq!(read_lock(&path).unwrap().token, second.token);
+    }
+
+    #[test]
+    fn release_removes_only_our_lock() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("realm.lock");
+        let ours = info(1, now_millis());
+        claim(&path, &ours).unwrap();
+
+        release_lock(&path, "someone else");
+        assert!(path.exists());
+        release_lock(&path, &ours.token);
+        assert!(!path.exists());
+        // Nothing to release is fine
+        release_lock(&path, &ours.token);
+    }
+}
@@ -0,0 +1,313 @@
+use crate::archive::{self, ExportOptions};
+use crate::error::HollowError;
+use crate::utils::{read_json_recovering, write_atomic, Recovery};
+use crate::versions;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tauri::{AppHandle, Emitter};
+use tauri_plugin_log::log;
+
+/// Layout version written by this build, realms without `realm.json` are version 0
+pub const SCHEMA_VERSION: u32 = 2;
+/// First schema with plugins in `plugins/<name>/<version>/`
+pub const VERSIONED_PLUGINS_SCHEMA: u32 = 2;
+pub const MIGRATION_PROGRESS_EVENT: &str = "realm-migration-progress";
+/// Archives taken before migrating, older ones are pruned
+const KEPT_ARCHIVES: usize = 3;
+const ARCHIVE_PREFIX: &str = "before-v";
+/// Hidden `.<name>.migrating` folders hold plugins while they get a version folder
+const MIGRATING_SUFFIX: &str = ".migrating";
+
+#[derive(Serialize, Deserialize)]
+pub struct RealmInfo {
+    pub schema_version: u32,
+    /// App version that last wrote the file
+    pub app_version: String,
+}
+
+#[derive(Serialize, Clone)]
+pub struct MigrationProgress {
+    pub step: usize,
+    pub total: usize,
+    /// Version the realm is at once this step is done
+    pub version: u32,
+    pub description: &'static str,
+}
+
+pub fn emit_progress(app: &AppHandle, progress: MigrationProgress) {
+    if let Err(e) = app.emit(MIGRATION_PROGRESS_EVENT, progress) {
+        log::error!("Failed to emit migration progress: {}", e);
+    }
+}
+
+/// Upgrades a realm from `to - 1` to `to`. Steps run in order and each one is
+/// recorded as soon as it succeeds, so an interrupted run resumes where it stopped.
+struct Migration {
+    to: u32,
+    description: &'static str,
+    run: fn(&Path) -> Result<(), HollowError>,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        to: 1,
+        description: "Create missing realm folders",
+        run: create_missing_dirs,
+    },
+    Migration {
+        to: VERSIONED_PLUGINS_SCHEMA,
+        description: "Move plugins into version folders",
+        run: version_plugin_folders,
+    },
+];
+
+fn create_missing_dirs(realm_root: &Path) -> Result<(), HollowError> {
+    for dir in ["vault", "plugins", "main"] {
+        let path = realm_root.join(dir);
+        fs::create_dir_all(&path).map_err(|e| HollowError::io(&path, e))?;
+    }
+    Ok(())
+}
+
+/// Name of the folder a plugin is rebuilt in by `version_plugin_folders`
+fn migrating_path(plugin_dir: &Path) -> PathBuf {
+    let name = plugin_dir.file_name().unwrap_or_default().to_string_lossy();
+    plugin_dir.with_file_name(format!(".{}{}", name, MIGRATING_SUFFIX))
+}
+
+/// Finishes or undoes plugins a crashed run left in their migrating folder.
+/// Once the plugin is inside, only the final rename is left to do.
+fn recover_migrating(plugins_dir: &Path, entries: &[PathBuf]) -> Result<(), HollowError> {
+    for staging in entries {
+        let file_name = staging.file_name().unwrap_or_default().to_string_lossy();
+        let Some(name) = file_name
+            .strip_prefix('.')
+            .and_then(|name| name.strip_suffix(MIGRATING_SUFFIX))
+        else {
+            continue;
+        };
+        let plugin_dir = plugins_dir.join(name);
+        if plugin_dir.exists() {
+            // The crash came before the plugin moved, only the empty folder is left
+            fs::remove_dir(staging).map_err(|e| HollowError::io(staging, e))?;
+            continue;
+        }
+        if !staging.join(versions::ACTIVE_FILE).exists() {
+            let version = versions::installed_versions(staging)?
+                .into_iter()
+                .next()
+                .ok_or_else(|| HollowError::NotFound(staging.clone()))?;
+            versions::activate(staging, &version)?;
+        }
+        fs::rename(staging, &plugin_dir).map_err(|e| HollowError::io(&plugin_dir, e))?;
+        log::warn!(
+            "Finished migrating plugin {} after an interrupted run",
+            name
+        );
+    }
+    Ok(())
+}
+
+/// `plugins/<name>/` becomes `plugins/<name>/<version>/`, the version coming
+/// from the plugin's manifest. The new layout is built in a hidden folder and
+/// renamed into place last, so a crash never leaves a plugin half moved.
+fn version_plugin_folders(realm_root: &Path) -> Result<(), HollowError> {
+    let plugins_dir = realm_root.join("plugins");
+    let entries = match fs::read_dir(&plugins_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(HollowError::io(&plugins_dir, e)),
+    };
+    let mut entries = entries
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| HollowError::io(&plugins_dir, e))?;
+    entries.sort();
+    recover_migrating(&plugins_dir, &entries)?;
+
+    for plugin_dir in entries {
+        let hidden = plugin_dir
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if hidden || !plugin_dir.is_dir() || plugin_dir.join(versions::ACTIVE_FILE).exists() {
+            continue;
+        }
+        // Plugins added from code alone have no manifest
+        let version = fs::read(plugin_dir.join("manifest.json"))
+            .ok()
+            .and_then(|content| serde_json::from_slice::<serde_json::Value>(&content).ok())
+            .and_then(|manifest| manifest.get("version")?.as_str().map(str::to_string))
+            .filter(|version| semver::Version::parse(version).is_ok());
+        let version = version.unwrap_or_else(|| {
+            log::warn!(
+                "Plugin {} has no manifest version, installing it as 0.0.0 so any registry version counts as an update",
+                plugin_dir.display()
+            );
+            "0.0.0".to_string()
+        });
+
+        let staging = migrating_path(&plugin_dir);
+        fs::create_dir(&staging).map_err(|e| HollowError::io(&staging, e))?;
+        let target = staging.join(&version);
+        fs::rename(&plugin_dir, &target).map_err(|e| HollowError::io(&plugin_dir, e))?;
+        versions::activate(&staging, &version)?;
+        fs::rename(&staging, &plugin_dir).map_err(|e| HollowError::io(&plugin_dir, e))?;
+    }
+    Ok(())
+}
+
+fn info_path(realm_root: &Path) -> PathBuf {
+    realm_root.join(".hollow").join("realm.json")
+}
+
+pub fn read_info(realm_root: &Path, recovery: Recovery) -> Result<Option<RealmInfo>, HollowError> {
+    read_json_recovering(&info_path(realm_root), recovery)
+}
+
+pub fn write_info(realm_root: &Path, schema_version: u32) -> Result<(), HollowError> {
+    let info = RealmInfo {
+        schema_version,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+    };
+    let content =
+        serde_json::to_vec_pretty(&info).map_err(|e| HollowError::Internal(e.to_string()))?;
+    write_atomic(&info_path(realm_root), &content)
+}
+
+pub fn schema_version(realm_root: &Path, recovery: Recovery) -> Result<u32, HollowError> {
+    Ok(read_info(realm_root, recovery)?.map_or(0, |info| info.schema_version))
+}
+
+/// Removes all but the newest `KEPT_ARCHIVES` archives in `archive_dir`.
+fn prune_archives(archive_dir: &Path) -> Result<(), HollowError> {
+    let entries = fs::read_dir(archive_dir).map_err(|e| HollowError::io(archive_dir, e))?;
+    let mut archives: Vec<(u128, PathBuf)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let (_, created) = name
+                .strip_prefix(ARCHIVE_PREFIX)?
+                .strip_suffix(".zip")?
+                .split_once('-')?;
+            Some((created.parse().ok()?, entry.path()))
+        })
+        .collect();
+    archives.sort_by_key(|(created, _)| std::cmp::Reverse(*created));
+    for (_, path) in archives.into_iter().skip(KEPT_ARCHIVES) {
+        fs::remove_file(&path).map_err(|e| HollowError::io(&path, e))?;
+    }
+    Ok(())
+}
+
+/// Brings the realm to `SCHEMA_VERSION`. The realm is archived to
+/// `archive_dir`, outside the realm, first and the archive path is returned
+/// when anything ran.
+pub fn migrate(
+    realm_root: &Path,
+    archive_dir: &Path,
+    progress: &dyn Fn(MigrationProgress),
+) -> Result<Option<PathBuf>, HollowError> {
+    let current = schema_version(realm_root, Recovery::Restore)?;
+    if current > SCHEMA_VERSION {
+        return Err(HollowError::InvalidInput(format!(
+            "The realm uses layout version {}, this version of Hollow only knows up to {}",
+            current, SCHEMA_VERSION
+        )));
+    }
+    let pending: Vec<&Migration> = MIGRATIONS.iter().filter(|m| m.to > current).collect();
+    if pending.is_empty() {
+        return Ok(None);
+    }
+
+    let created = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    let backup = archive_dir.join(format!("{}{}-{}.zip", ARCHIVE_PREFIX, current, created));
+    fs::create_dir_all(archive_dir).map_err(|e| HollowError::io(archive_dir, e))?;
+    archive::export(realm_root, &backup, &ExportOptions::default(), &|_| {})?;
+    log::info!("Backed up realm before migrating to {}", backup.display());
+    if let Err(e) = prune_archives(archive_dir) {
+        log::warn!("Could not prune migration archives: {}", e);
+    }
+
+    for (step, migration) in pending.iter().enumerate() {
+        progress(MigrationProgress {
+            step: step + 1,
+            total: pending.len(),
+            version: migration.to,
+            description: migration.description,
+        });
+        (migration.run)(realm_root).inspect_err(|e| {
+            log::error!("Migration to version {} failed: {}", migration.to, e);
+        })?;
+        write_info(realm_root, migration.to)?;
+        log::info!(
+            "Migrated realm to version {}: {}",
+            migration.to,
+            migration.description
+        );
+    }
+    Ok(Some(backup))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plugin(dir: &Path, version: Option<&str>) {
+        fs::create_dir_all(dir).unwrap();
+        fs::write(dir.join("index.js"), "run()").unwrap();
+        if let Some(version) = version {
+            let manifest = serde_json::json!({ "name": "demo", "version": version });
+            fs::write(dir.join("manifest.json"), manifest.to_string()).unwrap();
+        }
+    }
+
+    #[test]
+    fn moves_plugins_into_version_folders() {
+        let tmp = tempfile::tempdir().unwrap();
+        let plugins = tmp.path().join("plugins");
+        plugin(&plugins.join("demo"), Some("1.2.0"));
+        plugin(&plugins.join("bare"), None);
+        version_plugin_folders(tmp.path()).unwrap();
+        assert!(plugins.join("demo/1.2.0/index.js").is_file());
+        assert!(plugins.join("bare/0.0.0/index.js").is_file());
+        assert_eq!(
+            versions::active_version(&plugins.join("demo"), Recovery::Restore).unwrap(),
+            Some("1.2.0".to_string())
+        );
+        // Running again changes nothing
+        version_plugin_folders(tmp.path()).unwrap();
+        assert!(plugins.join("demo/1.2.0/index.js").is_file());
+    }
+
+    #[test]
+    fn resumes_after_a_crash() {
+        let tmp = tempfile::tempdir().unwrap();
+        let plugins = tmp.path().join("plugins");
+        // Crashed right after creating the migrating folder
+        plugin(&plugins.join("early"), Some("1.0.0"));
+        fs::create_dir(migrating_path(&plugins.join("early"))).unwrap();
+        // Crashed once the plugin was moved in, before it got a pointer
+        plugin(
+            &migrating_path(&plugins.join("late")).join("2.0.0"),
+            Some("2.0.0"),
+        );
+        version_plugin_folders(tmp.path()).unwrap();
+
+        for (name, version) in [("early", "1.0.0"), ("late", "2.0.0")] {
+            let plugin_dir = plugins.join(name);
+            assert!(plugin_dir.join(version).join("index.js").is_file());
+            assert_eq!(
+                versions::active_version(&plugin_dir, Recovery::Restore).unwrap(),
+                Some(version.to_string())
+            );
+            assert!(!migrating_path(&plugin_dir).exists());
+        }
+    }
+}
@@ -0,0 +1,465 @@
+use crate::error::HollowError;
+use crate::utils::{
+    ensure_writable, get_full_path, get_realm_root, read_json_recovering, recovery, validate_path,
+    write_atomic, Recovery,
+};
+use crate::versions;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs,
+    path::{Component, Path, PathBuf},
+    sync::Mutex,
+};
+use tauri::{command, State, WebviewWindow};
+use tauri_plugin_log::log;
+use uuid::Uuid;
+
+/// Grants per plugin, relative to the realm root
+const GRANTS_FILE: &str = ".hollow/permissions.json";
+/// What plugins are refused when they call commands only the app may use
+const APP_ONLY: &str = "app-only commands";
+
+/// Capability a plugin asks for in its manifest. Plugins always reach their
+/// own tool's cards, everything else needs a grant.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Permission {
+    /// Cards of other tools
+    #[serde(rename = "cards:read")]
+    CardsRead,
+    #[serde(rename = "cards:write")]
+    CardsWrite,
+    #[serde(rename = "vault:read")]
+    VaultRead,
+    #[serde(rename = "vault:write")]
+    VaultWrite,
+    /// Requests through the `fetch` of the plugin's frame, see `plugin_fetch`
+    #[serde(rename = "network")]
+    Network,
+    /// In-app notifications through `hollow.notify` in the plugin's frame
+    #[serde(rename = "notifications")]
+    Notifications,
+}
+
+impl Permission {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Permission::CardsRead => "cards:read",
+            Permission::CardsWrite => "cards:write",
+            Permission::VaultRead => "vault:read",
+            Permission::VaultWrite => "vault:write",
+            Permission::Network => "network",
+            Permission::Notifications => "notifications",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// Who a command runs for, worked out from the `caller` token it was given.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Caller {
+    App,
+    Plugin(String),
+}
+
+/// Tokens handed out to the app's windows. Each window's page takes its own
+/// before any plugin runs and each plugin gets one as it loads, so commands
+/// know who calls them without trusting a name the page sends.
+#[derive(Default, Debug)]
+pub struct Sessions {
+    /// The app's token per window label
+    app: HashMap<String, String>,
+    plugins: HashMap<String, String>,
+    /// Tokens plugin frames load their own files with, through `realm://`
+    assets: HashMap<String, String>,
+}
+
+impl Sessions {
+    /// Drops the tokens of a window, the page that held them is gone. Plugins
+    /// only run in the main window.
+    pub fn reset(&mut self, window: &str) {
+        self.app.remove(window);
+        if window == crate::realms::MAIN_WINDOW {
+            self.plugins.clear();
+            self.assets.clear();
+        }
+    }
+
+    /// The app's token for a window, only handed out once per page load.
+    pub fn start(&mut self, window: &str) -> Result<String, HollowError> {
+        if self.app.contains_key(window) {
+            return Err(HollowError::UnknownCaller);
+        }
+        let token = Uuid::new_v4().to_string();
+        self.app.insert(window.to_string(), token.clone());
+        Ok(token)
+    }
+
+    pub fn issue(&mut self, plugin: &str) -> String {
+        let token = Uuid::new_v4().to_string();
+        self.plugins.insert(token.clone(), plugin.to_string());
+        token
+    }
+
+    pub fn issue_assets(&mut self, plugin: &str) -> String {
+        let token = Uuid::new_v4().to_string();
+        self.assets.insert(token.clone(), plugin.to_string());
+        token
+    }
+
+    /// The plugin whose files an asset token opens.
+    pub fn assets(&self, token: &str) -> Option<&str> {
+        self.assets.get(token).map(String::as_str)
+    }
+
+    pub fn resolve(&self, token: &str) -> Result<Caller, HollowError> {
+        if self.app.values().any(|app| app == token) {
+            return Ok(Caller::App);
+        }
+        self.plugins
+            .get(token)
+            .map(|plugin| Caller::Plugin(plugin.clone()))
+            .ok_or(HollowError::UnknownCaller)
+    }
+}
+
+pub fn resolve_caller(
+    caller: &str,
+    state: &State<'_, Mutex<crate::app::AppData>>,
+) -> Result<Caller, HollowError> {
+    state.lock().unwrap().sessions.resolve(caller)
+}
+
+/// For commands that manage plugins or the realm itself.
+pub fn ensure_app(
+    caller: &str,
+    state: &State<'_, Mutex<crate::app::AppData>>,
+) -> Result<(), HollowError> {
+    match resolve_caller(caller, state)? {
+        Caller::App => Ok(()),
+        Caller::Plugin(plugin) => {
+            log::warn!("Plugin {} was refused {}", plugin, APP_ONLY);
+            Err(HollowError::NotGranted {
+                plugin,
+                permission: APP_ONLY,
+            })
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct PluginPermissions {
+    /// What the active version's manifest asks for
+    pub requested: Vec<Permission>,
+    pub granted: Vec<Permission>,
+}
+
+type Grants = BTreeMap<String, BTreeSet<Permission>>;
+
+fn load_grants(realm_root: &Path, recovery: Recovery) -> Result<Grants, HollowError> {
+    Ok(read_json_recovering(&realm_root.join(GRANTS_FILE), recovery)?.unwrap_or_default())
+}
+
+fn save_grants(realm_root: &Path, grants: &Grants) -> Result<(), HollowError> {
+    let content =
+        serde_json::to_vec_pretty(grants).map_err(|e| HollowError::Internal(e.to_string()))?;
+    write_atomic(&realm_root.join(GRANTS_FILE), &content)
+}
+
+pub fn granted(
+    realm_root: &Path,
+    plugin: &str,
+    recovery: Recovery,
+) -> Result<BTreeSet<Permission>, HollowError> {
+    Ok(load_grants(realm_root, recovery)?
+        .remove(plugin)
+        .unwrap_or_default())
+}
+
+/// Permissions in the active version's manifest. Unknown ones are left out,
+/// manifest validation already reports them.
+pub fn requested(
+    realm_root: &Path,
+    plugin: &str,
+    recovery: Recovery,
+) -> Result<Vec<Permission>, HollowError> {
+    let Some(dir) = versions::active_dir(&realm_root.join("plugins").join(plugin), recovery)?
+    else {
+        return Ok(Vec::new());
+    };
+    let path = dir.join("manifest.json");
+    let manifest: Value = match fs::read(&path) {
+        Ok(content) => {
+            serde_json::from_slice(&content).map_err(|e| HollowError::parse(&path, e))?
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(HollowError::io(&path, e)),
+    };
+    let mut permissions: Vec<Permission> = manifest
+        .get("permissions")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|p| serde_json::from_value(p.clone()).ok())
+        .collect();
+    permissions.sort();
+    permissions.dedup();
+    Ok(permissions)
+}
+
+/// Grants exactly `permissions`, which must all be requested by the plugin.
+pub fn set_granted(
+    realm_root: &Path,
+    plugin: &str,
+    permissions: &[Permission],
+) -> Result<(), HollowError> {
+    let requested = requested(realm_root, plugin, Recovery::Restore)?;
+    if let Some(extra) = permissions.iter().find(|p| !requested.contains(p)) {
+        return Err(HollowError::InvalidInput(format!(
+            "{} doesn't ask for {}",
+            plugin,
+            extra.as_str()
+        )));
+    }
+    let mut grants = load_grants(realm_root, Recovery::Restore)?;
+    if permissions.is_empty() {
+        grants.remove(plugin);
+    } else {
+        grants.insert(plugin.to_string(), permissions.iter().copied().collect());
+    }
+    save_grants(realm_root, &grants)
+}
+
+/// Drops a removed plugin's grants so a later install starts from none.
+pub fn forget(realm_root: &Path, plugin: &str) -> Result<(), HollowError> {
+    let mut grants = load_grants(realm_root, Recovery::Restore)?;
+    if grants.remove(plugin).is_some() {
+        save_grants(realm_root, &grants)?;
+    }
+    Ok(())
+}
+
+fn check_granted(
+    plugin: &str,
+    permission: Permission,
+    state: &State<'_, Mutex<crate::app::AppData>>,
+) -> Result<(), HollowError> {
+    if is_granted(plugin, permission, state)? {
+        return Ok(());
+    }
+    log::warn!("Plugin {} was refused {}", plugin, permission.as_str());
+    Err(HollowError::NotGranted {
+        plugin: plugin.to_string(),
+        permission: permission.as_str(),
+    })
+}
+
+pub fn ensure_granted(
+    caller: &str,
+    permission: Permission,
+    state: &State<'_, Mutex<crate::app::AppData>>,
+) -> Result<(), HollowError> {
+    match resolve_caller(caller, state)? {
+        Caller::Plugin(plugin) => check_granted(&plugin, permission, state),
+        Caller::App => Ok(()),
+    }
+}
+
+pub fn ensure_card_access(
+    caller: &str,
+    tool_name: &str,
+    access: Access,
+    state: &State<'_, Mutex<crate::app::AppData>>,
+) -> Result<(), HollowError> {
+    match resolve_caller(caller, state)? {
+        Caller::Plugin(plugin) if plugin != tool_name => {
+            let permission = match access {
+                Access::Read => Permission::CardsRead,
+                Access::Write => Permission::CardsWrite,
+            };
+            check_granted(&plugin, permission, state)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Whether `plugin` holds `permission`, for commands that narrow what they
+/// return instead of failing.
+pub fn is_granted(
+    plugin: &str,
+    permission: Permission,
+    state: &State<'_, Mutex<crate::app::AppData>>,
+) -> Result<bool, HollowError> {
+    Ok(granted(&get_realm_root(state)?, plugin, recovery(state))?.contains(&permission))
+}
+
+/// What a plugin needs to read a realm-relative path.
+#[derive(PartialEq, Debug)]
+enum FileRule {
+    /// Files of its own installed versions
+    Own,
+    Vault,
+    Card(String),
+    Denied,
+}
+
+fn file_rule(plugin: &str, path: &str) -> FileRule {
+    let parts: Vec<String> = Path::new(path)
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(part) => Some(part.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .collect();
+    match parts.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["plugins", owner, _, ..] if owner == plugin => FileRule::Own,
+        ["vault", _, ..] => FileRule::Vault,
+        ["main", tool, _, ..] => FileRule::Card(tool.to_string()),
+        _ => FileRule::Denied,
+    }
+}
+
+/// Plugins read their own files, vault files with `vault:read` and cards like
+/// the card commands allow. The rest of the realm is off limits.
+pub fn ensure_file_access(
+    caller: &str,
+    path: &str,
+    state: &State<'_, Mutex<crate::app::AppData>>,
+) -> Result<(), HollowError> {
+    let Caller::Plugin(plugin) = resolve_caller(caller, state)? else {
+        return Ok(());
+    };
+    match file_rule(&plugin, path) {
+        FileRule::Own => Ok(()),
+        FileRule::Vault => check_granted(&plugin, Permission::VaultRead, state),
+        FileRule::Card(tool) => ensure_card_access(caller, &tool, Access::Read, state),
+        FileRule::Denied => Err(HollowError::PermissionDenied(PathBuf::from(path))),
+    }
+}
+
+/// Hands the page of one of the app's windows its token. Only the first
+/// request after the page loads gets it, which is the app's own startup code.
+#[command]
+pub fn session_start(
+    window: WebviewWindow,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<String, HollowError> {
+    if !crate::realms::APP_WINDOWS.contains(&window.label()) {
+        return Err(HollowError::UnknownCaller);
+    }
+    state.lock().unwrap().sessions.start(window.label())
+}
+
+#[command]
+pub fn plugin_permissions(
+    name: String,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<PluginPermissions, HollowError> {
+    ensure_app(&caller, &state)?;
+    validate_path(&name)?;
+    let realm_root = get_realm_root(&state)?;
+    let recovery = recovery(&state);
+    Ok(PluginPermissions {
+        requested: requested(&realm_root, &name, recovery)?,
+        granted: granted(&realm_root, &name, recovery)?.into_iter().collect(),
+    })
+}
+
+#[command]
+pub fn plugin_set_permissions(
+    name: String,
+    granted: Vec<Permission>,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<PluginPermissions, HollowError> {
+    ensure_writable(&state)?;
+    ensure_app(&caller, &state)?;
+    validate_path(&name)?;
+    let plugin_dir = get_full_path(Path::new("plugins").join(&name), &state)?;
+    if !plugin_dir.is_dir() {
+        return Err(HollowError::NotFound(plugin_dir));
+    }
+    set_granted(&get_realm_root(&state)?, &name, &granted)?;
+    plugin_permissions(name, caller, state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plugins_read_their_own_files_vault_and_cards() {
+        assert_eq!(
+            file_rule("demo", "plugins/demo/1.0.0/index.js"),
+            FileRule::Own
+        );
+        assert_eq!(
+            file_rule("demo", "./plugins/demo/1.0.0/icon.svg"),
+            FileRule::Own
+        );
+        assert_eq!(file_rule("demo", "vault/photo.png"), FileRule::Vault);
+        assert_eq!(
+            file_rule("demo", "main/kanban/board/tasks.json"),
+            FileRule::Card("kanban".to_string())
+        );
+        assert_eq!(
+            file_rule("demo", "main/demo/card/notes.md"),
+            FileRule::Card("demo".to_string())
+        );
+    }
+
+    #[test]
+    fn the_rest_of_the_realm_is_denied() {
+        for path in [
+            "plugins/other/1.0.0/index.js",
+            "plugins/demo",
+            "plugins/demo-evil/1.0.0/index.js",
+            ".hollow/permissions.json",
+            "vault",
+            "main/kanban",
+            "main.json",
+            "",
+        ] {
+            assert_eq!(file_rule("demo", path), FileRule::Denied, "{}", path);
+        }
+    }
+
+    #[test]
+    fn tokens_name_their_caller() {
+        let mut sessions = Sessions::default();
+        let app = sessions.start("main").unwrap();
+        // Nobody else gets the app's token until the page loads again
+        assert!(sessions.start("main").is_err());
+        let selector = sessions.start("selector").unwrap();
+        let demo = sessions.issue("demo");
+        let files = sessions.issue_assets("demo");
+        assert_eq!(sessions.assets(&files), Some("demo"));
+        // A frame's asset token doesn't stand in for its caller
+        assert!(sessions.resolve(&files).is_err());
+        assert_eq!(sessions.resolve(&app).unwrap(), Caller::App);
+        assert_eq!(sessions.resolve(&selector).unwrap(), Caller::App);
+        assert_eq!(
+            sessions.resolve(&demo).unwrap(),
+            Caller::Plugin("demo".to_string())
+        );
+        assert!(sessions.resolve("demo").is_err());
+        assert!(sessions.resolve("").is_err());
+
+        sessions.reset("selector");
+        assert!(sessions.resolve(&selector).is_err());
+        assert!(sessions.resolve(&demo).is_ok());
+
+        sessions.reset("main");
+        assert!(sessions.resolve(&app).is_err());
+        assert!(sessions.resolve(&demo).is_err());
+        assert_eq!(sessions.assets(&files), None);
+        assert!(sessions.start("main").is_ok());
+    }
+}
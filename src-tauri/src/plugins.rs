@@ -1,65 +1,538 @@
-use serde_json::Value;
-use std::{fs, sync::Mutex};
-use tauri::{command, State};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{fs, path::Path, sync::Mutex};
+use tauri::{command, AppHandle, State};
+use tauri_plugin_log::log;
 
-use crate::utils::{get_full_path, validate_path};
+use crate::error::HollowError;
+use crate::permissions::{self, ensure_app, Permission};
+use crate::signing::{self, SignatureCheck, TrustedKey};
+use crate::utils::{
+    ensure_contained, ensure_writable, get_full_path, get_realm_root, recovery, validate_path,
+    Recovery,
+};
+use crate::versions;
 
-#[command]
-pub fn add_plugin(
-    plugin_name: String,
-    content: String,
-    state: State<'_, Mutex<crate::app::AppData>>,
-) -> Result<bool, String> {
-    validate_path(&plugin_name)?;
-    let plugin_file = get_full_path("plugins", &state)?
-        .join(&plugin_name)
-        .join("index.js");
+/// Typed `manifest.json`, matching the front end's `HandType`.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PluginManifest {
+    pub name: String,
+    /// Semver
+    pub version: String,
+    pub author: String,
+    #[serde(default)]
+    pub author_url: Option<String>,
+    pub description: String,
+    #[serde(default)]
+    pub cards: Vec<Value>,
+    /// Oldest Hollow version the plugin runs on
+    #[serde(default)]
+    pub min_app_version: Option<String>,
+    /// Minisign key the plugin is signed with, trusted only once the user adds it
+    #[serde(default)]
+    pub public_key: Option<String>,
+    /// Capabilities the plugin asks for, the user grants them per realm
+    #[serde(default)]
+    pub permissions: Vec<Permission>,
+    /// Fields the backend doesn't know, passed through to the front end
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Serialize)]
+pub struct PluginDiagnostic {
+    pub severity: Severity,
+    /// Manifest field the diagnostic is about
+    pub field: Option<String>,
+    pub message: String,
+}
+
+#[derive(Serialize)]
+pub struct PluginReport {
+    /// Folder under `plugins/`
+    pub folder: String,
+    /// Set whenever the manifest passed validation
+    pub manifest: Option<PluginManifest>,
+    pub diagnostics: Vec<PluginDiagnostic>,
+    /// No diagnostic is an error
+    pub valid: bool,
+    /// `index.js` carries a valid signature
+    pub signed: bool,
+    /// The signature comes from a trusted key
+    pub trusted: bool,
+    /// Id of the key that made the signature
+    pub signer: Option<String>,
+    /// Realm-relative folder of the active version, what the plugin loads from
+    pub path: Option<String>,
+}
+
+fn diagnostic(
+    severity: Severity,
+    field: Option<&str>,
+    message: impl Into<String>,
+) -> PluginDiagnostic {
+    PluginDiagnostic {
+        severity,
+        field: field.map(str::to_string),
+        message: message.into(),
+    }
+}
+
+/// Checks every field so a manifest reports all its problems at once.
+fn validate_manifest(folder: &str, value: &Value) -> Vec<PluginDiagnostic> {
+    let mut diagnostics = Vec::new();
+    let Some(fields) = value.as_object() else {
+        diagnostics.push(diagnostic(
+            Severity::Error,
+            None,
+            "Manifest is not an object",
+        ));
+        return diagnostics;
+    };
 
-    if let Some(parent) = plugin_file.parent() {
-        fs::create_dir_all(parent)
-            .map_err(|e| format!("Failed to create parent directory: {}", e))?;
+    for field in ["name", "version", "author", "description"] {
+        match fields.get(field) {
+            Some(Value::String(text)) if !text.trim().is_empty() => {}
+            Some(Value::String(_)) => diagnostics.push(diagnostic(
+                Severity::Error,
+                Some(field),
+                format!("{} is empty", field),
+            )),
+            Some(_) => diagnostics.push(diagnostic(
+                Severity::Error,
+                Some(field),
+                format!("{} must be a string", field),
+            )),
+            None => diagnostics.push(diagnostic(
+                Severity::Error,
+                Some(field),
+                format!("{} is missing", field),
+            )),
+        }
     }
 
-    fs::write(&plugin_file, &content).map_err(|e| format!("Failed to write file: {}", e))?;
-    Ok(true)
+    if let Some(name) = fields.get("name").and_then(Value::as_str) {
+        // Plugins are installed into a folder named after them in lowercase
+        if !name.trim().is_empty() && name.to_lowercase() != folder.to_lowercase() {
+            diagnostics.push(diagnostic(
+                Severity::Error,
+                Some("name"),
+                format!("{} doesn't match the plugin folder {}", name, folder),
+            ));
+        }
+    }
+    if let Some(version) = fields.get("version").and_then(Value::as_str) {
+        if let Err(e) = Version::parse(version) {
+            diagnostics.push(diagnostic(
+                Severity::Error,
+                Some("version"),
+                format!("{} is not a semver version: {}", version, e),
+            ));
+        }
+    }
+    match fields.get("minAppVersion") {
+        None | Some(Value::Null) => {}
+        Some(Value::String(required)) => match Version::parse(required) {
+            Ok(required) => {
+                let app = Version::parse(env!("CARGO_PKG_VERSION")).ok();
+                if app.is_some_and(|app| app < required) {
+                    diagnostics.push(diagnostic(
+                        Severity::Error,
+                        Some("minAppVersion"),
+                        format!("Needs Hollow {} or newer", required),
+                    ));
+                }
+            }
+            Err(e) => diagnostics.push(diagnostic(
+                Severity::Error,
+                Some("minAppVersion"),
+                format!("{} is not a semver version: {}", required, e),
+            )),
+        },
+        Some(_) => diagnostics.push(diagnostic(
+            Severity::Error,
+            Some("minAppVersion"),
+            "minAppVersion must be a string",
+        )),
+    }
+    match fields.get("cards") {
+        None | Some(Value::Array(_)) => {}
+        Some(_) => diagnostics.push(diagnostic(
+            Severity::Error,
+            Some("cards"),
+            "cards must be a list",
+        )),
+    }
+    match fields.get("publicKey") {
+        None | Some(Value::Null) => {}
+        Some(Value::String(key)) => {
+            if let Err(e) = signing::parse_key(key) {
+                diagnostics.push(diagnostic(
+                    Severity::Error,
+                    Some("publicKey"),
+                    e.to_string(),
+                ));
+            }
+        }
+        Some(_) => diagnostics.push(diagnostic(
+            Severity::Error,
+            Some("publicKey"),
+            "publicKey must be a string",
+        )),
+    }
+    match fields.get("permissions") {
+        None | Some(Value::Null) => {}
+        Some(Value::Array(permissions)) => {
+            for permission in permissions {
+                if serde_json::from_value::<Permission>(permission.clone()).is_err() {
+                    diagnostics.push(diagnostic(
+                        Severity::Error,
+                        Some("permissions"),
+                        format!("{} is not a known permission", permission),
+                    ));
+                }
+            }
+        }
+        Some(_) => diagnostics.push(diagnostic(
+            Severity::Error,
+            Some("permissions"),
+            "permissions must be a list",
+        )),
+    }
+    match fields.get("authorUrl") {
+        None | Some(Value::Null) => {}
+        Some(Value::String(_)) => {}
+        Some(_) => diagnostics.push(diagnostic(
+            Severity::Warning,
+            Some("authorUrl"),
+            "authorUrl should be a string, it's ignored",
+        )),
+    }
+    diagnostics
 }
 
-#[command]
-pub fn get_unsigned_plugins(
-    state: State<'_, Mutex<crate::app::AppData>>,
-) -> Result<Vec<Value>, String> {
-    let plugin_dir = get_full_path("plugins", &state)?;
-    let mut unsigned_tools = Vec::new();
+/// Reads and validates one plugin folder. Problems end up in the report
+/// instead of failing, so one broken plugin doesn't hide the others.
+pub fn check_plugin(path: &Path, folder: &str, trusted: &[TrustedKey]) -> PluginReport {
+    let mut diagnostics = Vec::new();
+    if !path.join("index.js").is_file() {
+        diagnostics.push(diagnostic(Severity::Error, None, "index.js is missing"));
+    }
+
+    let manifest_path = path.join("manifest.json");
+    let value = match fs::read(&manifest_path) {
+        Ok(content) => match serde_json::from_slice::<Value>(&content) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                diagnostics.push(diagnostic(
+                    Severity::Error,
+                    None,
+                    format!("manifest.json is not valid JSON: {}", e),
+                ));
+                None
+            }
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            diagnostics.push(diagnostic(
+                Severity::Error,
+                None,
+                "manifest.json is missing",
+            ));
+            None
+        }
+        Err(e) => {
+            diagnostics.push(diagnostic(
+                Severity::Error,
+                None,
+                format!("Could not read manifest.json: {}", e),
+            ));
+            None
+        }
+    };
+
+    let declared = value
+        .as_ref()
+        .and_then(|value| value.get("publicKey"))
+        .and_then(Value::as_str);
+    let signature = signing::verify_package(path, None, trusted, declared);
+    match &signature {
+        // Tampered files must not load
+        SignatureCheck::Invalid(reason) => diagnostics.push(diagnostic(
+            Severity::Error,
+            None,
+            format!("The package doesn't match its signature: {}", reason),
+        )),
+        SignatureCheck::UnknownKey => diagnostics.push(diagnostic(
+            Severity::Warning,
+            None,
+            "The package is signed with an unknown key",
+        )),
+        _ => {}
+    }
 
-    for entry in
-        fs::read_dir(&plugin_dir).map_err(|e| format!("Failed to read plugin directory: {}", e))?
-    {
-        let entry = entry.map_err(|e| format!("Failed to read directory entry: {}", e))?;
+    let mut manifest = None;
+    if let Some(mut value) = value {
+        diagnostics.extend(validate_manifest(folder, &value));
+        if !diagnostics.iter().any(|d| d.severity == Severity::Error) {
+            // Dropped with a warning above
+            if let Some(fields) = value.as_object_mut() {
+                if fields.get("authorUrl").is_some_and(|url| !url.is_string()) {
+                    fields.remove("authorUrl");
+                }
+            }
+            match serde_json::from_value::<PluginManifest>(value) {
+                Ok(parsed) => manifest = Some(parsed),
+                Err(e) => diagnostics.push(diagnostic(Severity::Error, None, e.to_string())),
+            }
+        }
+    }
+
+    PluginReport {
+        folder: folder.to_string(),
+        valid: manifest.is_some(),
+        manifest,
+        diagnostics,
+        signed: signature.signed(),
+        trusted: signature.trusted(),
+        signer: match signature {
+            SignatureCheck::Trusted(id) | SignatureCheck::Signed(id) => Some(id),
+            _ => None,
+        },
+        path: None,
+    }
+}
+
+fn failed_report(folder: &str, message: impl Into<String>) -> PluginReport {
+    PluginReport {
+        folder: folder.to_string(),
+        manifest: None,
+        diagnostics: vec![diagnostic(Severity::Error, None, message)],
+        valid: false,
+        signed: false,
+        trusted: false,
+        signer: None,
+        path: None,
+    }
+}
+
+/// Reports on every plugin folder in `plugin_dir`.
+pub fn check_plugins(
+    plugin_dir: &Path,
+    trusted: &[TrustedKey],
+    recovery: Recovery,
+) -> Result<Vec<PluginReport>, HollowError> {
+    let mut reports = Vec::new();
+    for entry in fs::read_dir(plugin_dir).map_err(|e| HollowError::io(plugin_dir, e))? {
+        let entry = entry.map_err(|e| HollowError::io(plugin_dir, e))?;
         let path = entry.path();
-        let manifest_path = path.join("manifest.json");
-
-        if manifest_path.exists() {
-            let content = fs::read_to_string(&manifest_path)
-                .map_err(|e| format!("Failed to read manifest: {}", e))?;
-            let manifest: Value = serde_json::from_str(&content)
-                .map_err(|e| format!("Failed to parse manifest JSON: {}", e))?;
-            unsigned_tools.push(manifest);
+        let folder = entry.file_name().to_string_lossy().to_string();
+        if !path.is_dir() || folder.starts_with('.') {
+            continue;
+        }
+        if let Err(e) = ensure_contained(plugin_dir, &path) {
+            log::warn!("Skipping plugin {}: {}", path.display(), e);
+            continue;
+        }
+        let report = match versions::active_dir(&path, recovery) {
+            Ok(Some(dir)) => {
+                let mut report = check_plugin(&dir, &folder, trusted);
+                let relative = dir.strip_prefix(plugin_dir).unwrap_or(&dir);
+                report.path = Some(format!(
+                    "plugins/{}",
+                    relative.to_string_lossy().replace('\\', "/")
+                ));
+                report
+            }
+            Ok(None) => match versions::active_version(&path, recovery) {
+                Ok(Some(version)) => {
+                    failed_report(&folder, format!("Active version {} is missing", version))
+                }
+                Ok(None) => failed_report(&folder, "No active version"),
+                Err(e) => failed_report(&folder, e.to_string()),
+            },
+            Err(e) => failed_report(&folder, e.to_string()),
+        };
+        if !report.valid {
+            log::warn!("Plugin {} failed validation", folder);
+        }
+        reports.push(report);
+    }
+    reports.sort_by(|a, b| a.folder.cmp(&b.folder));
+    Ok(reports)
+}
+
+/// Reads `index.js` from a version folder and checks the whole folder against
+/// its signature, so the bytes that run are the ones that were verified.
+/// Tampered packages never load, ones without a trusted signature only with
+/// `allow_untrusted`.
+pub fn load_code(
+    dir: &Path,
+    trusted: &[TrustedKey],
+    allow_untrusted: bool,
+) -> Result<String, HollowError> {
+    let code_path = dir.join("index.js");
+    let code = fs::read(&code_path).map_err(|e| HollowError::io(&code_path, e))?;
+    let declared = fs::read(dir.join("manifest.json"))
+        .ok()
+        .and_then(|content| serde_json::from_slice::<Value>(&content).ok())
+        .and_then(|value| value.get("publicKey")?.as_str().map(str::to_string));
+
+    match signing::verify_package(dir, Some(&code), trusted, declared.as_deref()) {
+        SignatureCheck::Trusted(_) => {}
+        SignatureCheck::Invalid(reason) => {
+            return Err(HollowError::InvalidInput(format!(
+                "{} doesn't match its signature: {}",
+                dir.display(),
+                reason
+            )))
+        }
+        _ if allow_untrusted => {}
+        _ => {
+            return Err(HollowError::InvalidInput(format!(
+                "{} isn't signed with a trusted key",
+                dir.display()
+            )))
         }
     }
+    String::from_utf8(code).map_err(|e| HollowError::parse(&code_path, e))
+}
+
+/// What a plugin is started with.
+#[derive(Serialize)]
+pub struct PluginCode {
+    pub code: String,
+    /// Token the plugin's commands pass as their `caller`
+    pub caller: String,
+    /// Token the plugin's frame loads its own files with,
+    /// `realm://localhost/plugin/<assets>/<file>`
+    pub assets: String,
+}
 
-    Ok(unsigned_tools)
+/// Code of a plugin's active version, verified again on every load so files
+/// changed after install don't run. Each load issues the plugin a new token.
+#[command]
+pub fn plugin_code(
+    name: String,
+    allow_untrusted: bool,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+    app: AppHandle,
+) -> Result<PluginCode, HollowError> {
+    ensure_app(&caller, &state)?;
+    validate_path(&name)?;
+    let plugin_dir = get_full_path(Path::new("plugins").join(&name), &state)?;
+    let dir = versions::active_dir(&plugin_dir, recovery(&state))?
+        .ok_or_else(|| HollowError::NotFound(plugin_dir.clone()))?;
+    ensure_contained(&plugin_dir, &dir)?;
+    let code = load_code(&dir, &signing::trusted_keys(&app)?, allow_untrusted)?;
+    let mut data = state.lock().unwrap();
+    Ok(PluginCode {
+        code,
+        caller: data.sessions.issue(&name),
+        assets: data.sessions.issue_assets(&name),
+    })
+}
+
+#[command]
+pub fn get_unsigned_plugins(
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+    app: AppHandle,
+) -> Result<Vec<PluginReport>, HollowError> {
+    ensure_app(&caller, &state)?;
+    let plugin_dir = get_full_path("plugins", &state)?;
+    check_plugins(&plugin_dir, &signing::trusted_keys(&app)?, recovery(&state))
 }
 
 #[command]
 pub fn remove_plugin(
     name: String,
+    caller: String,
     state: State<'_, Mutex<crate::app::AppData>>,
-) -> Result<bool, String> {
+) -> Result<bool, HollowError> {
+    ensure_writable(&state)?;
+    ensure_app(&caller, &state)?;
     validate_path(&name)?;
-    let plugin_dir = get_full_path("plugins", &state)?.join(&name);
+    let plugin_dir = get_full_path(Path::new("plugins").join(&name), &state)?;
     if plugin_dir.exists() {
-        fs::remove_dir_all(&plugin_dir)
-            .map_err(|e| format!("Failed to remove plugin directory: {}", e))?;
+        fs::remove_dir_all(&plugin_dir).map_err(|e| HollowError::io(&plugin_dir, e))?;
+    }
+    if let Err(e) = permissions::forget(&get_realm_root(&state)?, &name) {
+        log::warn!("Could not clear the grants of {}: {}", name, e);
     }
     Ok(true)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signing::tests::{package_dir, trusted_a, CODE, PACKAGE_SIG_A, PACKAGE_SIG_B};
+
+    #[test]
+    fn manifests_ask_for_known_permissions() {
+        let manifest = |permissions: Value| {
+            serde_json::json!({
+                "name": "demo",
+                "version": "1.0.0",
+                "author": "someone",
+                "description": "A demo",
+                "permissions": permissions,
+            })
+        };
+        let all = serde_json::json!([
+            "cards:read",
+            "cards:write",
+            "vault:read",
+            "vault:write",
+            "network",
+            "notifications"
+        ]);
+        assert!(validate_manifest("demo", &manifest(all)).is_empty());
+
+        let diagnostics = validate_manifest("demo", &manifest(serde_json::json!(["shell"])));
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].field.as_deref(), Some("permissions"));
+    }
+
+    #[test]
+    fn loads_only_trusted_code() {
+        let trusted = trusted_a();
+        let dir = package_dir(CODE, Some(PACKAGE_SIG_A));
+        assert_eq!(
+            load_code(dir.path(), &trusted, false).unwrap().as_bytes(),
+            CODE
+        );
+
+        // Signed by the key the manifest declares, but nobody trusts it
+        let dir = package_dir(CODE, Some(PACKAGE_SIG_B));
+        assert!(load_code(dir.path(), &trusted, false).is_err());
+        assert!(load_code(dir.path(), &trusted, true).is_ok());
+
+        let dir = package_dir(CODE, None);
+        assert!(load_code(dir.path(), &trusted, false).is_err());
+        assert!(load_code(dir.path(), &trusted, true).is_ok());
+    }
+
+    #[test]
+    fn tampered_packages_never_load() {
+        let dir = package_dir(b"steal()", Some(PACKAGE_SIG_A));
+        assert!(load_code(dir.path(), &trusted_a(), false).is_err());
+        assert!(load_code(dir.path(), &trusted_a(), true).is_err());
+
+        // Files besides the code are covered too
+        let dir = package_dir(CODE, Some(PACKAGE_SIG_A));
+        fs::write(dir.path().join("style/theme.css"), "body{display:none}").unwrap();
+        assert!(load_code(dir.path(), &trusted_a(), true).is_err());
+        let report = check_plugin(dir.path(), "demo", &trusted_a());
+        assert!(!report.valid && !report.signed);
+    }
+}
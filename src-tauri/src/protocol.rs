@@ -0,0 +1,270 @@
+use crate::app::AppData;
+use crate::realms::APP_WINDOWS;
+use crate::utils::{ensure_contained, percent_decode, recovery};
+use crate::versions;
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::{Component, Path, PathBuf},
+    sync::Mutex,
+};
+use tauri::{
+    http::{header, HeaderValue, Request, Response, StatusCode},
+    AppHandle, Manager, Runtime, UriSchemeContext,
+};
+use tauri_plugin_log::log;
+
+/// Scheme realm files are loaded through, `convertFileSrc(path, "realm")`
+pub const REALM_SCHEME: &str = "realm";
+/// Largest range served at once, media elements ask for the rest as they play
+const MAX_RANGE: u64 = 4 * 1024 * 1024;
+
+/// Serves files of the open realm. The asset protocol's scope can only grow
+/// within a process, this follows whichever realm is open instead, so closed
+/// realms can be entered again without a restart. Plugins reach their own
+/// files through the asset token `plugin_code` hands out.
+pub fn handle<R: Runtime>(
+    ctx: UriSchemeContext<'_, R>,
+    request: Request<Vec<u8>>,
+) -> Response<Vec<u8>> {
+    serve(ctx.app_handle(), ctx.webview_label(), &request).unwrap_or_else(|status| {
+        Response::builder()
+            .status(status)
+            .body(Vec::new())
+            .unwrap_or_default()
+    })
+}
+
+/// `bytes=start-end`, `bytes=start-` or `bytes=-suffix`, clamped to `MAX_RANGE`.
+fn parse_range(value: &str, size: u64) -> Option<(u64, u64)> {
+    if size == 0 {
+        return None;
+    }
+    let (start, end) = value.strip_prefix("bytes=")?.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => (size.checked_sub(suffix.parse().ok()?)?, size - 1),
+        (start, "") => (start.parse().ok()?, size - 1),
+        (start, end) => (start.parse().ok()?, end.parse::<u64>().ok()?.min(size - 1)),
+    };
+    if start > end || start >= size {
+        return None;
+    }
+    Some((start, end.min(start.saturating_add(MAX_RANGE - 1))))
+}
+
+/// What a `realm://` request asks for.
+#[derive(Debug, PartialEq)]
+enum Target {
+    /// `plugin/<token>/<file>`, a file of the plugin the asset token was
+    /// issued to, relative to its active version
+    Plugin { token: String, file: PathBuf },
+    /// An absolute path, see `is_public`
+    Path(PathBuf),
+}
+
+fn parse_target(path: &str) -> Result<Target, StatusCode> {
+    let path = path.trim_start_matches('/');
+    if let Some(rest) = path.strip_prefix("plugin/") {
+        let (token, file) = rest.split_once('/').ok_or(StatusCode::BAD_REQUEST)?;
+        let file = PathBuf::from(percent_decode(file));
+        if file.as_os_str().is_empty()
+            || file
+                .components()
+                .any(|c| !matches!(c, Component::Normal(_)))
+        {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        return Ok(Target::Plugin {
+            token: token.to_string(),
+            file,
+        });
+    }
+    let path = PathBuf::from(percent_decode(path));
+    if !path.is_absolute() || path.components().any(|c| c == Component::ParentDir) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    Ok(Target::Path(path))
+}
+
+/// Absolute paths only reach the vault and plugin icons, the rest of the realm
+/// goes through commands that know their caller.
+fn is_public(realm_root: &Path, path: &Path) -> bool {
+    let Ok(relative) = path.strip_prefix(realm_root) else {
+        return false;
+    };
+    let parts: Vec<_> = relative
+        .components()
+        .map(|c| match c {
+            Component::Normal(part) => part.to_str(),
+            _ => None,
+        })
+        .collect();
+    matches!(
+        parts.as_slice(),
+        [Some("vault"), Some(_)]
+            | [Some("plugins"), Some(_), Some("icon.svg")]
+            | [Some("plugins"), Some(_), Some(_), Some("icon.svg")]
+    )
+}
+
+/// Only the app's own pages may read realm files from scripts. Plugin frames
+/// have an opaque origin and send `null`.
+fn allowed_origin(webview: &str, request: &Request<Vec<u8>>) -> Option<HeaderValue> {
+    let origin = request.headers().get(header::ORIGIN)?;
+    (APP_WINDOWS.contains(&webview) && origin != "null").then(|| origin.clone())
+}
+
+fn serve<R: Runtime>(
+    app: &AppHandle<R>,
+    webview: &str,
+    request: &Request<Vec<u8>>,
+) -> Result<Response<Vec<u8>>, StatusCode> {
+    let state = app.state::<Mutex<AppData>>();
+    let realm_root = state
+        .lock()
+        .unwrap()
+        .realm_location
+        .clone()
+        .map(PathBuf::from)
+        .ok_or(StatusCode::FORBIDDEN)?;
+    let path = match parse_target(request.uri().path())? {
+        Target::Plugin { token, file } => {
+            let plugin = state
+                .lock()
+                .unwrap()
+                .sessions
+                .assets(&token)
+                .map(str::to_string)
+                .ok_or(StatusCode::FORBIDDEN)?;
+            let plugin_dir = realm_root.join("plugins").join(plugin);
+            let dir = versions::active_dir(&plugin_dir, recovery(&state))
+                .ok()
+                .flatten()
+                .ok_or(StatusCode::NOT_FOUND)?;
+            let path = dir.join(file);
+            if ensure_contained(&dir, &path).is_err() {
+                log::warn!("Refused to serve {} outside its plugin", path.display());
+                return Err(StatusCode::FORBIDDEN);
+            }
+            path
+        }
+        Target::Path(path) => {
+            if !is_public(&realm_root, &path) || ensure_contained(&realm_root, &path).is_err() {
+                log::warn!("Refused to serve {}", path.display());
+                return Err(StatusCode::FORBIDDEN);
+            }
+            path
+        }
+    };
+
+    let mut file = File::open(&path).map_err(|_| StatusCode::NOT_FOUND)?;
+    let size = file
+        .metadata()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .len();
+    let mime = mime_guess::from_path(&path).first_or_octet_stream();
+    let mut response = Response::builder()
+        .header(header::CONTENT_TYPE, mime.as_ref())
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::VARY, "Origin");
+    if let Some(origin) = allowed_origin(webview, request) {
+        response = response.header(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+    }
+
+    let range = request
+        .headers()
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok());
+    let Some(range) = range else {
+        let mut body = Vec::with_capacity(size as usize);
+        file.read_to_end(&mut body)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        return response
+            .body(body)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
+    };
+    let Some((start, end)) = parse_range(range, size) else {
+        return response
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(header::CONTENT_RANGE, format!("bytes */{}", size))
+            .body(Vec::new())
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
+    };
+    let mut body = vec![0; (end - start + 1) as usize];
+    file.seek(SeekFrom::Start(start))
+        .and_then(|_| file.read_exact(&mut body))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    response
+        .status(StatusCode::PARTIAL_CONTENT)
+        .header(
+            header::CONTENT_RANGE,
+            format!("bytes {}-{}/{}", start, end, size),
+        )
+        .body(body)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ranges_within_the_file() {
+        assert_eq!(parse_range("bytes=0-99", 1000), Some((0, 99)));
+        assert_eq!(parse_range("bytes=900-", 1000), Some((900, 999)));
+        assert_eq!(parse_range("bytes=-100", 1000), Some((900, 999)));
+        assert_eq!(parse_range("bytes=990-2000", 1000), Some((990, 999)));
+        assert_eq!(parse_range("bytes=1000-", 1000), None);
+        assert_eq!(parse_range("bytes=5-1", 1000), None);
+        assert_eq!(parse_range("bytes=0-", 0), None);
+        assert_eq!(parse_range("items=0-1", 1000), None);
+        assert_eq!(parse_range("bytes=0-", u64::MAX), Some((0, MAX_RANGE - 1)));
+    }
+
+    #[test]
+    fn plugin_files_stay_in_their_folder() {
+        assert_eq!(
+            parse_target("/plugin/abc/img/a%20b.png"),
+            Ok(Target::Plugin {
+                token: "abc".to_string(),
+                file: PathBuf::from("img/a b.png"),
+            })
+        );
+        for path in [
+            "/plugin/abc/",
+            "/plugin/abc",
+            "/plugin/abc/../../main/notes.md",
+            "/plugin/abc/%2E%2E/x",
+            "/plugin/abc/%2Fetc%2Fpasswd",
+            "/main/notes.md",
+        ] {
+            assert_eq!(parse_target(path), Err(StatusCode::BAD_REQUEST), "{}", path);
+        }
+    }
+
+    #[test]
+    fn only_the_vault_and_icons_are_public() {
+        let realm = std::env::temp_dir().join("realm");
+        for file in [
+            "vault/photo.png",
+            "plugins/demo/icon.svg",
+            "plugins/demo/1.0.0/icon.svg",
+        ] {
+            assert!(is_public(&realm, &realm.join(file)), "{}", file);
+        }
+        for file in [
+            "main/notes.md",
+            ".hollow/settings.json",
+            "plugins/demo/1.0.0/index.js",
+            "plugins/demo/1.0.0/assets/icon.svg",
+            "vault/nested/photo.png",
+            "vault",
+        ] {
+            assert!(!is_public(&realm, &realm.join(file)), "{}", file);
+        }
+        assert!(!is_public(
+            &realm,
+            &std::env::temp_dir().join("vault/a.png")
+        ));
+    }
+}
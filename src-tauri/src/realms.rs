@@ -0,0 +1,477 @@
+use crate::error::HollowError;
+use crate::permissions::ensure_app;
+use crate::utils::{read_json_recovering, write_atomic, Recovery};
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use tauri::{command, AppHandle, Manager, State, WebviewUrl, WebviewWindow, WebviewWindowBuilder};
+use tauri_plugin_log::log;
+
+pub const MAIN_WINDOW: &str = "main";
+const SELECTOR_WINDOW: &str = "selector";
+/// Windows whose pages get an app token
+pub const APP_WINDOWS: [&str; 2] = [MAIN_WINDOW, SELECTOR_WINDOW];
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RealmColors {
+    pub primary: String,
+    pub secondary: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RealmEntry {
+    pub id: String,
+    pub name: String,
+    pub location: String,
+    /// ISO 8601, like the front end's `toISOString`
+    pub created_date: String,
+    pub last_entered: String,
+    pub colors: RealmColors,
+}
+
+/// Known realms, kept in `realms.json` in the app config folder. The file
+/// predates the backend registry, so the front end's format is kept as is.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RealmRegistry {
+    /// Id of the realm entered last, None while selecting
+    pub current: Option<String>,
+    pub realms: Vec<RealmEntry>,
+    pub select_on_startup: bool,
+}
+
+impl RealmRegistry {
+    fn load(path: &Path) -> Result<Self, HollowError> {
+        Ok(read_json_recovering(path, Recovery::Restore)?.unwrap_or_default())
+    }
+
+    fn save(&self, path: &Path) -> Result<(), HollowError> {
+        let content =
+            serde_json::to_vec_pretty(self).map_err(|e| HollowError::Internal(e.to_string()))?;
+        write_atomic(path, &content)
+    }
+
+    fn entry_mut(&mut self, id: &str) -> Result<&mut RealmEntry, HollowError> {
+        self.realms
+            .iter_mut()
+            .find(|realm| realm.id == id)
+            .ok_or_else(|| HollowError::InvalidInput(format!("Unknown realm {}", id)))
+    }
+}
+
+fn now_iso() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+fn check_location(location: &str) -> Result<(), HollowError> {
+    if !Path::new(location).is_absolute() {
+        return Err(HollowError::InvalidInput(
+            "Realm locations must be absolute".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn registry_path(app: &AppHandle) -> Result<PathBuf, HollowError> {
+    app.path()
+        .app_config_dir()
+        .map(|dir| dir.join("realms.json"))
+        .map_err(|e| HollowError::Internal(format!("Could not get app config directory: {}", e)))
+}
+
+/// Loads the registry, applies `change` and saves it again.
+fn update_registry<T>(
+    path: &Path,
+    change: impl FnOnce(&mut RealmRegistry) -> Result<T, HollowError>,
+) -> Result<T, HollowError> {
+    let mut registry = RealmRegistry::load(path)?;
+    let result = change(&mut registry)?;
+    registry.save(path)?;
+    Ok(result)
+}
+
+pub fn add(path: &Path, realm: RealmEntry) -> Result<RealmEntry, HollowError> {
+    check_location(&realm.location)?;
+    if realm.id.is_empty() || realm.name.trim().is_empty() {
+        return Err(HollowError::InvalidInput(
+            "Realms need an id and a name".to_string(),
+        ));
+    }
+    update_registry(path, |registry| {
+        if registry.realms.iter().any(|r| r.id == realm.id) {
+            return Err(HollowError::InvalidInput(format!(
+                "A realm with id {} already exists",
+                realm.id
+            )));
+        }
+        if registry.realms.iter().any(|r| r.location == realm.location) {
+            return Err(HollowError::AlreadyExists(PathBuf::from(&realm.location)));
+        }
+        registry.realms.push(realm.clone());
+        Ok(realm)
+    })
+}
+
+/// Forgets the realm, its folder stays on disk.
+pub fn remove(path: &Path, id: &str) -> Result<(), HollowError> {
+    update_registry(path, |registry| {
+        let before = registry.realms.len();
+        registry.realms.retain(|realm| realm.id != id);
+        if registry.realms.len() == before {
+            return Err(HollowError::InvalidInput(format!("Unknown realm {}", id)));
+        }
+        if registry.current.as_deref() == Some(id) {
+            registry.current = None;
+        }
+        Ok(())
+    })
+}
+
+pub fn rename(path: &Path, id: &str, name: &str) -> Result<RealmEntry, HollowError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(HollowError::InvalidInput("Realms need a name".to_string()));
+    }
+    update_registry(path, |registry| {
+        let realm = registry.entry_mut(id)?;
+        realm.name = name.to_string();
+        Ok(realm.clone())
+    })
+}
+
+/// Points the realm at the folder it was moved to, which must hold a realm.
+pub fn relocate(path: &Path, id: &str, location: &str) -> Result<RealmEntry, HollowError> {
+    check_location(location)?;
+    if !Path::new(location).join(".hollow").is_dir() {
+        return Err(HollowError::InvalidInput(format!(
+            "No realm found at {}",
+            location
+        )));
+    }
+    update_registry(path, |registry| {
+        if registry
+            .realms
+            .iter()
+            .any(|r| r.id != id && r.location == location)
+        {
+            return Err(HollowError::AlreadyExists(PathBuf::from(location)));
+        }
+        let realm = registry.entry_mut(id)?;
+        realm.location = location.to_string();
+        Ok(realm.clone())
+    })
+}
+
+/// Marks the realm as current and entered now.
+pub fn enter(path: &Path, id: &str) -> Result<RealmEntry, HollowError> {
+    update_registry(path, |registry| {
+        let realm = registry.entry_mut(id)?;
+        if !Path::new(&realm.location).is_dir() {
+            return Err(HollowError::NotFound(PathBuf::from(&realm.location)));
+        }
+        realm.last_entered = now_iso();
+        let realm = realm.clone();
+        registry.current = Some(realm.id.clone());
+        Ok(realm)
+    })
+}
+
+fn show_window(app: &AppHandle, label: &str) -> Result<(), HollowError> {
+    if let Some(window) = app.get_webview_window(label) {
+        window
+            .show()
+            .and_then(|_| window.set_focus())
+            .map_err(|e| HollowError::Internal(e.to_string()))?;
+        return Ok(());
+    }
+    // Same page for both, it shows the selector while no realm is current
+    WebviewWindowBuilder::new(app, label, WebviewUrl::App("index.html".into()))
+        .title("hollow")
+        .decorations(false)
+        .inner_size(800.0, 600.0)
+        .build()
+        .map_err(|e| HollowError::Internal(e.to_string()))?;
+    Ok(())
+}
+
+fn close_window(app: &AppHandle, label: &str) {
+    if let Some(window) = app.get_webview_window(label) {
+        if let Err(e) = window.close() {
+            log::warn!("Could not close the {} window: {}", label, e);
+        }
+    }
+}
+
+#[command]
+pub fn realm_registry(
+    caller: String,
+    app: AppHandle,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<RealmRegistry, HollowError> {
+    ensure_app(&caller, &state)?;
+    RealmRegistry::load(&registry_path(&app)?)
+}
+
+#[command]
+pub fn realm_add(
+    realm: RealmEntry,
+    caller: String,
+    app: AppHandle,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<RealmEntry, HollowError> {
+    ensure_app(&caller, &state)?;
+    add(&registry_path(&app)?, realm)
+}
+
+#[command]
+pub fn realm_remove(
+    id: String,
+    caller: String,
+    app: AppHandle,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<(), HollowError> {
+    ensure_app(&caller, &state)?;
+    remove(&registry_path(&app)?, &id)
+}
+
+#[command]
+pub fn realm_rename(
+    id: String,
+    name: String,
+    caller: String,
+    app: AppHandle,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<RealmEntry, HollowError> {
+    ensure_app(&caller, &state)?;
+    rename(&registry_path(&app)?, &id, &name)
+}
+
+#[command]
+pub fn realm_relocate(
+    id: String,
+    location: String,
+    caller: String,
+    app: AppHandle,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<RealmEntry, HollowError> {
+    ensure_app(&caller, &state)?;
+    let path = registry_path(&app)?;
+    let registry = RealmRegistry::load(&path)?;
+    let open = state.lock().unwrap().realm_location.clone();
+    if registry
+        .realms
+        .iter()
+        .any(|r| r.id == id && Some(&r.location) == open.as_ref())
+    {
+        return Err(HollowError::InvalidInput(
+            "Close the realm before relocating it".to_string(),
+        ));
+    }
+    relocate(&path, &id, &location)
+}
+
+#[command]
+pub fn realm_set_colors(
+    id: String,
+    colors: RealmColors,
+    caller: String,
+    app: AppHandle,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<RealmEntry, HollowError> {
+    ensure_app(&caller, &state)?;
+    update_registry(&registry_path(&app)?, |registry| {
+        let realm = registry.entry_mut(&id)?;
+        realm.colors = colors;
+        Ok(realm.clone())
+    })
+}
+
+#[command]
+pub fn realm_set_select_on_startup(
+    value: bool,
+    caller: String,
+    app: AppHandle,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<(), HollowError> {
+    ensure_app(&caller, &state)?;
+    update_registry(&registry_path(&app)?, |registry| {
+        registry.select_on_startup = value;
+        Ok(())
+    })
+}
+
+/// Makes the realm current. From the selector window this swaps it for the
+/// main window, which opens the realm through `start_realm` once loaded.
+#[command]
+pub fn realm_enter(
+    id: String,
+    caller: String,
+    app: AppHandle,
+    window: WebviewWindow,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<RealmEntry, HollowError> {
+    ensure_app(&caller, &state)?;
+    let realm = enter(&registry_path(&app)?, &id)?;
+    if window.label() == SELECTOR_WINDOW {
+        show_window(&app, MAIN_WINDOW)?;
+        close_window(&app, SELECTOR_WINDOW);
+    }
+    Ok(realm)
+}
+
+/// Closes the open realm and swaps the main window for the selector, without
+/// restarting the app.
+#[command]
+pub fn open_realm_selector(
+    caller: String,
+    app: AppHandle,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<(), HollowError> {
+    ensure_app(&caller, &state)?;
+    crate::app::close_realm(&app, &state);
+    update_registry(&registry_path(&app)?, |registry| {
+        registry.current = None;
+        Ok(())
+    })?;
+    show_window(&app, SELECTOR_WINDOW)?;
+    close_window(&app, MAIN_WINDOW);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: &str, location: &Path) -> RealmEntry {
+        RealmEntry {
+            id: id.to_string(),
+            name: format!("Realm {}", id),
+            location: location.to_string_lossy().to_string(),
+            created_date: now_iso(),
+            last_entered: now_iso(),
+            colors: RealmColors {
+                primary: "#000".to_string(),
+                secondary: "#fff".to_string(),
+            },
+        }
+    }
+
+    fn realm_dir(root: &Path, name: &str) -> PathBuf {
+        let dir = root.join(name);
+        std::fs::create_dir_all(dir.join(".hollow")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn adds_realms_with_unique_ids_and_locations() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("realms.json");
+        let a = realm_dir(tmp.path(), "a");
+        let b = realm_dir(tmp.path(), "b");
+        add(&path, entry("1", &a)).unwrap();
+
+        assert!(matches!(
+            add(&path, entry("1", &b)),
+            Err(HollowError::InvalidInput(_))
+        ));
+        assert!(matches!(
+            add(&path, entry("2", &a)),
+            Err(HollowError::AlreadyExists(_))
+        ));
+        assert!(add(&path, entry("3", Path::new("relative"))).is_err());
+        let mut unnamed = entry("4", &b);
+        unnamed.name = "  ".to_string();
+        assert!(add(&path, unnamed).is_err());
+
+        add(&path, entry("2", &b)).unwrap();
+        let registry = RealmRegistry::load(&path).unwrap();
+        let ids: Vec<&str> = registry.realms.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids, ["1", "2"]);
+    }
+
+    #[test]
+    fn removing_the_current_realm_clears_it() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("realms.json");
+        add(&path, entry("1", &realm_dir(tmp.path(), "a"))).unwrap();
+        add(&path, entry("2", &realm_dir(tmp.path(), "b"))).unwrap();
+
+        enter(&path, "1").unwrap();
+        remove(&path, "2").unwrap();
+        assert_eq!(
+            RealmRegistry::load(&path).unwrap().current.as_deref(),
+            Some("1")
+        );
+        remove(&path, "1").unwrap();
+        let registry = RealmRegistry::load(&path).unwrap();
+        assert!(registry.current.is_none() && registry.realms.is_empty());
+        // The folder itself stays
+        assert!(tmp.path().join("a").is_dir());
+        assert!(remove(&path, "1").is_err());
+    }
+
+    #[test]
+    fn renames_with_a_trimmed_name() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("realms.json");
+        add(&path, entry("1", &realm_dir(tmp.path(), "a"))).unwrap();
+
+        assert_eq!(rename(&path, "1", "  Garden ").unwrap().name, "Garden");
+        assert!(rename(&path, "1", " ").is_err());
+        assert!(rename(&path, "missing", "Name").is_err());
+        assert_eq!(RealmRegistry::load(&path).unwrap().realms[0].name, "Garden");
+    }
+
+    #[test]
+    fn relocates_only_to_a_free_realm_folder() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("realms.json");
+        let a = realm_dir(tmp.path(), "a");
+        let b = realm_dir(tmp.path(), "b");
+        add(&path, entry("1", &a)).unwrap();
+        add(&path, entry("2", &b)).unwrap();
+
+        let empty = tmp.path().join("empty");
+        std::fs::create_dir(&empty).unwrap();
+        assert!(relocate(&path, "1", &empty.to_string_lossy()).is_err());
+        assert!(matches!(
+            relocate(&path, "1", &b.to_string_lossy()),
+            Err(HollowError::AlreadyExists(_))
+        ));
+        // Its own location is not a collision
+        relocate(&path, "1", &a.to_string_lossy()).unwrap();
+
+        let moved = realm_dir(tmp.path(), "moved");
+        let realm = relocate(&path, "1", &moved.to_string_lossy()).unwrap();
+        assert_eq!(realm.location, moved.to_string_lossy());
+        assert_eq!(
+            RealmRegistry::load(&path).unwrap().realms[0].location,
+            moved.to_string_lossy()
+        );
+    }
+
+    #[test]
+    fn enters_only_realms_that_exist() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("realms.json");
+        let a = realm_dir(tmp.path(), "a");
+        let mut realm = entry("1", &a);
+        realm.last_entered = "2000-01-01T00:00:00.000Z".to_string();
+        add(&path, realm).unwrap();
+
+        let entered = enter(&path, "1").unwrap();
+        assert_ne!(entered.last_entered, "2000-01-01T00:00:00.000Z");
+        assert_eq!(
+            RealmRegistry::load(&path).unwrap().current.as_deref(),
+            Some("1")
+        );
+
+        std::fs::remove_dir_all(&a).unwrap();
+        assert!(matches!(enter(&path, "1"), Err(HollowError::NotFound(_))));
+        assert!(enter(&path, "missing").is_err());
+    }
+}
@@ -0,0 +1,530 @@
+use crate::error::HollowError;
+use crate::permissions::{
+    ensure_app, ensure_card_access, is_granted, resolve_caller, Access, Caller, Permission,
+};
+use crate::utils::{get_realm_root, is_internal_file, is_read_only, write_replacing};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::UNIX_EPOCH,
+};
+use tauri::{command, State};
+use tauri_plugin_log::log;
+use walkdir::WalkDir;
+
+/// Bump when the stored format or tokenizer changes, older indexes are rebuilt
+const INDEX_VERSION: u32 = 1;
+/// Larger files are listed by name only
+const MAX_FILE_SIZE: u64 = 1024 * 1024;
+const MAX_TERM_LENGTH: usize = 64;
+/// Extra term frequency for words in the file name
+const NAME_WEIGHT: u32 = 5;
+const DEFAULT_LIMIT: usize = 50;
+/// Characters kept on each side of the first match
+const SNIPPET_CONTEXT: usize = 60;
+// BM25 parameters
+const K1: f32 = 1.2;
+const B: f32 = 0.75;
+
+#[derive(Serialize, Deserialize)]
+struct IndexedFile {
+    modified: u64,
+    size: u64,
+    length: u32,
+    terms: HashMap<String, u32>,
+}
+
+#[derive(Deserialize)]
+struct StoredIndex {
+    version: u32,
+    files: HashMap<String, IndexedFile>,
+}
+
+#[derive(Serialize)]
+struct StoredIndexRef<'a> {
+    version: u32,
+    files: &'a HashMap<String, IndexedFile>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SearchOptions {
+    pub tool_name: Option<String>,
+    pub card_name: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct SearchHit {
+    pub tool_name: String,
+    pub card_name: String,
+    /// Relative to the card root
+    pub path: String,
+    pub score: f32,
+    pub snippet: String,
+    /// 1-based line of the snippet, `None` when only the name matched
+    pub line: Option<usize>,
+}
+
+/// Term index of the text files under `main/`, stored in `.hollow/search`.
+/// Files are keyed `tool/card/path` and re-indexed when their size or
+/// modification time changes, so edits made outside the app are picked up too.
+#[derive(Default)]
+pub struct SearchIndex {
+    realm_root: Option<PathBuf>,
+    files: HashMap<String, IndexedFile>,
+    /// Kept in memory only for read-only realms
+    read_only: bool,
+}
+
+fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty() && t.len() <= MAX_TERM_LENGTH)
+        .map(str::to_lowercase)
+}
+
+fn split_key(key: &str) -> Option<(&str, &str, &str)> {
+    let (tool_name, rest) = key.split_once('/')?;
+    let (card_name, path) = rest.split_once('/')?;
+    Some((tool_name, card_name, path))
+}
+
+fn index_file(path: &Path, modified: u64, size: u64) -> IndexedFile {
+    let mut terms: HashMap<String, u32> = HashMap::new();
+    let mut length = 0;
+
+    if size <= MAX_FILE_SIZE {
+        // Binary files fail to decode and are only indexed by name
+        if let Ok(Ok(text)) = fs::read(path).map(String::from_utf8) {
+            for term in tokenize(&text) {
+                *terms.entry(term).or_default() += 1;
+                length += 1;
+            }
+        }
+    }
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    for term in tokenize(&name) {
+        *terms.entry(term).or_default() += NAME_WEIGHT;
+        length += 1;
+    }
+
+    IndexedFile {
+        modified,
+        size,
+        length,
+        terms,
+    }
+}
+
+/// Picks the first line mentioning a term and trims it around the match.
+fn snippet(text: &str, terms: &[String]) -> (String, Option<usize>) {
+    for (number, line) in text.lines().enumerate() {
+        let lower = line.to_lowercase();
+        let Some(byte_index) = terms.iter().filter_map(|t| lower.find(t.as_str())).min() else {
+            continue;
+        };
+        let position = lower[..byte_index].chars().count();
+        let start = position.saturating_sub(SNIPPET_CONTEXT);
+        let chars: Vec<char> = line.chars().collect();
+        let end = (position + SNIPPET_CONTEXT).min(chars.len());
+        let mut snippet: String = chars[start.min(end)..end].iter().collect();
+        if start > 0 {
+            snippet.insert(0, '…');
+        }
+        if end < chars.len() {
+            snippet.push('…');
+        }
+        return (snippet.trim().to_string(), Some(number + 1));
+    }
+    let first = text.lines().find(|l| !l.trim().is_empty()).unwrap_or("");
+    (first.chars().take(SNIPPET_CONTEXT * 2).collect(), None)
+}
+
+impl SearchIndex {
+    fn index_path(realm_root: &Path) -> PathBuf {
+        realm_root.join(".hollow").join("search").join("index.json")
+    }
+
+    fn load(&mut self, realm_root: &Path) {
+        let index_path = Self::index_path(realm_root);
+        // Only a cache, a broken index is rebuilt rather than recovered
+        let stored = fs::read(&index_path).ok().and_then(|content| {
+            serde_json::from_slice::<StoredIndex>(&content)
+                .inspect_err(|e| log::warn!("Rebuilding search index: {}", e))
+                .ok()
+        });
+        self.files = match stored {
+            Some(stored) if stored.version == INDEX_VERSION => stored.files,
+            _ => HashMap::new(),
+        };
+        self.realm_root = Some(realm_root.to_path_buf());
+    }
+
+    fn save(&self, realm_root: &Path) -> Result<(), HollowError> {
+        if self.read_only {
+            return Ok(());
+        }
+        let content = serde_json::to_vec(&StoredIndexRef {
+            version: INDEX_VERSION,
+            files: &self.files,
+        })
+        .map_err(|e| HollowError::Internal(e.to_string()))?;
+        write_replacing(&Self::index_path(realm_root), &content)
+    }
+
+    /// Brings the index in line with `main/`, only reading files whose size
+    /// or modification time changed and dropping the ones that are gone.
+    fn refresh(&mut self, realm_root: &Path) -> Result<(), HollowError> {
+        if self.realm_root.as_deref() != Some(realm_root) {
+            self.load(realm_root);
+        }
+
+        let main_dir = realm_root.join("main");
+        let mut seen = HashSet::new();
+        let mut changed = false;
+        let walker = WalkDir::new(&main_dir)
+            .min_depth(1)
+            .into_iter()
+            .filter_entry(|e| !e.file_name().to_string_lossy().starts_with('.'));
+
+        for entry in walker {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    log::warn!("Skipping while indexing: {}", e);
+                    continue;
+                }
+            };
+            let name = entry.file_name().to_string_lossy();
+            if !entry.file_type().is_file() || is_internal_file(&name) {
+                continue;
+            }
+            let Ok(relative) = entry.path().strip_prefix(&main_dir) else {
+                continue;
+            };
+            // Files directly under a tool or tool folder aren't in a card
+            if relative.components().count() < 3 {
+                continue;
+            }
+            let key = relative.to_string_lossy().replace('\\', "/");
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or_default();
+            let size = metadata.len();
+
+            let current = self
+                .files
+                .get(&key)
+                .is_some_and(|f| f.modified == modified && f.size == size);
+            if !current {
+                self.files
+                    .insert(key.clone(), index_file(entry.path(), modified, size));
+                changed = true;
+            }
+            seen.insert(key);
+        }
+
+        let before = self.files.len();
+        self.files.retain(|key, _| seen.contains(key));
+        if changed || self.files.len() != before {
+            self.save(realm_root)?;
+        }
+        Ok(())
+    }
+
+    /// Ranks files containing every query term with BM25. The last term also
+    /// matches as a prefix, so results show up while typing.
+    fn search(&self, query: &str, options: &SearchOptions) -> Vec<(String, f32)> {
+        let terms: Vec<String> = tokenize(query).collect();
+        if terms.is_empty() {
+            return Vec::new();
+        }
+        let prefix_last = !query.ends_with(char::is_whitespace);
+
+        let candidates: Vec<(&String, &IndexedFile)> = self
+            .files
+            .iter()
+            .filter(|(key, _)| {
+                let Some((tool_name, card_name, _)) = split_key(key) else {
+                    return false;
+                };
+                options.tool_name.as_deref().is_none_or(|t| t == tool_name)
+                    && options.card_name.as_deref().is_none_or(|c| c == card_name)
+            })
+            .collect();
+        if candidates.is_empty() {
+            return Vec::new();
+        }
+
+        let frequency = |file: &IndexedFile, index: usize| -> u32 {
+            let term = &terms[index];
+            if prefix_last && index == terms.len() - 1 {
+                file.terms
+                    .iter()
+                    .filter(|(t, _)| t.starts_with(term.as_str()))
+                    .map(|(_, n)| n)
+                    .sum()
+            } else {
+                file.terms.get(term).copied().unwrap_or_default()
+            }
+        };
+
+        let frequencies: Vec<Vec<u32>> = candidates
+            .iter()
+            .map(|(_, file)| (0..terms.len()).map(|i| frequency(file, i)).collect())
+            .collect();
+        let total = candidates.len() as f32;
+        let average_length = candidates.iter().map(|(_, f)| f.length as f32).sum::<f32>() / total;
+        let idf: Vec<f32> = (0..terms.len())
+            .map(|i| {
+                let containing = frequencies.iter().filter(|f| f[i] > 0).count() as f32;
+                ((total - containing + 0.5) / (containing + 0.5) + 1.0).ln()
+            })
+            .collect();
+
+        let mut hits: Vec<(String, f32)> = candidates
+            .iter()
+            .zip(&frequencies)
+            .filter(|(_, f)| f.iter().all(|n| *n > 0))
+            .map(|((key, file), f)| {
+                let norm = K1 * (1.0 - B + B * file.length as f32 / average_length.max(1.0));
+                let score = f
+                    .iter()
+                    .zip(&idf)
+                    .map(|(n, idf)| idf * (*n as f32 * (K1 + 1.0)) / (*n as f32 + norm))
+                    .sum();
+                ((*key).clone(), score)
+            })
+            .collect();
+        hits.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        hits.truncate(options.limit.unwrap_or(DEFAULT_LIMIT));
+        hits
+    }
+
+    /// The index belongs to the previous realm after a switch.
+    pub fn clear(&mut self) {
+        self.realm_root = None;
+        self.files.clear();
+    }
+}
+
+/// Searches the text of every card file, best matches first. Plugins without
+/// `cards:read` only search their own tool's cards.
+#[command]
+pub fn search_cards(
+    query: String,
+    options: Option<SearchOptions>,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+    index: State<'_, Mutex<SearchIndex>>,
+) -> Result<Vec<SearchHit>, HollowError> {
+    let realm_root = get_realm_root(&state)?;
+    let mut options = options.unwrap_or_default();
+    if let Caller::Plugin(plugin) = resolve_caller(&caller, &state)? {
+        match &options.tool_name {
+            Some(tool_name) => ensure_card_access(&caller, tool_name, Access::Read, &state)?,
+            None if !is_granted(&plugin, Permission::CardsRead, &state)? => {
+                options.tool_name = Some(plugin)
+            }
+            None => {}
+        }
+    }
+    let mut index = index.lock().unwrap();
+    index.read_only = is_read_only(&state);
+    index.refresh(&realm_root)?;
+
+    let terms: Vec<String> = tokenize(&query).collect();
+    let main_dir = realm_root.join("main");
+    Ok(index
+        .search(&query, &options)
+        .into_iter()
+        .filter_map(|(key, score)| {
+            let (tool_name, card_name, path) = split_key(&key)?;
+            let readable = index
+                .files
+                .get(&key)
+                .is_some_and(|f| f.size <= MAX_FILE_SIZE);
+            let text = match readable {
+                // Searching never writes to the cards, so no backup recovery
+                true => fs::read(main_dir.join(&key))
+                    .map(|bytes| String::from_utf8_lossy(&bytes).to_string())
+                    .unwrap_or_default(),
+                false => String::new(),
+            };
+            let (snippet, line) = snippet(&text, &terms);
+            Some(SearchHit {
+                tool_name: tool_name.to_string(),
+                card_name: card_name.to_string(),
+                path: path.to_string(),
+                score,
+                snippet,
+                line,
+            })
+        })
+        .collect())
+}
+
+/// Drops the stored index and indexes every file again.
+#[command]
+pub fn search_rebuild(
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+    index: State<'_, Mutex<SearchIndex>>,
+) -> Result<(), HollowError> {
+    ensure_app(&caller, &state)?;
+    let realm_root = get_realm_root(&state)?;
+    let mut index = index.lock().unwrap();
+    index.read_only = is_read_only(&state);
+    index.realm_root = Some(realm_root.clone());
+    index.files.clear();
+    index.refresh(&realm_root)?;
+    index.save(&realm_root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(realm_root: &Path, key: &str, contents: &str) {
+        let path = realm_root.join("main").join(key);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    fn keys(hits: &[(String, f32)]) -> Vec<&str> {
+        hits.iter().map(|(key, _)| key.as_str()).collect()
+    }
+
+    #[test]
+    fn tokenizes_into_lowercase_words() {
+        let long = "x".repeat(MAX_TERM_LENGTH + 1);
+        let text = format!("Hello, wörld! foo_bar 42 {}", long);
+        let terms: Vec<String> = tokenize(&text).collect();
+        assert_eq!(terms, ["hello", "wörld", "foo", "bar", "42"]);
+    }
+
+    #[test]
+    fn ranks_with_bm25() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "notes/a/dense.md", "apple apple apple pear");
+        write(
+            dir.path(),
+            "notes/a/sparse.md",
+            "apple pear plum kiwi fig lime date",
+        );
+        write(dir.path(), "notes/a/other.md", "pear plum");
+        let mut index = SearchIndex::default();
+        index.refresh(dir.path()).unwrap();
+
+        let hits = index.search("apple ", &SearchOptions::default());
+        assert_eq!(keys(&hits), ["notes/a/dense.md", "notes/a/sparse.md"]);
+        assert!(hits[0].1 > hits[1].1);
+        // Every term has to match
+        let hits = index.search("apple plum ", &SearchOptions::default());
+        assert_eq!(keys(&hits), ["notes/a/sparse.md"]);
+    }
+
+    #[test]
+    fn matches_the_last_term_as_a_prefix() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "notes/a/one.md", "searching the realm");
+        write(dir.path(), "notes/a/two.md", "sea breeze");
+        let mut index = SearchIndex::default();
+        index.refresh(dir.path()).unwrap();
+
+        let options = SearchOptions::default();
+        assert_eq!(index.search("sea", &options).len(), 2);
+        assert_eq!(keys(&index.search("sea ", &options)), ["notes/a/two.md"]);
+        // Only the last term is a prefix
+        assert!(index.search("sea real", &options).is_empty());
+        assert_eq!(
+            keys(&index.search("the real", &options)),
+            ["notes/a/one.md"]
+        );
+    }
+
+    #[test]
+    fn filters_by_tool_and_card() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "notes/a/x.md", "shared");
+        write(dir.path(), "notes/b/x.md", "shared");
+        write(dir.path(), "tasks/a/x.md", "shared");
+        // Not inside a card
+        write(dir.path(), "notes/loose.md", "shared");
+        let mut index = SearchIndex::default();
+        index.refresh(dir.path()).unwrap();
+
+        let search = |tool_name: Option<&str>, card_name: Option<&str>| {
+            let options = SearchOptions {
+                tool_name: tool_name.map(str::to_string),
+                card_name: card_name.map(str::to_string),
+                limit: None,
+            };
+            let hits = index.search("shared", &options);
+            keys(&hits)
+                .into_iter()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(search(None, None).len(), 3);
+        assert_eq!(
+            search(Some("notes"), None),
+            ["notes/a/x.md", "notes/b/x.md"]
+        );
+        assert_eq!(search(None, Some("a")), ["notes/a/x.md", "tasks/a/x.md"]);
+        assert_eq!(search(Some("tasks"), Some("b")), Vec::<String>::new());
+    }
+
+    #[test]
+    fn refresh_follows_changes_on_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "notes/a/x.md", "first");
+        let mut index = SearchIndex::default();
+        index.refresh(dir.path()).unwrap();
+        assert_eq!(index.search("first", &SearchOptions::default()).len(), 1);
+
+        // Written behind the index's back, the size differs so it is re-read
+        write(dir.path(), "notes/a/x.md", "second edit");
+        write(dir.path(), "notes/a/y.md", "second");
+        index.refresh(dir.path()).unwrap();
+        assert!(index.search("first", &SearchOptions::default()).is_empty());
+        assert_eq!(index.search("second", &SearchOptions::default()).len(), 2);
+
+        fs::remove_file(dir.path().join("main/notes/a/y.md")).unwrap();
+        index.refresh(dir.path()).unwrap();
+        assert_eq!(index.search("second", &SearchOptions::default()).len(), 1);
+
+        // The stored index is picked up again
+        let mut reloaded = SearchIndex::default();
+        reloaded.load(dir.path());
+        assert_eq!(reloaded.files.len(), 1);
+    }
+
+    #[test]
+    fn snippets_stay_around_the_match() {
+        let terms = vec!["needle".to_string()];
+        let line = format!("{}needle{}", "a".repeat(100), "b".repeat(100));
+        let text = format!("intro\n{}", line);
+        let (trimmed, line) = snippet(&text, &terms);
+        assert_eq!(line, Some(2));
+        assert!(trimmed.starts_with('…') && trimmed.ends_with('…'));
+        assert_eq!(trimmed.chars().count(), SNIPPET_CONTEXT * 2 + 2);
+        assert!(trimmed.contains("needle"));
+
+        let (trimmed, line) = snippet("short needle line", &terms);
+        assert_eq!((trimmed.as_str(), line), ("short needle line", Some(1)));
+
+        // Name-only matches show the start of the file
+        let (trimmed, line) = snippet("\n\nfirst line\nsecond", &terms);
+        assert_eq!((trimmed.as_str(), line), ("first line", None));
+    }
+}
@@ -0,0 +1,514 @@
+use crate::error::HollowError;
+use crate::permissions::ensure_app;
+use crate::utils::{read_json_recovering, write_atomic, Recovery};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use minisign_verify::{Error as MinisignError, PublicKey, Signature};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use tauri::{command, AppHandle, Manager, State};
+use walkdir::WalkDir;
+
+/// Sha256 of every other file in the package, as `sha256sum` lists them
+pub const DIGEST_FILE: &str = "package.sha256";
+/// Detached minisign signature of the digest, what `minisign -Sm package.sha256` writes
+pub const SIGNATURE_FILE: &str = "package.sha256.minisig";
+/// Key of the official plugins, the same one that signs app updates
+const OFFICIAL_KEY: &str = "RWQs9WXdvY06wkrjCOlcxIS6iMe4/ixLAY6TVJUHUntjk2A7TvY2Zs6W";
+const OFFICIAL_KEY_NAME: &str = "Hollow";
+
+/// A key the user trusts to sign plugins.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TrustedKey {
+    /// Minisign key id, as shown by `minisign -V`
+    pub id: String,
+    pub name: String,
+    /// Base64 line of the `.pub` file
+    pub key: String,
+    /// Set for the official key, which can't be removed
+    #[serde(default)]
+    pub builtin: bool,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+struct TrustedKeys {
+    keys: Vec<TrustedKey>,
+}
+
+impl TrustedKeys {
+    fn load(path: &Path) -> Result<Self, HollowError> {
+        Ok(read_json_recovering(path, Recovery::Restore)?.unwrap_or_default())
+    }
+
+    fn save(&self, path: &Path) -> Result<(), HollowError> {
+        let content =
+            serde_json::to_vec_pretty(self).map_err(|e| HollowError::Internal(e.to_string()))?;
+        write_atomic(path, &content)
+    }
+}
+
+/// Outcome of checking a plugin's signature.
+#[derive(PartialEq, Debug)]
+pub enum SignatureCheck {
+    Unsigned,
+    /// Valid signature from a trusted key
+    Trusted(String),
+    /// Valid signature from the key the manifest declares, which isn't trusted
+    Signed(String),
+    /// None of the known keys made the signature
+    UnknownKey,
+    /// The signature doesn't match the code, or can't be read
+    Invalid(String),
+}
+
+impl SignatureCheck {
+    pub fn signed(&self) -> bool {
+        matches!(self, SignatureCheck::Trusted(_) | SignatureCheck::Signed(_))
+    }
+
+    pub fn trusted(&self) -> bool {
+        matches!(self, SignatureCheck::Trusted(_))
+    }
+}
+
+/// Keys are given either as the base64 line or as the whole `.pub` file.
+fn key_line(key: &str) -> &str {
+    key.lines()
+        .map(str::trim)
+        .rfind(|line| !line.is_empty())
+        .unwrap_or_default()
+}
+
+/// Returns the key id along with the parsed key.
+pub fn parse_key(key: &str) -> Result<(String, PublicKey), HollowError> {
+    let line = key_line(key);
+    let invalid = || HollowError::InvalidInput("Not a minisign public key".to_string());
+    let public_key = PublicKey::from_base64(line).map_err(|_| invalid())?;
+    // "Ed" then the key id, little endian
+    let bytes = STANDARD.decode(line).map_err(|_| invalid())?;
+    let id = bytes
+        .get(2..10)
+        .ok_or_else(invalid)?
+        .iter()
+        .rev()
+        .map(|b| format!("{:02X}", b))
+        .collect();
+    Ok((id, public_key))
+}
+
+/// Checks `signature` over `content` against the trusted keys, then the
+/// key the plugin declares for itself.
+pub fn verify(
+    content: &[u8],
+    signature: Option<&str>,
+    trusted: &[TrustedKey],
+    declared: Option<&str>,
+) -> SignatureCheck {
+    let Some(signature) = signature else {
+        return SignatureCheck::Unsigned;
+    };
+    let signature = match Signature::decode(signature) {
+        Ok(signature) => signature,
+        Err(e) => return SignatureCheck::Invalid(format!("Unreadable signature: {}", e)),
+    };
+
+    let candidates = trusted
+        .iter()
+        .map(|key| (key.key.as_str(), true))
+        .chain(declared.map(|key| (key, false)));
+    for (key, is_trusted) in candidates {
+        let Ok((id, public_key)) = parse_key(key) else {
+            continue;
+        };
+        match public_key.verify(content, &signature, false) {
+            Ok(()) if is_trusted => return SignatureCheck::Trusted(id),
+            Ok(()) => return SignatureCheck::Signed(id),
+            Err(MinisignError::UnexpectedKeyId) => continue,
+            Err(e) => return SignatureCheck::Invalid(format!("{} (key {})", e, id)),
+        }
+    }
+    SignatureCheck::UnknownKey
+}
+
+fn sha256_hex(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
+}
+
+/// Sha256 of every file under `dir` by `/`-separated relative path, leaving
+/// out the digest and its signature.
+pub fn hash_files(dir: &Path) -> Result<BTreeMap<String, String>, HollowError> {
+    let mut hashes = BTreeMap::new();
+    for entry in WalkDir::new(dir).min_depth(1) {
+        let entry = entry.map_err(|e| {
+            let path = e.path().unwrap_or(dir).to_path_buf();
+            HollowError::io(path, e.into())
+        })?;
+        if entry.file_type().is_dir() {
+            continue;
+        }
+        let relative = entry
+            .path()
+            .strip_prefix(dir)
+            .unwrap_or(entry.path())
+            .to_string_lossy()
+            .replace('\\', "/");
+        if relative == DIGEST_FILE || relative == SIGNATURE_FILE {
+            continue;
+        }
+        // Links aren't followed, what they point at could change after signing
+        if !entry.file_type().is_file() {
+            return Err(HollowError::InvalidInput(format!(
+                "{} is not a regular file",
+                relative
+            )));
+        }
+        let content = fs::read(entry.path()).map_err(|e| HollowError::io(entry.path(), e))?;
+        hashes.insert(relative, sha256_hex(&content));
+    }
+    Ok(hashes)
+}
+
+fn parse_digest(text: &str) -> Result<BTreeMap<String, String>, String> {
+    let mut hashes = BTreeMap::new();
+    for line in text.lines().filter(|line| !line.trim().is_empty()) {
+        let invalid = || format!("{} has an unreadable line: {}", DIGEST_FILE, line);
+        let (hash, path) = line.split_once(' ').ok_or_else(invalid)?;
+        // `*` marks binary mode, which reads the same bytes
+        let path = path.strip_prefix([' ', '*']).ok_or_else(invalid)?;
+        let path = path.strip_prefix("./").unwrap_or(path);
+        if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        hashes.insert(path.to_string(), hash.to_ascii_lowercase());
+    }
+    Ok(hashes)
+}
+
+/// Compares the files in `dir` with the signed digest. `code` stands in for
+/// `index.js` when given.
+fn check_files(dir: &Path, digest: &[u8], code: Option<&[u8]>) -> Result<(), String> {
+    let text = std::str::from_utf8(digest).map_err(|e| format!("{}: {}", DIGEST_FILE, e))?;
+    let expected = parse_digest(text)?;
+    let mut actual = hash_files(dir).map_err(|e| e.to_string())?;
+    if let Some(code) = code {
+        actual.insert("index.js".to_string(), sha256_hex(code));
+    }
+    for (path, hash) in &expected {
+        match actual.remove(path) {
+            Some(actual) if actual == *hash => {}
+            Some(_) => return Err(format!("{} changed after signing", path)),
+            None => return Err(format!("{} is missing", path)),
+        }
+    }
+    match actual.keys().next() {
+        Some(path) => Err(format!("{} isn't covered by the signature", path)),
+        None => Ok(()),
+    }
+}
+
+/// Checks the signature over a plugin folder's digest, then every file in the
+/// folder against the digest. `code` is `index.js` as the caller read it, so
+/// the bytes that run are the ones that were checked.
+pub fn verify_package(
+    dir: &Path,
+    code: Option<&[u8]>,
+    trusted: &[TrustedKey],
+    declared: Option<&str>,
+) -> SignatureCheck {
+    let signature = match fs::read_to_string(dir.join(SIGNATURE_FILE)) {
+        Ok(signature) => signature,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return SignatureCheck::Unsigned,
+        Err(e) => return SignatureCheck::Invalid(e.to_string()),
+    };
+    let digest = match fs::read(dir.join(DIGEST_FILE)) {
+        Ok(digest) => digest,
+        Err(e) => return SignatureCheck::Invalid(format!("{}: {}", DIGEST_FILE, e)),
+    };
+    let check = verify(&digest, Some(&signature), trusted, declared);
+    if !check.signed() {
+        return check;
+    }
+    match check_files(dir, &digest, code) {
+        Ok(()) => check,
+        Err(reason) => SignatureCheck::Invalid(reason),
+    }
+}
+
+fn keys_path(app: &AppHandle) -> Result<PathBuf, HollowError> {
+    app.path()
+        .app_config_dir()
+        .map(|dir| dir.join("trusted_keys.json"))
+        .map_err(|e| HollowError::Internal(format!("Could not get app config directory: {}", e)))
+}
+
+/// The official key followed by the ones the user added.
+pub fn load_trusted(path: &Path) -> Result<Vec<TrustedKey>, HollowError> {
+    let (id, _) = parse_key(OFFICIAL_KEY)?;
+    let mut keys = vec![TrustedKey {
+        id,
+        name: OFFICIAL_KEY_NAME.to_string(),
+        key: OFFICIAL_KEY.to_string(),
+        builtin: true,
+    }];
+    keys.extend(TrustedKeys::load(path)?.keys);
+    Ok(keys)
+}
+
+pub fn trusted_keys(app: &AppHandle) -> Result<Vec<TrustedKey>, HollowError> {
+    load_trusted(&keys_path(app)?)
+}
+
+pub fn trust(path: &Path, name: &str, key: &str) -> Result<TrustedKey, HollowError> {
+    let (id, _) = parse_key(key)?;
+    if load_trusted(path)?.iter().any(|k| k.id == id) {
+        return Err(HollowError::InvalidInput(format!(
+            "Key {} is already trusted",
+            id
+        )));
+    }
+    let entry = TrustedKey {
+        id,
+        name: name.trim().to_string(),
+        key: key_line(key).to_string(),
+        builtin: false,
+    };
+    let mut stored = TrustedKeys::load(path)?;
+    stored.keys.push(entry.clone());
+    stored.save(path)?;
+    Ok(entry)
+}
+
+pub fn untrust(path: &Path, id: &str) -> Result<(), HollowError> {
+    let mut stored = TrustedKeys::load(path)?;
+    let before = stored.keys.len();
+    stored.keys.retain(|key| key.id != id);
+    if stored.keys.len() == before {
+        return Err(HollowError::InvalidInput(format!(
+            "Key {} isn't trusted or can't be removed",
+            id
+        )));
+    }
+    stored.save(path)
+}
+
+#[command]
+pub fn plugin_trusted_keys(
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+    app: AppHandle,
+) -> Result<Vec<TrustedKey>, HollowError> {
+    ensure_app(&caller, &state)?;
+    trusted_keys(&app)
+}
+
+#[command]
+pub fn plugin_trust_key(
+    name: String,
+    key: String,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+    app: AppHandle,
+) -> Result<TrustedKey, HollowError> {
+    ensure_app(&caller, &state)?;
+    trust(&keys_path(&app)?, &name, &key)
+}
+
+#[command]
+pub fn plugin_untrust_key(
+    id: String,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+    app: AppHandle,
+) -> Result<(), HollowError> {
+    ensure_app(&caller, &state)?;
+    untrust(&keys_path(&app)?, &id)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // Test keys with ids 0101... and 0202..., both signatures are over `CODE`
+    pub const CODE: &[u8] = b"export default class {}";
+    pub const KEY_A: &str = "RWQBAQEBAQEBAYqI4910CfGV/VLbLTy6XXLKZwm/HZQSG/N0iAG0D29c";
+    pub const KEY_B: &str = "RWQCAgICAgICAoE5dw6ofRdfVqNUZsNMfszLjYqRtO43ol32D1uPybOU";
+    pub const SIG_A: &str = "untrusted comment: sig
+RUQBAQEBAQEBAeM5KDEJMoe8ymgWhxAZk6w2efW0ztsLACw1hwL93cqiwWAemM6JwSl1yRH5DbwVM7f2vf4NQhA8css85zxsAQw=
+trusted comment: timestamp:0
+UJXPjHIV0o+TguNvcon6czX9flzeKG6DIaNUOPJoPBL9Hlu7CrPzSJePDKP/gkd7TuXA1cAS833ZIzqx8wh+Bg==
+";
+    pub const SIG_B: &str = "untrusted comment: sig
+RUQCAgICAgICAsIWsf9Q+qAD2oakrAOTQQC+cFERkjAmncHzsdGI/JIhBNPcntfdbxKln5SFb0giemXcgx6y+WXRbwHdh3zCpgw=
+trusted comment: timestamp:0
+iLF8SjvCsTEuW4u44NPVxZqUu39GLDR1LcmgyneVsNbfWkqT7aNmbN7W5LtAD7lDRNLlMUdvVF4vBuNC9IcHDQ==
+";
+
+    /// A package of `CODE`, this manifest and a stylesheet, the signatures
+    /// are over its digest
+    pub const PACKAGE_MANIFEST: &str =
+        r#"{"name":"demo","publicKey":"RWQCAgICAgICAoE5dw6ofRdfVqNUZsNMfszLjYqRtO43ol32D1uPybOU"}"#;
+    pub const PACKAGE_DIGEST: &str = "\
+d2332f9187c6a20a2444c315723759e5bbc8e0b964343e14028f83edcba71746  index.js
+33aa296c8d53d9d17e1f1e3baf01b37cbc6fc147dd6f33bbf567c5966b38d91d  manifest.json
+5f546eb4606b5c2b7d2a449a5cc2bbb477ed5a246c7051ce871b12f2dbfc8419  style/theme.css
+";
+    pub const PACKAGE_SIG_A: &str = "untrusted comment: sig
+RUQBAQEBAQEBAeMZ7fZyLUZJzARrWqSXuf4Pd3XHUMWvvKAEUpmxU1F0sT1AxKeC3rSqJyfmXr5+V/kx2A6dF2s5SyyVZPGy/gU=
+trusted comment: timestamp:0
+mTyv6AO0ocGj9jEH689rJh453qOFrLtvpI3Frk1U68ISsExrabufgZbzyIGF8Jtq+Zs7Wzw/94WCP2mkkXTzAg==
+";
+    pub const PACKAGE_SIG_B: &str = "untrusted comment: sig
+RUQCAgICAgICAmIJCPu4gL0odeuaZuvjGalDdaVsnr779L24MKizKmFpgLzyo7knOOFgEoOOzwXxDMYB3WAitCfY5LiQdvW8WAk=
+trusted comment: timestamp:0
+TdrnJc+MCDhAl/NVHKQ+wnKGnQCLTZT7L+e2D/ZegFVu2oQtIkRgLHRu7oxf/u7xJlwmEL7Q+mlw+0ka2DBtDA==
+";
+
+    pub fn package_dir(code: &[u8], signature: Option<&str>) -> tempfile::TempDir {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::create_dir(tmp.path().join("style")).unwrap();
+        fs::write(tmp.path().join("style/theme.css"), "a{}").unwrap();
+        fs::write(tmp.path().join("index.js"), code).unwrap();
+        fs::write(tmp.path().join("manifest.json"), PACKAGE_MANIFEST).unwrap();
+        fs::write(tmp.path().join(DIGEST_FILE), PACKAGE_DIGEST).unwrap();
+        if let Some(signature) = signature {
+            fs::write(tmp.path().join(SIGNATURE_FILE), signature).unwrap();
+        }
+        tmp
+    }
+
+    pub fn trusted_a() -> Vec<TrustedKey> {
+        vec![TrustedKey {
+            id: "0101010101010101".to_string(),
+            name: "A".to_string(),
+            key: KEY_A.to_string(),
+            builtin: false,
+        }]
+    }
+
+    #[test]
+    fn reads_key_ids() {
+        assert_eq!(parse_key(KEY_A).unwrap().0, "0101010101010101");
+        let pub_file = format!("untrusted comment: minisign public key\n{}\n", KEY_B);
+        assert_eq!(parse_key(&pub_file).unwrap().0, "0202020202020202");
+        assert!(parse_key("not a key").is_err());
+    }
+
+    #[test]
+    fn tampered_content_is_invalid() {
+        assert_eq!(
+            verify(CODE, Some(SIG_A), &trusted_a(), None),
+            SignatureCheck::Trusted("0101010101010101".to_string())
+        );
+        assert!(matches!(
+            verify(
+                b"export default class { steal() {} }",
+                Some(SIG_A),
+                &trusted_a(),
+                None
+            ),
+            SignatureCheck::Invalid(_)
+        ));
+        assert!(matches!(
+            verify(CODE, Some("junk"), &trusted_a(), None),
+            SignatureCheck::Invalid(_)
+        ));
+        assert_eq!(
+            verify(CODE, None, &trusted_a(), None),
+            SignatureCheck::Unsigned
+        );
+    }
+
+    #[test]
+    fn unknown_keys_are_reported() {
+        assert_eq!(
+            verify(CODE, Some(SIG_B), &trusted_a(), None),
+            SignatureCheck::UnknownKey
+        );
+        assert_eq!(
+            verify(CODE, Some(SIG_B), &[], Some(KEY_A)),
+            SignatureCheck::UnknownKey
+        );
+    }
+
+    #[test]
+    fn declared_keys_are_never_trusted() {
+        let check = verify(CODE, Some(SIG_B), &trusted_a(), Some(KEY_B));
+        assert_eq!(
+            check,
+            SignatureCheck::Signed("0202020202020202".to_string())
+        );
+        assert!(check.signed() && !check.trusted());
+        // A plugin declaring a trusted key still needs a signature from it
+        let check = verify(CODE, Some(SIG_A), &trusted_a(), Some(KEY_A));
+        assert!(check.trusted());
+    }
+
+    #[test]
+    fn packages_are_checked_file_by_file() {
+        let trusted = trusted_a();
+        let dir = package_dir(CODE, Some(PACKAGE_SIG_A));
+        assert!(verify_package(dir.path(), None, &trusted, None).trusted());
+        assert_eq!(
+            hash_files(dir.path()).unwrap(),
+            parse_digest(PACKAGE_DIGEST).unwrap()
+        );
+        // The code the caller is about to run counts, not what's on disk now
+        assert!(matches!(
+            verify_package(dir.path(), Some(b"steal()"), &trusted, None),
+            SignatureCheck::Invalid(_)
+        ));
+
+        let invalid = |change: &dyn Fn(&Path)| {
+            let dir = package_dir(CODE, Some(PACKAGE_SIG_A));
+            change(dir.path());
+            match verify_package(dir.path(), None, &trusted, None) {
+                SignatureCheck::Invalid(reason) => reason,
+                check => panic!("{:?}", check),
+            }
+        };
+        let reason = invalid(&|dir| fs::write(dir.join("manifest.json"), "{}").unwrap());
+        assert_eq!(reason, "manifest.json changed after signing");
+        let reason = invalid(&|dir| fs::write(dir.join("style/extra.css"), "").unwrap());
+        assert_eq!(reason, "style/extra.css isn't covered by the signature");
+        let reason = invalid(&|dir| fs::remove_file(dir.join("style/theme.css")).unwrap());
+        assert_eq!(reason, "style/theme.css is missing");
+        let reason = invalid(&|dir| fs::remove_file(dir.join(DIGEST_FILE)).unwrap());
+        assert!(reason.starts_with(DIGEST_FILE));
+        // Rewriting the digest breaks the signature instead
+        invalid(&|dir| fs::write(dir.join(DIGEST_FILE), "").unwrap());
+    }
+
+    #[test]
+    fn unsigned_and_unknown_packages_are_reported() {
+        let dir = package_dir(CODE, None);
+        assert_eq!(
+            verify_package(dir.path(), None, &trusted_a(), None),
+            SignatureCheck::Unsigned
+        );
+        let dir = package_dir(CODE, Some(PACKAGE_SIG_B));
+        assert_eq!(
+            verify_package(dir.path(), None, &trusted_a(), None),
+            SignatureCheck::UnknownKey
+        );
+        assert_eq!(
+            verify_package(dir.path(), None, &trusted_a(), Some(KEY_B)),
+            SignatureCheck::Signed("0202020202020202".to_string())
+        );
+    }
+
+    #[test]
+    fn reads_sha256sum_output() {
+        let hash = "D2332F9187C6A20A2444C315723759E5BBC8E0B964343E14028F83EDCBA71746";
+        let digest = parse_digest(&format!("{} *./index.js\n\n", hash)).unwrap();
+        assert_eq!(digest["index.js"], hash.to_ascii_lowercase());
+        assert!(parse_digest("abc  index.js").is_err());
+        assert!(parse_digest(&format!("{}index.js", hash)).is_err());
+    }
+}
@@ -0,0 +1,334 @@
+use crate::error::HollowError;
+use crate::permissions::ensure_app;
+use crate::utils::{
+    ensure_contained, ensure_writable, get_realm_root, is_read_only, read_json_recovering,
+    recovery, validate_path, write_atomic, Recovery,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tauri::{command, State};
+use tauri_plugin_log::log;
+use uuid::Uuid;
+
+const DEFAULT_RETENTION_DAYS: u64 = 30;
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TrashItem {
+    pub id: String,
+    pub name: String,
+    /// Realm-relative path the item was removed from, using `/`
+    pub origin: String,
+    pub is_dir: bool,
+    /// Milliseconds since the unix epoch
+    pub deleted_at: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TrashIndex {
+    /// Items older than this are purged, 0 keeps them until emptied by hand
+    retention_days: u64,
+    items: Vec<TrashItem>,
+}
+
+impl Default for TrashIndex {
+    fn default() -> Self {
+        TrashIndex {
+            retention_days: DEFAULT_RETENTION_DAYS,
+            items: Vec::new(),
+        }
+    }
+}
+
+/// In-realm trash under `.hollow/trash`. Each item is moved to `items/<id>/<name>`
+/// and described in `index.json`.
+struct Trash {
+    realm_root: PathBuf,
+    dir: PathBuf,
+    index: TrashIndex,
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+impl Trash {
+    fn open(realm_root: PathBuf, recovery: Recovery) -> Result<Self, HollowError> {
+        let dir = realm_root.join(".hollow").join("trash");
+        let index = read_json_recovering(&dir.join("index.json"), recovery)?.unwrap_or_default();
+        Ok(Trash {
+            realm_root,
+            dir,
+            index,
+        })
+    }
+
+    fn save(&self) -> Result<(), HollowError> {
+        let content = serde_json::to_vec_pretty(&self.index)
+            .map_err(|e| HollowError::Internal(e.to_string()))?;
+        write_atomic(&self.dir.join("index.json"), &content)
+    }
+
+    fn item_dir(&self, id: &str) -> PathBuf {
+        self.dir.join("items").join(id)
+    }
+
+    fn add(&mut self, path: &Path) -> Result<TrashItem, HollowError> {
+        let origin = path
+            .strip_prefix(&self.realm_root)
+            .map_err(|_| HollowError::InvalidInput("Path is not inside the realm".to_string()))?
+            .to_string_lossy()
+            .replace('\\', "/");
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let item = TrashItem {
+            id: Uuid::new_v4().to_string(),
+            name: name.clone(),
+            origin,
+            is_dir: path.is_dir(),
+            deleted_at: now_millis(),
+        };
+        let item_dir = self.item_dir(&item.id);
+        fs::create_dir_all(&item_dir).map_err(|e| HollowError::io(&item_dir, e))?;
+        // The trash lives in the realm, so this is a rename on the same volume
+        fs::rename(path, item_dir.join(&name)).map_err(|e| HollowError::io(path, e))?;
+
+        self.index.items.push(item.clone());
+        self.save()?;
+        Ok(item)
+    }
+
+    fn remove_item(&mut self, id: &str) -> Result<(), HollowError> {
+        validate_path(id)?;
+        if !self.index.items.iter().any(|item| item.id == id) {
+            return Err(HollowError::InvalidInput(format!(
+                "Unknown trash item {}",
+                id
+            )));
+        }
+        let item_dir = self.item_dir(id);
+        if item_dir.exists() {
+            fs::remove_dir_all(&item_dir).map_err(|e| HollowError::io(&item_dir, e))?;
+        }
+        self.index.items.retain(|item| item.id != id);
+        Ok(())
+    }
+
+    /// Puts an item back where it was removed from, failing if that path is taken again.
+    fn restore(&mut self, id: &str) -> Result<TrashItem, HollowError> {
+        let item = self
+            .index
+            .items
+            .iter()
+            .find(|item| item.id == id)
+            .cloned()
+            .ok_or_else(|| HollowError::InvalidInput(format!("Unknown trash item {}", id)))?;
+
+        validate_path(&item.origin)?;
+        validate_path(&item.name)?;
+        let destination = self.realm_root.join(&item.origin);
+        ensure_contained(&self.realm_root, &destination)?;
+        if destination.exists() {
+            return Err(HollowError::AlreadyExists(destination));
+        }
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent).map_err(|e| HollowError::io(parent, e))?;
+        }
+
+        let source = self.item_dir(&item.id).join(&item.name);
+        fs::rename(&source, &destination).map_err(|e| HollowError::io(&source, e))?;
+        self.remove_item(&item.id)?;
+        self.save()?;
+        Ok(item)
+    }
+
+    fn purge_expired(&mut self) -> Result<(), HollowError> {
+        if self.index.retention_days == 0 {
+            return Ok(());
+        }
+        let cutoff = now_millis().saturating_sub(self.index.retention_days * DAY_MS);
+        let expired: Vec<String> = self
+            .index
+            .items
+            .iter()
+            .filter(|item| item.deleted_at < cutoff)
+            .map(|item| item.id.clone())
+            .collect();
+        if expired.is_empty() {
+            return Ok(());
+        }
+        for id in &expired {
+            self.remove_item(id)?;
+        }
+        log::info!("Purged {} expired trash items", expired.len());
+        self.save()
+    }
+}
+
+/// Moves a realm path into the trash instead of deleting it.
+pub fn move_to_trash(realm_root: PathBuf, path: &Path) -> Result<TrashItem, HollowError> {
+    let mut trash = Trash::open(realm_root, Recovery::Restore)?;
+    let item = trash.add(path)?;
+    if let Err(e) = trash.purge_expired() {
+        log::warn!("Could not purge trash: {}", e);
+    }
+    log::info!("Moved to trash: {}", item.origin);
+    Ok(item)
+}
+
+pub fn purge_expired(realm_root: PathBuf) -> Result<(), HollowError> {
+    Trash::open(realm_root, Recovery::Restore)?.purge_expired()
+}
+
+#[command]
+pub fn trash_list(
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<Vec<TrashItem>, HollowError> {
+    ensure_app(&caller, &state)?;
+    let mut trash = Trash::open(get_realm_root(&state)?, recovery(&state))?;
+    // Expired items stay listed until the realm is writable again
+    if !is_read_only(&state) {
+        trash.purge_expired()?;
+    }
+    Ok(trash.index.items)
+}
+
+/// Puts an item back where it was removed from.
+#[command]
+pub fn trash_restore(
+    id: String,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<TrashItem, HollowError> {
+    ensure_writable(&state)?;
+    ensure_app(&caller, &state)?;
+    let item = Trash::open(get_realm_root(&state)?, recovery(&state))?.restore(&id)?;
+    log::info!("Restored from trash: {}", item.origin);
+    Ok(item)
+}
+
+/// Permanently deletes the given items, or everything when `ids` is unset.
+#[command]
+pub fn trash_empty(
+    ids: Option<Vec<String>>,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<(), HollowError> {
+    ensure_writable(&state)?;
+    ensure_app(&caller, &state)?;
+    let mut trash = Trash::open(get_realm_root(&state)?, recovery(&state))?;
+    let ids = ids.unwrap_or_else(|| trash.index.items.iter().map(|i| i.id.clone()).collect());
+    for id in ids {
+        trash.remove_item(&id)?;
+    }
+    trash.save()
+}
+
+#[command]
+pub fn trash_set_retention(
+    days: u64,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<(), HollowError> {
+    ensure_writable(&state)?;
+    ensure_app(&caller, &state)?;
+    let mut trash = Trash::open(get_realm_root(&state)?, recovery(&state))?;
+    trash.index.retention_days = days;
+    trash.save()?;
+    trash.purge_expired()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn realm_with(files: &[&str]) -> tempfile::TempDir {
+        let tmp = tempfile::tempdir().unwrap();
+        for file in files {
+            let path = tmp.path().join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, file).unwrap();
+        }
+        tmp
+    }
+
+    #[test]
+    fn restores_items_where_they_were() {
+        let tmp = realm_with(&["main/notes/a/x.md"]);
+        let path = tmp.path().join("main/notes/a/x.md");
+        let item = move_to_trash(tmp.path().to_path_buf(), &path).unwrap();
+        assert_eq!(item.origin, "main/notes/a/x.md");
+        assert!(!path.exists());
+
+        // A file that took its place since is never overwritten
+        fs::write(&path, "new").unwrap();
+        let mut trash = Trash::open(tmp.path().to_path_buf(), Recovery::Restore).unwrap();
+        assert!(matches!(
+            trash.restore(&item.id),
+            Err(HollowError::AlreadyExists(_))
+        ));
+        assert_eq!(trash.index.items.len(), 1);
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_dir(path.parent().unwrap()).unwrap();
+        trash.restore(&item.id).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "main/notes/a/x.md");
+        assert!(!trash.item_dir(&item.id).exists());
+        let trash = Trash::open(tmp.path().to_path_buf(), Recovery::Restore).unwrap();
+        assert!(trash.index.items.is_empty());
+    }
+
+    #[test]
+    fn purges_items_past_retention() {
+        let tmp = realm_with(&["main/a.md", "main/b.md"]);
+        let old = move_to_trash(tmp.path().to_path_buf(), &tmp.path().join("main/a.md")).unwrap();
+        let recent =
+            move_to_trash(tmp.path().to_path_buf(), &tmp.path().join("main/b.md")).unwrap();
+
+        let mut trash = Trash::open(tmp.path().to_path_buf(), Recovery::Restore).unwrap();
+        trash.index.items[0].deleted_at = now_millis() - 31 * DAY_MS;
+        trash.index.retention_days = 0;
+        trash.save().unwrap();
+        // 0 keeps everything until emptied by hand
+        purge_expired(tmp.path().to_path_buf()).unwrap();
+        let mut trash = Trash::open(tmp.path().to_path_buf(), Recovery::Restore).unwrap();
+        assert_eq!(trash.index.items.len(), 2);
+
+        trash.index.retention_days = 30;
+        trash.save().unwrap();
+        purge_expired(tmp.path().to_path_buf()).unwrap();
+        let trash = Trash::open(tmp.path().to_path_buf(), Recovery::Restore).unwrap();
+        let ids: Vec<&str> = trash.index.items.iter().map(|i| i.id.as_str()).collect();
+        assert_eq!(ids, vec![recent.id.as_str()]);
+        assert!(!trash.item_dir(&old.id).exists());
+        assert!(trash.item_dir(&recent.id).exists());
+    }
+
+    #[test]
+    fn recovers_a_torn_index() {
+        let tmp = realm_with(&["main/a.md", "main/b.md"]);
+        move_to_trash(tmp.path().to_path_buf(), &tmp.path().join("main/a.md")).unwrap();
+        move_to_trash(tmp.path().to_path_buf(), &tmp.path().join("main/b.md")).unwrap();
+        let index = tmp.path().join(".hollow/trash/index.json");
+        fs::write(&index, "{\"retention_days\":").unwrap();
+
+        let trash = Trash::open(tmp.path().to_path_buf(), Recovery::InMemory).unwrap();
+        assert_eq!(trash.index.items.len(), 1);
+        assert_eq!(fs::read(&index).unwrap(), b"{\"retention_days\":");
+        let trash = Trash::open(tmp.path().to_path_buf(), Recovery::Restore).unwrap();
+        assert_eq!(trash.index.items[0].name, "a.md");
+        assert!(fs::read_to_string(&index).unwrap().contains("a.md"));
+    }
+}
@@ -1,35 +1,193 @@
 use crate::app::AppData;
-use std::{fs, path::PathBuf, sync::Mutex};
+use crate::error::HollowError;
+use crate::trash::move_to_trash;
+use serde::de::DeserializeOwned;
+use std::{
+    fs,
+    io::{ErrorKind, Write},
+    path::{Component, Path, PathBuf},
+    sync::Mutex,
+};
 use tauri::{command, AppHandle, Manager, State};
 use tauri_plugin_log::log::{self};
+use unicode_normalization::UnicodeNormalization;
+use uuid::Uuid;
 
-pub fn validate_path(path: &str) -> Result<(), String> {
-    let p = std::path::Path::new(path);
+/// Namespaced so a user's own `.bak` files aren't mistaken for ours
+const BACKUP_SUFFIX: &str = ".hollow-bak";
+const TEMP_SUFFIX: &str = ".tmp";
+
+/// What the `*_recovering` reads do with a backup that replaces a broken file.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Recovery {
+    /// Put it back in place of the broken file
+    Restore,
+    /// Only return its contents, read-only realms are never written
+    InMemory,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PathError {
+    NotRelative,
+    Traversal,
+    OutsideRealm(PathBuf),
+    Unresolvable(PathBuf),
+}
+
+impl std::fmt::Display for PathError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PathError::NotRelative => write!(f, "Path must be relative"),
+            PathError::Traversal => write!(f, "Path traversal is not allowed"),
+            PathError::OutsideRealm(path) => {
+                write!(f, "Path resolves outside the realm: {}", path.display())
+            }
+            PathError::Unresolvable(path) => {
+                write!(f, "Path could not be resolved: {}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for PathError {}
+
+/// Lookalikes such as `\u{2025}` or fullwidth dots normalize (NFKC) to `..`, and some
+/// platforms and sync tools apply that normalization, so judge the normalized form.
+fn is_traversal_component(component: &str) -> bool {
+    let normalized: String = component.nfkc().collect();
+    if normalized.contains(['/', '\\']) {
+        return true;
+    }
+    // Windows ignores trailing dots and spaces, so `.. ` is `..` there
+    let trimmed = normalized.trim_end_matches(['.', ' ']);
+    trimmed.is_empty() && normalized.starts_with("..")
+}
+
+pub fn validate_path(path: &str) -> Result<(), PathError> {
+    let p = Path::new(path);
     if p.is_absolute() {
-        return Err("Path must be relative".to_string());
+        return Err(PathError::NotRelative);
     }
     for component in p.components() {
-        if matches!(component, std::path::Component::ParentDir) {
-            return Err("Path traversal is not allowed".to_string());
+        match component {
+            // `\foo` isn't absolute on Windows but still jumps to the drive root
+            Component::Prefix(_) | Component::RootDir => return Err(PathError::NotRelative),
+            Component::ParentDir => return Err(PathError::Traversal),
+            Component::Normal(name) if is_traversal_component(&name.to_string_lossy()) => {
+                return Err(PathError::Traversal)
+            }
+            _ => {}
         }
     }
     Ok(())
 }
 
-pub fn get_full_path(path: &str, state: &State<'_, Mutex<AppData>>) -> Result<PathBuf, String> {
+/// Resolves symlinks and junctions in the existing part of `path` and checks that the
+/// result is still under `root`. Parts that don't exist yet can't be links, and
+/// `validate_path` already ruled out `..` in them.
+pub fn ensure_contained(root: &Path, path: &Path) -> Result<(), PathError> {
+    let Ok(root) = root.canonicalize() else {
+        // Nothing exists under a missing root, so nothing can point out of it
+        return Ok(());
+    };
+
+    let mut existing = path;
+    let mut missing = Vec::new();
+    while fs::symlink_metadata(existing).is_err() {
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name);
+                existing = parent;
+            }
+            _ => return Err(PathError::Unresolvable(path.to_path_buf())),
+        }
+    }
+
+    // Fails for dangling links, whose target we can't vouch for
+    let mut resolved = existing
+        .canonicalize()
+        .map_err(|_| PathError::Unresolvable(path.to_path_buf()))?;
+    for name in missing.into_iter().rev() {
+        resolved.push(name);
+    }
+
+    if resolved.starts_with(&root) {
+        Ok(())
+    } else {
+        Err(PathError::OutsideRealm(path.to_path_buf()))
+    }
+}
+
+/// Decodes `%XX` escapes, leaving malformed ones as written.
+pub fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = text
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match hex {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+pub fn get_realm_root(state: &State<'_, Mutex<AppData>>) -> Result<PathBuf, HollowError> {
     let app_data = state.lock().unwrap();
     let base = app_data
         .realm_location
         .as_ref()
-        .ok_or("Realm location not initialized yet")?;
-    Ok(PathBuf::from(base).join(path))
+        .ok_or(HollowError::RealmNotInitialized)?;
+    Ok(PathBuf::from(base))
+}
+
+pub fn is_read_only(state: &State<'_, Mutex<AppData>>) -> bool {
+    state.lock().unwrap().read_only
+}
+
+pub fn recovery(state: &State<'_, Mutex<AppData>>) -> Recovery {
+    match is_read_only(state) {
+        true => Recovery::InMemory,
+        false => Recovery::Restore,
+    }
+}
+
+/// Guard for every command that changes the realm.
+pub fn ensure_writable(state: &State<'_, Mutex<AppData>>) -> Result<(), HollowError> {
+    match is_read_only(state) {
+        true => Err(HollowError::ReadOnly),
+        false => Ok(()),
+    }
+}
+
+pub fn get_full_path(
+    path: impl AsRef<Path>,
+    state: &State<'_, Mutex<AppData>>,
+) -> Result<PathBuf, HollowError> {
+    let root = get_realm_root(state)?;
+    let full_path = root.join(path);
+    ensure_contained(&root, &full_path)?;
+    Ok(full_path)
 }
 
-pub fn create_dir_internal(path: &str, state: &State<'_, Mutex<AppData>>) -> Result<(), String> {
+pub fn create_dir_internal(
+    path: &str,
+    state: &State<'_, Mutex<AppData>>,
+) -> Result<(), HollowError> {
     validate_path(path)?;
     let full_path = get_full_path(path, state)?;
     if !full_path.exists() {
-        fs::create_dir_all(&full_path).map_err(|e| e.to_string())?;
+        fs::create_dir_all(&full_path).map_err(|e| HollowError::io(&full_path, e))?;
         log::info!("Directory created: {}", full_path.display());
     }
     // else {
@@ -38,6 +196,153 @@ pub fn create_dir_internal(path: &str, state: &State<'_, Mutex<AppData>>) -> Res
     Ok(())
 }
 
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(BACKUP_SUFFIX);
+    path.with_file_name(name)
+}
+
+/// Backups and in-flight temp files live next to the real file and should not
+/// show up in listings.
+pub fn is_internal_file(name: &str) -> bool {
+    name.ends_with(BACKUP_SUFFIX) || (name.starts_with('.') && name.ends_with(TEMP_SUFFIX))
+}
+
+/// Hidden sibling of `path` for staging writes, filtered out by `is_internal_file`.
+pub fn temp_path(path: &Path) -> PathBuf {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.{}{}", file_name, Uuid::new_v4(), TEMP_SUFFIX))
+}
+
+fn replace_file(path: &Path, contents: &[u8], keep_backup: bool) -> Result<(), HollowError> {
+    let parent = path
+        .parent()
+        .ok_or_else(|| HollowError::InvalidInput("Path has no parent directory".to_string()))?;
+    fs::create_dir_all(parent).map_err(|e| HollowError::io(parent, e))?;
+
+    let temp_path = temp_path(path);
+
+    let result = (|| -> std::io::Result<()> {
+        let mut file = fs::File::create(&temp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+
+        if keep_backup && path.is_file() {
+            let backup = backup_path(path);
+            fs::copy(path, &backup)?;
+            fs::File::open(&backup)?.sync_all()?;
+        }
+
+        fs::rename(&temp_path, path)?;
+
+        // Persist the rename itself, directories can't be opened this way on Windows
+        #[cfg(unix)]
+        fs::File::open(parent)?.sync_all()?;
+        Ok(())
+    })();
+
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temp_path);
+        return Err(HollowError::io(path, e));
+    }
+    Ok(())
+}
+
+/// Writes through a synced temp file and a rename so a crash never leaves a
+/// truncated file behind. The previous version is kept as `<name>.hollow-bak`.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), HollowError> {
+    replace_file(path, contents, true)
+}
+
+/// Like `write_atomic` without keeping a backup, for caches that are rebuilt
+/// rather than recovered.
+pub fn write_replacing(path: &Path, contents: &[u8]) -> Result<(), HollowError> {
+    replace_file(path, contents, false)
+}
+
+/// Puts the `<name>.hollow-bak` copy back in place of `path`, leaving the backup as is.
+pub fn restore_backup(path: &Path) -> Result<Vec<u8>, HollowError> {
+    let backup = backup_path(path);
+    let bytes = fs::read(&backup).map_err(|e| HollowError::io(&backup, e))?;
+    replace_file(path, &bytes, false)?;
+    Ok(bytes)
+}
+
+fn recover_from_backup(
+    path: &Path,
+    error: std::io::Error,
+    recovery: Recovery,
+) -> Result<Vec<u8>, HollowError> {
+    let backup = backup_path(path);
+    if !backup.is_file() {
+        return Err(HollowError::io(path, error));
+    }
+    log::warn!(
+        "Recovering {} from backup after read failure: {}",
+        path.display(),
+        error
+    );
+    match recovery {
+        Recovery::Restore => restore_backup(path),
+        Recovery::InMemory => fs::read(&backup).map_err(|e| HollowError::io(&backup, e)),
+    }
+}
+
+/// Falls back to the backup when `path` can't be read. A missing file was
+/// deleted rather than corrupted, so it stays missing.
+pub fn read_recovering(path: &Path, recovery: Recovery) -> Result<Vec<u8>, HollowError> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(bytes),
+        Err(e) if e.kind() == ErrorKind::NotFound => Err(HollowError::NotFound(path.to_path_buf())),
+        Err(e) => recover_from_backup(path, e, recovery),
+    }
+}
+
+/// Reads a JSON store, `None` when it doesn't exist. Content that doesn't
+/// parse counts as corrupt and is replaced by the backup if that parses.
+pub fn read_json_recovering<T: DeserializeOwned>(
+    path: &Path,
+    recovery: Recovery,
+) -> Result<Option<T>, HollowError> {
+    let content = match read_recovering(path, recovery) {
+        Ok(content) => content,
+        Err(HollowError::NotFound(_)) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let error = match serde_json::from_slice(&content) {
+        Ok(value) => return Ok(Some(value)),
+        Err(e) => e,
+    };
+    let backup = fs::read(backup_path(path)).ok();
+    let Some((backup, value)) = backup.and_then(|backup| {
+        let value = serde_json::from_slice(&backup).ok()?;
+        Some((backup, value))
+    }) else {
+        return Err(HollowError::parse(path, error));
+    };
+    log::warn!(
+        "Recovering {} from backup after parse failure: {}",
+        path.display(),
+        error
+    );
+    if recovery == Recovery::Restore {
+        replace_file(path, &backup, false)?;
+    }
+    Ok(Some(value))
+}
+
+/// Like `read_recovering`, but invalid UTF-8 also counts as a corrupt file.
+pub fn read_to_string_recovering(path: &Path, recovery: Recovery) -> Result<String, HollowError> {
+    match String::from_utf8(read_recovering(path, recovery)?) {
+        Ok(text) => Ok(text),
+        Err(e) => {
+            let error = std::io::Error::new(ErrorKind::InvalidData, e);
+            String::from_utf8(recover_from_backup(path, error, recovery)?)
+                .map_err(|e| HollowError::parse(path, e))
+        }
+    }
+}
+
 // Commands
 #[command]
 pub fn first_launch(app: AppHandle) {
@@ -54,7 +359,13 @@ pub fn first_launch(app: AppHandle) {
     }
 }
 #[command]
-pub fn create_dir(paths: Vec<String>, state: State<'_, Mutex<AppData>>) -> Result<(), String> {
+pub fn create_dir(
+    paths: Vec<String>,
+    caller: String,
+    state: State<'_, Mutex<AppData>>,
+) -> Result<(), HollowError> {
+    ensure_writable(&state)?;
+    crate::permissions::ensure_app(&caller, &state)?;
     for path in paths {
         create_dir_internal(&path, &state)?
     }
@@ -62,29 +373,178 @@ pub fn create_dir(paths: Vec<String>, state: State<'_, Mutex<AppData>>) -> Resul
 }
 
 #[command]
-pub fn remove_dir(path: String, state: State<'_, Mutex<AppData>>) -> Result<(), String> {
+pub fn remove_dir(
+    path: String,
+    caller: String,
+    state: State<'_, Mutex<AppData>>,
+) -> Result<(), HollowError> {
+    ensure_writable(&state)?;
+    crate::permissions::ensure_app(&caller, &state)?;
     validate_path(&path)?;
     let full_path = get_full_path(&path, &state)?;
     if full_path.exists() {
-        fs::remove_dir_all(&full_path).map_err(|e| e.to_string())?;
+        move_to_trash(get_realm_root(&state)?, &full_path)?;
         log::info!("Directory removed: {}", full_path.display());
     }
     Ok(())
 }
 
 #[command]
-pub fn path_exists(path: String, state: State<'_, Mutex<AppData>>) -> Result<bool, String> {
+pub fn path_exists(
+    path: String,
+    caller: String,
+    state: State<'_, Mutex<AppData>>,
+) -> Result<bool, HollowError> {
+    crate::permissions::ensure_app(&caller, &state)?;
     validate_path(&path)?;
     let full_path = get_full_path(&path, &state)?;
     Ok(full_path.exists())
 }
 
+/// Plugins calling it may only read what their grants allow.
 #[command]
-pub fn read_file(path: String, state: State<'_, Mutex<AppData>>) -> Result<String, String> {
+pub fn read_file(
+    path: String,
+    caller: String,
+    state: State<'_, Mutex<AppData>>,
+) -> Result<String, HollowError> {
     validate_path(&path)?;
+    crate::permissions::ensure_file_access(&caller, &path, &state)?;
     let file_path = get_full_path(&path, &state)?;
-    if !file_path.exists() {
-        return Err("File does not exist".to_string());
+    fs::read_to_string(&file_path).map_err(|e| HollowError::io(&file_path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_absolute_and_parent_paths() {
+        assert_eq!(validate_path("/etc/passwd"), Err(PathError::NotRelative));
+        assert_eq!(validate_path("notes/../../x"), Err(PathError::Traversal));
+        assert_eq!(validate_path(".."), Err(PathError::Traversal));
+        assert!(validate_path("notes/today.md").is_ok());
+        assert!(validate_path("./notes/...draft.md").is_ok());
+    }
+
+    #[test]
+    fn rejects_unicode_normalization_escapes() {
+        // two dot leader and fullwidth full stops both normalize to `..`
+        assert_eq!(validate_path("\u{2025}/secret"), Err(PathError::Traversal));
+        assert_eq!(
+            validate_path("\u{FF0E}\u{FF0E}/secret"),
+            Err(PathError::Traversal)
+        );
+        // fullwidth solidus normalizes to a separator
+        assert_eq!(
+            validate_path("a\u{FF0F}..\u{FF0F}b"),
+            Err(PathError::Traversal)
+        );
+        assert_eq!(validate_path(".. /secret"), Err(PathError::Traversal));
+        assert!(validate_path("café/ノート.md").is_ok());
+    }
+
+    #[test]
+    fn deleted_files_stay_deleted() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("note.md");
+        write_atomic(&path, b"one").unwrap();
+        write_atomic(&path, b"two").unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            read_recovering(&path, Recovery::Restore),
+            Err(HollowError::NotFound(_))
+        ));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn recovers_json_that_fails_to_parse() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("store.json");
+        write_atomic(&path, br#"{"a":1}"#).unwrap();
+        write_atomic(&path, br#"{"a":2}"#).unwrap();
+        fs::write(&path, b"{\"a\":").unwrap();
+        let value: serde_json::Value = read_json_recovering(&path, Recovery::InMemory)
+            .unwrap()
+            .unwrap();
+        assert_eq!(value["a"], 1);
+        assert_eq!(fs::read(&path).unwrap(), b"{\"a\":");
+        let value: serde_json::Value = read_json_recovering(&path, Recovery::Restore)
+            .unwrap()
+            .unwrap();
+        assert_eq!(value["a"], 1);
+        assert_eq!(fs::read(&path).unwrap(), br#"{"a":1}"#);
+        let missing: Option<serde_json::Value> =
+            read_json_recovering(&tmp.path().join("none.json"), Recovery::Restore).unwrap();
+        assert!(missing.is_none());
+    }
+
+    #[test]
+    fn only_our_backups_are_internal() {
+        assert!(is_internal_file("note.md.hollow-bak"));
+        assert!(!is_internal_file("draft.bak"));
+        assert!(is_internal_file(".note.md.0f3e.tmp"));
+    }
+
+    #[cfg(unix)]
+    mod links {
+        use super::*;
+        use std::os::unix::fs::symlink;
+
+        fn setup() -> (tempfile::TempDir, PathBuf, PathBuf) {
+            let tmp = tempfile::tempdir().unwrap();
+            let realm = tmp.path().join("realm");
+            let outside = tmp.path().join("outside");
+            fs::create_dir_all(realm.join("main")).unwrap();
+            fs::create_dir_all(&outside).unwrap();
+            fs::write(outside.join("secret.txt"), "secret").unwrap();
+            (tmp, realm, outside)
+        }
+
+        #[test]
+        fn rejects_file_symlink_escape() {
+            let (_tmp, realm, outside) = setup();
+            let link = realm.join("main").join("link.txt");
+            symlink(outside.join("secret.txt"), &link).unwrap();
+            assert_eq!(
+                ensure_contained(&realm, &link),
+                Err(PathError::OutsideRealm(link))
+            );
+        }
+
+        #[test]
+        fn rejects_directory_link_escape() {
+            // Same shape as a junction on Windows: a directory link in the middle of the path
+            let (_tmp, realm, outside) = setup();
+            let link = realm.join("main").join("tool");
+            symlink(&outside, &link).unwrap();
+            let new_file = link.join("card").join("new.md");
+            assert_eq!(
+                ensure_contained(&realm, &new_file),
+                Err(PathError::OutsideRealm(new_file))
+            );
+        }
+
+        #[test]
+        fn rejects_dangling_symlink() {
+            let (_tmp, realm, outside) = setup();
+            let link = realm.join("main").join("dangling.md");
+            symlink(outside.join("missing.md"), &link).unwrap();
+            assert_eq!(
+                ensure_contained(&realm, &link),
+                Err(PathError::Unresolvable(link))
+            );
+        }
+
+        #[test]
+        fn allows_links_within_realm() {
+            let (_tmp, realm, _outside) = setup();
+            fs::create_dir_all(realm.join("vault")).unwrap();
+            let link = realm.join("main").join("vault-link");
+            symlink(realm.join("vault"), &link).unwrap();
+            assert!(ensure_contained(&realm, &link.join("image.png")).is_ok());
+            assert!(ensure_contained(&realm, &realm.join("main/tool/card/new.md")).is_ok());
+        }
     }
-    Ok(fs::read_to_string(&file_path).map_err(|e| format!("Failed to read file: {}", e))?)
 }
@@ -7,13 +7,18 @@ use tauri::{command, State};
 use tauri_plugin_log::log::{self};
 use uuid::Uuid;
 
-use crate::utils::{get_full_path, validate_path};
+use crate::error::HollowError;
+use crate::permissions::{ensure_granted, Permission};
+use crate::utils::{ensure_writable, get_full_path, validate_path};
 
 #[command]
 pub fn vault_add(
     paths: Vec<String>,
+    caller: String,
     state: State<'_, Mutex<crate::app::AppData>>,
-) -> Result<Vec<PathBuf>, String> {
+) -> Result<Vec<PathBuf>, HollowError> {
+    ensure_writable(&state)?;
+    ensure_granted(&caller, Permission::VaultWrite, &state)?;
     let vault_dir = get_full_path("vault", &state)?;
     let mut added_files = Vec::new();
 
@@ -34,7 +39,7 @@ pub fn vault_add(
 
         let dest_path = vault_dir.join(&new_file_name);
 
-        if let Err(e) = fs::copy(&source_path, &dest_path) {
+        if let Err(e) = fs::copy(source_path, &dest_path) {
             log::error!("Could not copy {}: {}", path, e);
             continue;
         }
@@ -50,14 +55,16 @@ pub fn vault_add(
 #[command]
 pub fn vault_remove(
     names: Vec<String>,
+    caller: String,
     state: State<'_, Mutex<crate::app::AppData>>,
-) -> Result<(), String> {
-    let vault_dir = get_full_path("vault", &state)?;
+) -> Result<(), HollowError> {
+    ensure_writable(&state)?;
+    ensure_granted(&caller, Permission::VaultWrite, &state)?;
     let mut had_errors = false;
 
     for name in names {
         validate_path(&name)?;
-        let file_path = vault_dir.join(&name);
+        let file_path = get_full_path(Path::new("vault").join(&name), &state)?;
 
         if file_path.exists() {
             if let Err(e) = trash::delete(&file_path) {
@@ -73,7 +80,9 @@ pub fn vault_remove(
     }
 
     if had_errors {
-        Err("Some files could not be removed. Check logs.".into())
+        Err(HollowError::PartialFailure(
+            "Some files could not be removed. Check logs.".to_string(),
+        ))
     } else {
         Ok(())
     }
@@ -82,8 +91,11 @@ pub fn vault_remove(
 #[command]
 pub async fn vault_add_url(
     url: String,
+    caller: String,
     state: State<'_, Mutex<crate::app::AppData>>,
-) -> Result<PathBuf, String> {
+) -> Result<PathBuf, HollowError> {
+    ensure_writable(&state)?;
+    ensure_granted(&caller, Permission::VaultWrite, &state)?;
     let vault_dir = get_full_path("vault", &state)?;
 
     let extension = Path::new(&url)
@@ -103,15 +115,14 @@ pub async fn vault_add_url(
 
     let resp = reqwest::get(&url)
         .await
-        .map_err(|e| format!("Failed to fetch {}: {}", url, e))?;
+        .map_err(|e| HollowError::Network(format!("Failed to fetch {}: {}", url, e)))?;
 
     let bytes = resp
         .bytes()
         .await
-        .map_err(|e| format!("Failed to read bytes from {}: {}", url, e))?;
+        .map_err(|e| HollowError::Network(format!("Failed to read bytes from {}: {}", url, e)))?;
 
-    fs::write(&dest_path, &bytes)
-        .map_err(|e| format!("Failed to write file {}: {}", dest_path.display(), e))?;
+    fs::write(&dest_path, &bytes).map_err(|e| HollowError::io(&dest_path, e))?;
 
     log::info!("Downloaded {} to {}", url, dest_path.display());
 
@@ -0,0 +1,448 @@
+use crate::error::HollowError;
+use crate::migrations;
+use crate::permissions::ensure_app;
+use crate::utils::{
+    ensure_writable, get_full_path, is_internal_file, read_json_recovering, recovery,
+    validate_path, write_atomic, Recovery,
+};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use tauri::{command, State};
+use tauri_plugin_log::log;
+
+/// Names the active version, inside `plugins/<name>/`
+pub const ACTIVE_FILE: &str = "active.json";
+const REGISTRY_INDEX_URL: &str =
+    "https://raw.githubusercontent.com/hollow-app/hollow-registry/refs/heads/main/plugins.json";
+/// Versions `plugin_prune` keeps besides the active one
+const DEFAULT_KEEP: usize = 2;
+
+#[derive(Serialize, Deserialize)]
+struct ActivePointer {
+    version: String,
+    /// Version active before the last switch, what a rollback returns to
+    #[serde(default)]
+    previous: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct PluginVersion {
+    pub version: String,
+    pub active: bool,
+    /// Realm-relative folder of the version
+    pub path: String,
+}
+
+/// Entry of the registry index, only the fields the update check needs.
+#[derive(Deserialize)]
+pub struct RegistryEntry {
+    pub name: String,
+    pub version: String,
+    #[serde(default)]
+    pub repo: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct PluginUpdate {
+    pub name: String,
+    pub installed: String,
+    pub available: String,
+    pub repo: Option<String>,
+}
+
+fn read_pointer(
+    plugin_dir: &Path,
+    recovery: Recovery,
+) -> Result<Option<ActivePointer>, HollowError> {
+    read_json_recovering(&plugin_dir.join(ACTIVE_FILE), recovery)
+}
+
+fn write_pointer(plugin_dir: &Path, pointer: &ActivePointer) -> Result<(), HollowError> {
+    let content =
+        serde_json::to_vec_pretty(pointer).map_err(|e| HollowError::Internal(e.to_string()))?;
+    write_atomic(&plugin_dir.join(ACTIVE_FILE), &content)
+}
+
+/// Semver order, with versions that don't parse sorted last.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    match (Version::parse(a), Version::parse(b)) {
+        (Ok(a), Ok(b)) => a.cmp(&b),
+        (Ok(_), Err(_)) => Ordering::Greater,
+        (Err(_), Ok(_)) => Ordering::Less,
+        (Err(_), Err(_)) => a.cmp(b),
+    }
+}
+
+pub fn active_version(
+    plugin_dir: &Path,
+    recovery: Recovery,
+) -> Result<Option<String>, HollowError> {
+    Ok(read_pointer(plugin_dir, recovery)?.map(|pointer| pointer.version))
+}
+
+/// Whether `plugin_dir` still has the flat layout from before version folders.
+/// Read-only realms aren't migrated, so they can be opened with it.
+pub fn is_legacy(plugin_dir: &Path, recovery: Recovery) -> Result<bool, HollowError> {
+    let Some(realm_root) = plugin_dir.parent().and_then(Path::parent) else {
+        return Ok(false);
+    };
+    Ok(!plugin_dir.join(ACTIVE_FILE).exists()
+        && migrations::schema_version(realm_root, recovery)? < migrations::VERSIONED_PLUGINS_SCHEMA)
+}
+
+/// Folder of the active version, if it's set and still there. Plugins in the
+/// legacy layout are their own active folder.
+pub fn active_dir(plugin_dir: &Path, recovery: Recovery) -> Result<Option<PathBuf>, HollowError> {
+    if is_legacy(plugin_dir, recovery)? {
+        return Ok(Some(plugin_dir.to_path_buf()).filter(|dir| dir.is_dir()));
+    }
+    Ok(active_version(plugin_dir, recovery)?
+        .map(|version| plugin_dir.join(version))
+        .filter(|dir| dir.is_dir()))
+}
+
+/// Installed versions, newest first.
+pub fn installed_versions(plugin_dir: &Path) -> Result<Vec<String>, HollowError> {
+    let entries = match fs::read_dir(plugin_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(HollowError::io(plugin_dir, e)),
+    };
+    let mut versions = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|e| HollowError::io(plugin_dir, e))?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if entry.path().is_dir() && !name.starts_with('.') && !is_internal_file(&name) {
+            versions.push(name);
+        }
+    }
+    versions.sort_by(|a, b| compare_versions(b, a));
+    Ok(versions)
+}
+
+/// Makes `version` the active one, remembering the current one for rollbacks.
+pub fn activate(plugin_dir: &Path, version: &str) -> Result<(), HollowError> {
+    validate_path(version)?;
+    if !plugin_dir.join(version).is_dir() {
+        return Err(HollowError::InvalidInput(format!(
+            "Version {} is not installed",
+            version
+        )));
+    }
+    let current = active_version(plugin_dir, Recovery::Restore)?;
+    if current.as_deref() == Some(version) {
+        return Ok(());
+    }
+    write_pointer(
+        plugin_dir,
+        &ActivePointer {
+            version: version.to_string(),
+            previous: current,
+        },
+    )
+}
+
+/// Goes back to the version active before the last switch, or the newest
+/// version older than the active one. Returns the version now active.
+pub fn rollback(plugin_dir: &Path) -> Result<String, HollowError> {
+    let pointer = read_pointer(plugin_dir, Recovery::Restore)?
+        .ok_or_else(|| HollowError::InvalidInput("The plugin has no active version".to_string()))?;
+    let installed = installed_versions(plugin_dir)?;
+    let target = pointer
+        .previous
+        .filter(|previous| installed.contains(previous))
+        .or_else(|| {
+            installed
+                .into_iter()
+                .find(|v| compare_versions(v, &pointer.version) == Ordering::Less)
+        })
+        .ok_or_else(|| HollowError::InvalidInput("No version to roll back to".to_string()))?;
+    activate(plugin_dir, &target)?;
+    Ok(target)
+}
+
+/// Removes all but the `keep` newest versions, never the active one or the
+/// one a rollback would return to. Returns the removed versions.
+pub fn prune(plugin_dir: &Path, keep: usize) -> Result<Vec<String>, HollowError> {
+    let pointer = read_pointer(plugin_dir, Recovery::Restore)?;
+    let active = pointer.as_ref().map(|p| p.version.as_str());
+    let previous = pointer.as_ref().and_then(|p| p.previous.as_deref());
+    let mut removed = Vec::new();
+    for version in installed_versions(plugin_dir)?.into_iter().skip(keep) {
+        if Some(version.as_str()) == active || Some(version.as_str()) == previous {
+            continue;
+        }
+        let dir = plugin_dir.join(&version);
+        fs::remove_dir_all(&dir).map_err(|e| HollowError::io(&dir, e))?;
+        removed.push(version);
+    }
+    if !removed.is_empty() {
+        log::info!(
+            "Pruned {} from {}",
+            removed.join(", "),
+            plugin_dir.display()
+        );
+    }
+    Ok(removed)
+}
+
+/// Active versions that are older than the registry's.
+pub fn find_updates(
+    plugins_dir: &Path,
+    index: Vec<RegistryEntry>,
+    recovery: Recovery,
+) -> Result<Vec<PluginUpdate>, HollowError> {
+    let index: HashMap<String, RegistryEntry> = index
+        .into_iter()
+        .map(|entry| (entry.name.to_lowercase(), entry))
+        .collect();
+    let entries = match fs::read_dir(plugins_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(HollowError::io(plugins_dir, e)),
+    };
+    let mut updates = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|e| HollowError::io(plugins_dir, e))?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        // Plugins with a broken pointer are left to fsck
+        let (Ok(Some(installed)), Some(available)) =
+            (active_version(&entry.path(), recovery), index.get(&name))
+        else {
+            continue;
+        };
+        let newer = matches!(
+            (Version::parse(&installed), Version::parse(&available.version)),
+            (Ok(installed), Ok(available)) if available > installed
+        );
+        if newer {
+            updates.push(PluginUpdate {
+                name,
+                installed,
+                available: available.version.clone(),
+                repo: available.repo.clone(),
+            });
+        }
+    }
+    updates.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(updates)
+}
+
+fn plugin_dir(
+    name: &str,
+    state: &State<'_, Mutex<crate::app::AppData>>,
+) -> Result<PathBuf, HollowError> {
+    validate_path(name)?;
+    let dir = get_full_path(Path::new("plugins").join(name), state)?;
+    if !dir.is_dir() {
+        return Err(HollowError::NotFound(dir));
+    }
+    Ok(dir)
+}
+
+/// Realm-relative folder of the plugin's active version, where its
+/// `index.js` and assets are loaded from.
+#[command]
+pub fn plugin_active_dir(
+    name: String,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<String, HollowError> {
+    ensure_app(&caller, &state)?;
+    let dir = plugin_dir(&name, &state)?;
+    if is_legacy(&dir, recovery(&state))? {
+        return Ok(format!("plugins/{}", name));
+    }
+    let version = active_version(&dir, recovery(&state))?
+        .ok_or_else(|| HollowError::InvalidInput(format!("{} has no active version", name)))?;
+    Ok(format!("plugins/{}/{}", name, version))
+}
+
+#[command]
+pub fn plugin_versions(
+    name: String,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<Vec<PluginVersion>, HollowError> {
+    ensure_app(&caller, &state)?;
+    let dir = plugin_dir(&name, &state)?;
+    // Subfolders of a legacy plugin are its own assets, not versions
+    if is_legacy(&dir, recovery(&state))? {
+        return Ok(Vec::new());
+    }
+    let active = active_version(&dir, recovery(&state))?;
+    Ok(installed_versions(&dir)?
+        .into_iter()
+        .map(|version| PluginVersion {
+            active: active.as_deref() == Some(version.as_str()),
+            path: format!("plugins/{}/{}", name, version),
+            version,
+        })
+        .collect())
+}
+
+#[command]
+pub fn plugin_switch_version(
+    name: String,
+    version: String,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<(), HollowError> {
+    ensure_writable(&state)?;
+    ensure_app(&caller, &state)?;
+    activate(&plugin_dir(&name, &state)?, &version)
+}
+
+#[command]
+pub fn plugin_rollback(
+    name: String,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<String, HollowError> {
+    ensure_writable(&state)?;
+    ensure_app(&caller, &state)?;
+    rollback(&plugin_dir(&name, &state)?)
+}
+
+#[command]
+pub fn plugin_prune(
+    name: String,
+    keep: Option<usize>,
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<Vec<String>, HollowError> {
+    ensure_writable(&state)?;
+    ensure_app(&caller, &state)?;
+    prune(&plugin_dir(&name, &state)?, keep.unwrap_or(DEFAULT_KEEP))
+}
+
+/// Compares the installed plugins with the official registry index.
+#[command]
+pub async fn plugin_check_updates(
+    caller: String,
+    state: State<'_, Mutex<crate::app::AppData>>,
+) -> Result<Vec<PluginUpdate>, HollowError> {
+    ensure_app(&caller, &state)?;
+    let plugins_dir = get_full_path("plugins", &state)?;
+    let recovery = recovery(&state);
+    let url = REGISTRY_INDEX_URL;
+    let entries: Vec<RegistryEntry> = reqwest::get(url)
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| HollowError::Network(format!("Failed to fetch {}: {}", url, e)))?
+        .json()
+        .await
+        .map_err(|e| HollowError::Network(format!("Invalid registry index {}: {}", url, e)))?;
+    find_updates(&plugins_dir, entries, recovery)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn install(plugin_dir: &Path, versions: &[&str]) {
+        for version in versions {
+            fs::create_dir_all(plugin_dir.join(version)).unwrap();
+            activate(plugin_dir, version).unwrap();
+        }
+    }
+
+    #[test]
+    fn sorts_versions_newest_first() {
+        let tmp = tempfile::tempdir().unwrap();
+        install(tmp.path(), &["1.2.0", "1.10.0", "1.9.0", "dev"]);
+        fs::create_dir(tmp.path().join(".staging")).unwrap();
+        assert_eq!(
+            installed_versions(tmp.path()).unwrap(),
+            vec!["1.10.0", "1.9.0", "1.2.0", "dev"]
+        );
+    }
+
+    #[test]
+    fn rolls_back_to_the_previous_version() {
+        let tmp = tempfile::tempdir().unwrap();
+        install(tmp.path(), &["1.0.0", "2.0.0", "1.5.0"]);
+        // 1.5.0 was switched to from 2.0.0
+        assert_eq!(rollback(tmp.path()).unwrap(), "2.0.0");
+        assert_eq!(rollback(tmp.path()).unwrap(), "1.5.0");
+
+        // Without a previous version, the newest older one is used
+        fs::remove_dir(tmp.path().join("2.0.0")).unwrap();
+        write_pointer(
+            tmp.path(),
+            &ActivePointer {
+                version: "1.5.0".to_string(),
+                previous: Some("2.0.0".to_string()),
+            },
+        )
+        .unwrap();
+        assert_eq!(rollback(tmp.path()).unwrap(), "1.0.0");
+        fs::remove_dir(tmp.path().join("1.5.0")).unwrap();
+        write_pointer(
+            tmp.path(),
+            &ActivePointer {
+                version: "1.0.0".to_string(),
+                previous: None,
+            },
+        )
+        .unwrap();
+        assert!(rollback(tmp.path()).is_err());
+    }
+
+    #[test]
+    fn prune_keeps_the_active_and_previous_versions() {
+        let tmp = tempfile::tempdir().unwrap();
+        install(tmp.path(), &["1.0.0", "1.1.0", "2.0.0", "3.0.0", "1.2.0"]);
+        // 1.2.0 is active and 3.0.0 is what a rollback returns to
+        let removed = prune(tmp.path(), 1).unwrap();
+        assert_eq!(removed, vec!["2.0.0", "1.1.0", "1.0.0"]);
+        assert_eq!(
+            installed_versions(tmp.path()).unwrap(),
+            vec!["3.0.0", "1.2.0"]
+        );
+        assert_eq!(
+            active_version(tmp.path(), Recovery::Restore).unwrap(),
+            Some("1.2.0".to_string())
+        );
+        assert!(prune(tmp.path(), 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn reads_the_flat_layout_of_unmigrated_realms() {
+        let tmp = tempfile::tempdir().unwrap();
+        migrations::write_info(tmp.path(), 1).unwrap();
+        let plugins = tmp.path().join("plugins");
+        let plugin_dir = plugins.join("demo");
+        fs::create_dir_all(plugin_dir.join("assets")).unwrap();
+        fs::write(plugin_dir.join("index.js"), "run()").unwrap();
+        let manifest = serde_json::json!({ "name": "demo", "version": "1.0.0" });
+        fs::write(plugin_dir.join("manifest.json"), manifest.to_string()).unwrap();
+
+        // Opened read-only, so nothing is migrated or written
+        assert!(is_legacy(&plugin_dir, Recovery::InMemory).unwrap());
+        assert_eq!(
+            active_dir(&plugin_dir, Recovery::InMemory).unwrap(),
+            Some(plugin_dir.clone())
+        );
+        let reports = crate::plugins::check_plugins(&plugins, &[], Recovery::InMemory).unwrap();
+        assert_eq!(reports[0].path.as_deref(), Some("plugins/demo"));
+        assert!(!plugin_dir.join(ACTIVE_FILE).exists());
+
+        // Migrated realms need the pointer
+        migrations::write_info(tmp.path(), 2).unwrap();
+        assert_eq!(active_dir(&plugin_dir, Recovery::InMemory).unwrap(), None);
+        install(&plugin_dir, &["1.0.0"]);
+        migrations::write_info(tmp.path(), 1).unwrap();
+        assert_eq!(
+            active_dir(&plugin_dir, Recovery::InMemory).unwrap(),
+            Some(plugin_dir.join("1.0.0"))
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use crate::error::HollowError;
+use crate::utils::is_internal_file;
+use notify_debouncer_full::{
+    new_debouncer,
+    notify::{
+        event::{EventKind, ModifyKind},
+        RecommendedWatcher, RecursiveMode,
+    },
+    DebounceEventResult, Debouncer, RecommendedCache,
+};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tauri::{AppHandle, Emitter};
+use tauri_plugin_log::log;
+
+pub const CARD_FS_EVENT: &str = "card-fs-change";
+const DEBOUNCE_TIMEOUT: Duration = Duration::from_millis(300);
+
+#[derive(Serialize, Clone)]
+pub struct CardFsEvent {
+    pub tool_name: String,
+    pub card_name: String,
+    pub kind: &'static str,
+    pub paths: Vec<String>,
+}
+
+struct CardWatcher {
+    _debouncer: Debouncer<RecommendedWatcher, RecommendedCache>,
+    subscribers: usize,
+}
+
+#[derive(Default)]
+pub struct CardWatchers {
+    watchers: HashMap<String, CardWatcher>,
+}
+
+fn watcher_key(tool_name: &str, card_name: &str) -> String {
+    format!("{}/{}", tool_name, card_name)
+}
+
+fn event_kind(kind: &EventKind) -> Option<&'static str> {
+    match kind {
+        EventKind::Create(_) => Some("created"),
+        EventKind::Modify(ModifyKind::Name(_)) => Some("renamed"),
+        EventKind::Modify(_) => Some("modified"),
+        EventKind::Remove(_) => Some("removed"),
+        _ => None,
+    }
+}
+
+fn relative_paths(root: &Path, paths: &[PathBuf]) -> (Vec<String>, bool) {
+    let mut had_internal = false;
+    let mut relative = Vec::new();
+    for path in paths {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if is_internal_file(&name) {
+            had_internal = true;
+            continue;
+        }
+        if let Ok(rel) = path.strip_prefix(root) {
+            relative.push(rel.to_string_lossy().replace('\\', "/"));
+        }
+    }
+    (relative, had_internal)
+}
+
+impl CardWatchers {
+    /// Cards of read-only realms aren't created just to be watched.
+    pub fn watch(
+        &mut self,
+        app: AppHandle,
+        tool_name: &str,
+        card_name: &str,
+        card_root: PathBuf,
+        read_only: bool,
+    ) -> Result<(), HollowError> {
+        let key = watcher_key(tool_name, card_name);
+        if let Some(watcher) = self.watchers.get_mut(&key) {
+            watcher.subscribers += 1;
+            return Ok(());
+        }
+
+        if read_only {
+            if !card_root.is_dir() {
+                return Err(HollowError::NotFound(card_root));
+            }
+        } else {
+            std::fs::create_dir_all(&card_root).map_err(|e| HollowError::io(&card_root, e))?;
+        }
+
+        let root = card_root.clone();
+        let tool = tool_name.to_string();
+        let card = card_name.to_string();
+        let mut debouncer = new_debouncer(
+            DEBOUNCE_TIMEOUT,
+            None,
+            move |result: DebounceEventResult| match result {
+                Ok(events) => {
+                    for event in events {
+                        let Some(mut kind) = event_kind(&event.kind) else {
+                            continue;
+                        };
+                        let (paths, had_internal) = relative_paths(&root, &event.paths);
+                        if paths.is_empty() {
+                            continue;
+                        }
+                        // Atomic writes land as a rename from a temp file
+                        if kind == "renamed" && had_internal {
+                            kind = "modified";
+                        }
+                        let payload = CardFsEvent {
+                            tool_name: tool.clone(),
+                            card_name: card.clone(),
+                            kind,
+                            paths,
+                        };
+                        if let Err(e) = app.emit(CARD_FS_EVENT, payload) {
+                            log::error!("Failed to emit card fs event: {}", e);
+                        }
+                    }
+                }
+                Err(errors) => {
+                    for e in errors {
+                        log::error!("Card watcher error for {}/{}: {}", tool, card, e);
+                    }
+                }
+            },
+        )
+        .map_err(|e| HollowError::Internal(format!("Failed to create watcher: {}", e)))?;
+
+        debouncer
+            .watch(&card_root, RecursiveMode::Recursive)
+            .map_err(|e| {
+                HollowError::Internal(format!("Failed to watch {}: {}", card_root.display(), e))
+            })?;
+
+        log::info!("Watching card {}", key);
+        self.watchers.insert(
+            key,
+            CardWatcher {
+                _debouncer: debouncer,
+                subscribers: 1,
+            },
+        );
+        Ok(())
+    }
+
+    pub fn unwatch(&mut self, tool_name: &str, card_name: &str) {
+        let key = watcher_key(tool_name, card_name);
+        if let Some(watcher) = self.watchers.get_mut(&key) {
+            watcher.subscribers -= 1;
+            if watcher.subscribers == 0 {
+                // Dropping the debouncer stops it
+                self.watchers.remove(&key);
+                log::info!("Stopped watching card {}", key);
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.watchers.clear();
+    }
+}
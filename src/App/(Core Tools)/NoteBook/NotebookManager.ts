@@ -1,4 +1,3 @@
-import fm from "front-matter";
 import { NotebookType } from "./NotebookType";
 import { AppApi, CardFs, IStore, ToolApi } from "@type/hollow";
 import { NoteType } from "./NoteType";
@@ -46,15 +45,16 @@ export class NotebookManager {
 	}
 
 	async getNotebook(id: string, cardName: string): Promise<NotebookType> {
-		const entries = await this.getCardFs(cardName).readDir();
-		const notes: NoteType[] = await Promise.all(
-			entries
-				.filter((i) => !i.is_dir)
-				.map(async (i) => ({
-					...fm(await this.getNote(cardName, i.name)),
-					title: i.name.split(".md")[0],
-				})),
-		);
+		const entries = await this.getCardFs(cardName).scanFrontmatter("", {
+			includeBody: true,
+		});
+		const notes: NoteType[] = entries.map((i) => ({
+			attributes: i.attributes as NoteType["attributes"],
+			body: i.body ?? "",
+			bodyBegin: i.body_begin,
+			frontmatter: i.frontmatter ?? undefined,
+			title: i.title,
+		}));
 		const notebook: NotebookType = {
 			...this.store.get(id),
 			notes,
@@ -195,7 +195,8 @@ const CustomOption = (props: { option: OptionType<"custom"> }) => {
 	onMount(() => {
 		const obj = props.option.render(el, props.option);
 		onCleanup(() => {
-			obj.cleanup();
+			// plugins answer from their frame, with a promise
+			obj?.cleanup?.();
 		});
 	});
 	return <div class="size-full" ref={el} />;
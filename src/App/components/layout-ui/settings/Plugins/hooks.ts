@@ -153,13 +153,10 @@ export const usePlugins = (): PluginsHook => {
 			repo: plugin.repo,
 		});
 		if (request.state) {
-			const manifest = JSON.parse(request.manifest);
 			const newTool: HandType = {
-				...manifest,
+				...request.manifest,
 				icon: request.icon,
-				name: manifest.name.toLowerCase(),
 				cards: [],
-				signed: true,
 			};
 
 			if (isUpdate) {
@@ -36,6 +36,21 @@ export default function FetchedIcon(props: FetchedIconProps) {
 			throw new Error("Invalid SVG markup");
 		}
 
+		// icons come from plugins, nothing in them may run in the app's page
+		svg.querySelectorAll("script, foreignObject").forEach((node) =>
+			node.remove(),
+		);
+		for (const node of [svg, ...Array.from(svg.querySelectorAll("*"))]) {
+			for (const { name, value } of Array.from(node.attributes)) {
+				if (
+					name.toLowerCase().startsWith("on") ||
+					/^\s*javascript:/i.test(value)
+				) {
+					node.removeAttribute(name);
+				}
+			}
+		}
+
 		svg.setAttribute("class", `fetched-icon ${props.class ?? ""}`);
 
 		if (props.style) {
@@ -1,14 +1,47 @@
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import { app_caller } from "@rust";
 
 type PathProps = {
 	toolName: string;
 	cardName: string;
 	path?: string;
+	/**
+	 * Token of the plugin the call is made for, the backend checks its
+	 * grants. The app's own token when unset.
+	 */
+	caller?: string;
 };
 
 export type CardDirEntry = {
 	name: string;
 	is_dir: boolean;
+	is_symlink: boolean;
+	size: number;
+	created: number | null;
+	modified: number | null;
+	mime: string | null;
+};
+
+export type CardFsEvent = {
+	tool_name: string;
+	card_name: string;
+	kind: "created" | "modified" | "removed" | "renamed";
+	paths: string[];
+};
+
+export type CardVersion = {
+	id: string;
+	hash: string;
+	size: number;
+	created: number;
+};
+
+export type DiffLine = {
+	tag: "equal" | "insert" | "delete";
+	old_line: number | null;
+	new_line: number | null;
+	value: string;
 };
 
 export async function mkdir(props: PathProps): Promise<void> {
@@ -16,6 +49,7 @@ export async function mkdir(props: PathProps): Promise<void> {
 		toolName: props.toolName,
 		cardName: props.cardName,
 		path: props.path || "",
+		caller: props.caller ?? (await app_caller()),
 	});
 }
 
@@ -24,6 +58,35 @@ export async function readDir(props: PathProps): Promise<CardDirEntry[]> {
 		toolName: props.toolName,
 		cardName: props.cardName,
 		path: props.path || "",
+		caller: props.caller ?? (await app_caller()),
+	});
+}
+
+export type WalkOptions = {
+	maxDepth?: number;
+	include?: string[];
+	exclude?: string[];
+	includeHidden?: boolean;
+};
+
+export async function walk(
+	props: PathProps & { options?: WalkOptions },
+): Promise<CardDirEntry[]> {
+	return await invoke<CardDirEntry[]>("card_walk", {
+		toolName: props.toolName,
+		cardName: props.cardName,
+		path: props.path || "",
+		options: props.options,
+		caller: props.caller ?? (await app_caller()),
+	});
+}
+
+export async function stat(props: PathProps): Promise<CardDirEntry> {
+	return await invoke<CardDirEntry>("card_stat", {
+		toolName: props.toolName,
+		cardName: props.cardName,
+		path: props.path || "",
+		caller: props.caller ?? (await app_caller()),
 	});
 }
 
@@ -32,7 +95,32 @@ export async function readFile(props: PathProps): Promise<string> {
 		toolName: props.toolName,
 		cardName: props.cardName,
 		path: props.path || "",
+		caller: props.caller ?? (await app_caller()),
+	});
+}
+
+export async function readFileBytes(props: PathProps): Promise<Uint8Array> {
+	const buffer = await invoke<ArrayBuffer>("card_read_file_bytes", {
+		toolName: props.toolName,
+		cardName: props.cardName,
+		path: props.path || "",
+		caller: props.caller ?? (await app_caller()),
+	});
+	return new Uint8Array(buffer);
+}
+
+export async function readFileRange(
+	props: PathProps & { offset: number; length: number },
+): Promise<Uint8Array> {
+	const buffer = await invoke<ArrayBuffer>("card_read_file_range", {
+		toolName: props.toolName,
+		cardName: props.cardName,
+		path: props.path || "",
+		offset: props.offset,
+		length: props.length,
+		caller: props.caller ?? (await app_caller()),
 	});
+	return new Uint8Array(buffer);
 }
 
 export async function writeFile(
@@ -43,17 +131,140 @@ export async function writeFile(
 		cardName: props.cardName,
 		path: props.path || "",
 		contents: props.contents,
+		caller: props.caller ?? (await app_caller()),
+	});
+}
+
+export async function writeFileBytes(
+	props: PathProps & { contents: Uint8Array },
+): Promise<void> {
+	await invoke("card_write_file_bytes", {
+		toolName: props.toolName,
+		cardName: props.cardName,
+		path: props.path || "",
+		contents: toBase64(props.contents),
+		caller: props.caller ?? (await app_caller()),
 	});
 }
 
 export async function rename(
-	props: PathProps & { newPath: string },
+	props: PathProps & { newPath: string; rewriteLinks?: boolean },
 ): Promise<void> {
 	await invoke("card_rename", {
 		toolName: props.toolName,
 		cardName: props.cardName,
 		path: props.path || "",
 		newPath: props.newPath,
+		rewriteLinks: props.rewriteLinks,
+		caller: props.caller ?? (await app_caller()),
+	});
+}
+
+export type NoteLink = {
+	source: string;
+	target: string;
+	kind: "wiki" | "markdown";
+	line: number;
+	resolved: string | null;
+};
+
+export async function links(props: PathProps): Promise<NoteLink[]> {
+	return await invoke<NoteLink[]>("card_links", {
+		toolName: props.toolName,
+		cardName: props.cardName,
+		path: props.path || "",
+		caller: props.caller ?? (await app_caller()),
+	});
+}
+
+export async function backlinks(props: PathProps): Promise<NoteLink[]> {
+	return await invoke<NoteLink[]>("card_backlinks", {
+		toolName: props.toolName,
+		cardName: props.cardName,
+		path: props.path || "",
+		caller: props.caller ?? (await app_caller()),
+	});
+}
+
+export async function orphanNotes(
+	props: Omit<PathProps, "path">,
+): Promise<string[]> {
+	return await invoke<string[]>("card_orphan_notes", {
+		toolName: props.toolName,
+		cardName: props.cardName,
+		caller: props.caller ?? (await app_caller()),
+	});
+}
+
+export async function brokenLinks(
+	props: Omit<PathProps, "path">,
+): Promise<NoteLink[]> {
+	return await invoke<NoteLink[]>("card_broken_links", {
+		toolName: props.toolName,
+		cardName: props.cardName,
+		caller: props.caller ?? (await app_caller()),
+	});
+}
+
+export type CardLocation = Required<PathProps>;
+
+export type CollisionStrategy = "fail" | "overwrite" | "rename";
+
+export async function copy(props: {
+	from: PathProps;
+	to: PathProps;
+	onCollision?: CollisionStrategy;
+	caller?: string;
+}): Promise<CardLocation> {
+	return await invoke<CardLocation>("card_copy", {
+		from: { ...props.from, path: props.from.path || "" },
+		to: { ...props.to, path: props.to.path || "" },
+		onCollision: props.onCollision,
+		caller: props.caller ?? (await app_caller()),
+	});
+}
+
+export async function move(props: {
+	from: PathProps;
+	to: PathProps;
+	onCollision?: CollisionStrategy;
+	caller?: string;
+}): Promise<CardLocation> {
+	return await invoke<CardLocation>("card_move", {
+		from: { ...props.from, path: props.from.path || "" },
+		to: { ...props.to, path: props.to.path || "" },
+		onCollision: props.onCollision,
+		caller: props.caller ?? (await app_caller()),
+	});
+}
+
+export type FrontmatterQuery = {
+	recursive?: boolean;
+	includeBody?: boolean;
+	has?: string[];
+	equals?: Record<string, string>;
+};
+
+export type NoteFrontmatter = {
+	path: string;
+	title: string;
+	attributes: Record<string, any>;
+	frontmatter: string | null;
+	body_begin: number;
+	body_offset: number;
+	body: string | null;
+	error: string | null;
+};
+
+export async function scanFrontmatter(
+	props: PathProps & { query?: FrontmatterQuery },
+): Promise<NoteFrontmatter[]> {
+	return await invoke<NoteFrontmatter[]>("card_scan_frontmatter", {
+		toolName: props.toolName,
+		cardName: props.cardName,
+		path: props.path || "",
+		query: props.query,
+		caller: props.caller ?? (await app_caller()),
 	});
 }
 
@@ -62,6 +273,7 @@ export async function remove(props: PathProps): Promise<void> {
 		toolName: props.toolName,
 		cardName: props.cardName,
 		path: props.path || "",
+		caller: props.caller ?? (await app_caller()),
 	});
 }
 
@@ -70,5 +282,76 @@ export async function exists(props: PathProps): Promise<boolean> {
 		toolName: props.toolName,
 		cardName: props.cardName,
 		path: props.path || "",
+		caller: props.caller ?? (await app_caller()),
 	});
 }
+
+export async function watch(
+	props: Omit<PathProps, "path">,
+	onChange: (event: CardFsEvent) => void,
+): Promise<() => Promise<void>> {
+	const unlisten = await listen<CardFsEvent>("card-fs-change", (e) => {
+		if (
+			e.payload.tool_name === props.toolName &&
+			e.payload.card_name === props.cardName
+		) {
+			onChange(e.payload);
+		}
+	});
+	await invoke("card_watch", {
+		toolName: props.toolName,
+		cardName: props.cardName,
+		caller: props.caller ?? (await app_caller()),
+	});
+	return async () => {
+		unlisten();
+		await invoke("card_unwatch", {
+			toolName: props.toolName,
+			cardName: props.cardName,
+			caller: props.caller ?? (await app_caller()),
+		});
+	};
+}
+
+export async function listVersions(props: PathProps): Promise<CardVersion[]> {
+	return await invoke<CardVersion[]>("card_history_list", {
+		toolName: props.toolName,
+		cardName: props.cardName,
+		path: props.path || "",
+		caller: props.caller ?? (await app_caller()),
+	});
+}
+
+export async function diffVersions(
+	props: PathProps & { from: string; to?: string },
+): Promise<DiffLine[]> {
+	return await invoke<DiffLine[]>("card_history_diff", {
+		toolName: props.toolName,
+		cardName: props.cardName,
+		path: props.path || "",
+		from: props.from,
+		to: props.to,
+		caller: props.caller ?? (await app_caller()),
+	});
+}
+
+export async function restoreVersion(
+	props: PathProps & { version: string },
+): Promise<void> {
+	await invoke("card_history_restore", {
+		toolName: props.toolName,
+		cardName: props.cardName,
+		path: props.path || "",
+		version: props.version,
+		caller: props.caller ?? (await app_caller()),
+	});
+}
+
+function toBase64(bytes: Uint8Array): string {
+	let binary = "";
+	// chunked to stay under the argument limit of fromCharCode
+	for (let i = 0; i < bytes.length; i += 0x8000) {
+		binary += String.fromCharCode(...bytes.subarray(i, i + 0x8000));
+	}
+	return btoa(binary);
+}
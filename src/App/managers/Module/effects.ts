@@ -4,13 +4,13 @@ import { Storage } from "@managers/Storage";
 import { CoreTool, Events, ModuleState, ToolMap, ToolMethods } from "./type";
 import DEFAULT from "@assets/configs/main.json?raw";
 import { HandType } from "@type/HandType";
-import { convertFileSrc } from "@tauri-apps/api/core";
 import {
 	HollowEvent,
 	IPlugin,
 	CardType,
 	ToolEvents,
 	CardFs,
+	CardLocation,
 	IStore,
 	ToolEventReturns,
 	ToolApi,
@@ -30,6 +30,7 @@ import {
 	create_dir,
 	get_unsigned_plugins,
 	load_plugin,
+	realmFileSrc,
 	reload,
 	remove_dir,
 	remove_plugin,
@@ -51,26 +52,42 @@ export function setupModule(dispatch: (action: any) => void) {
 		});
 		let parsedData = store.getData();
 		if (loadUnsigned) {
-			const unsignedTools: HandType[] = await get_unsigned_plugins();
+			const reports = await get_unsigned_plugins();
+			const unsignedTools: HandType[] = [];
+			const pluginPaths: Record<string, string> = {};
+			for (const report of reports) {
+				for (const diagnostic of report.diagnostics) {
+					console.warn(
+						`plugin ${report.folder}: ${diagnostic.message}`,
+					);
+				}
+				if (report.manifest && report.path) {
+					unsignedTools.push(report.manifest);
+					pluginPaths[report.manifest.name] = report.path;
+				}
+			}
 			const thereIsNewPlugins = {};
 			for (const tool of unsignedTools) {
 				const targetTool = parsedData[tool.name];
+				// stored icons may predate the realm protocol, so refresh them
+				const icon = realmFileSrc(
+					await join(
+						realmLocation,
+						pluginPaths[tool.name],
+						"icon.svg",
+					),
+				);
 				if (targetTool) {
 					parsedData[tool.name] = {
 						...targetTool,
 						...tool,
+						icon,
 					};
 				} else {
-					const iconPath = await join(
-						realmLocation,
-						"plugins",
-						tool.name,
-						"icon.svg",
-					);
 					const ntool = {
 						...tool,
 						cards: [],
-						icon: convertFileSrc(iconPath),
+						icon,
 					};
 					parsedData[tool.name] = ntool;
 					thereIsNewPlugins[tool.name] = ntool;
@@ -86,17 +103,17 @@ export function setupModule(dispatch: (action: any) => void) {
 
 		for (const key in parsedData) {
 			const tool = parsedData[key];
-			if (!tool.signed && !loadUnsigned) continue;
-			const toolInstance = await createToolInstance(tool);
-			if (toolInstance) {
-				toolMap.set(tool.name, toolInstance);
-
-				toolInstance.toolEvent.on(
-					"card-fs",
-					({ cardName }: { cardName: string }) =>
-						getCardFs(tool.name, cardName),
-				);
-			}
+			// the backend refuses plugins without a trusted signature, the
+			// stored flags are only what the user saw at install time
+			const toolInstance = await createToolInstance(tool, loadUnsigned);
+			if (!toolInstance) continue;
+			toolMap.set(tool.name, toolInstance);
+
+			toolInstance.toolEvent.on(
+				"card-fs",
+				({ cardName }: { cardName: string }) =>
+					getCardFs(tool.name, cardName),
+			);
 
 			updateToolMetadata(tool.name, {
 				cards: tool.cards ?? [],
@@ -136,13 +153,10 @@ export async function moduleEffects(action: Events, state: ModuleState) {
 				repo: repo,
 			});
 			if (request.state) {
-				const manifest = JSON.parse(request.manifest);
 				const newTool: HandType = {
-					...manifest,
+					...request.manifest,
 					icon: request.icon,
-					name: manifest.name.toLowerCase(),
 					cards: [],
-					signed: true,
 				};
 
 				if (isUpdate) {
@@ -203,6 +217,7 @@ export async function moduleEffects(action: Events, state: ModuleState) {
 				const toolEvents = getToolEvents(name);
 				const storeConfig: IStore = toolEvents.getData("config");
 				await storeConfig.close();
+				toolMap.get(name)?.close?.();
 				toolMap.delete(name);
 				const toolPath = await join("main", name);
 				await remove_dir(toolPath);
@@ -398,7 +413,10 @@ export async function moduleEffects(action: Events, state: ModuleState) {
 }
 
 // loads internal tools classes
-async function createToolInstance(tool: HandType): Promise<ToolMethods | null> {
+async function createToolInstance(
+	tool: HandType,
+	allowUntrusted: boolean,
+): Promise<ToolMethods | null> {
 	if (hollow.coreTools.includes(tool.name as CoreTool)) {
 		const toolEvent = await createToolEvent(tool.name);
 		const toolClass = createCoreTool(tool.name as CoreTool, toolEvent);
@@ -413,26 +431,30 @@ async function createToolInstance(tool: HandType): Promise<ToolMethods | null> {
 			toolEvent,
 		};
 	}
-	return loadTool(tool);
+	return loadTool(tool, allowUntrusted);
 }
 
 // loads external tools classes
-export async function loadTool(tool: HandType): Promise<ToolMethods | null> {
-	const semiPath = await join(...["plugins", tool.name, "index.js"]);
-
+export async function loadTool(
+	tool: HandType,
+	allowUntrusted = false,
+): Promise<ToolMethods | null> {
 	const toolEvent = await createToolEvent(tool.name);
 	const toolClass = await load_plugin({
-		semiPath: semiPath,
+		name: tool.name,
+		allowUntrusted,
 		toolEvent,
 	});
 
 	if (!toolClass) return null;
 	return {
 		name: tool.name,
+		caller: toolClass.caller,
 		onCreate: toolClass.onCreate.bind(toolClass),
 		onDelete: toolClass.onDelete.bind(toolClass),
 		onLoad: toolClass.onLoad.bind(toolClass),
 		onUnload: toolClass.onUnload.bind(toolClass),
+		close: toolClass.close,
 		toolEvent,
 	};
 }
@@ -477,16 +499,56 @@ function getCard(toolName: string, cardId: string): CardType {
 }
 
 function getCardFs(toolName: string, cardName: string) {
+	// Core tools call as the app, plugins with the token they were loaded
+	// with and are held to their grants. Without one every call is refused.
+	const caller = hollow.coreTools.includes(toolName as CoreTool)
+		? undefined
+		: (toolMap.get(toolName)?.caller ?? "");
+	const at = { toolName, cardName, caller };
+	const locate = (to: string | CardLocation): CardLocation =>
+		typeof to === "string" ? { toolName, cardName, path: to } : to;
 	const cardFs: CardFs = {
-		exists: (path) => cfm.exists({ toolName, cardName, path }),
-		readFile: (path) => cfm.readFile({ toolName, cardName, path }),
-		writeFile: (path, contents) =>
-			cfm.writeFile({ toolName, cardName, path, contents }),
-		mkdir: (path) => cfm.mkdir({ toolName, cardName, path }),
-		readDir: (path) => cfm.readDir({ toolName, cardName, path }),
-		remove: (path) => cfm.remove({ toolName, cardName, path }),
-		rename: (path, newPath) =>
-			cfm.rename({ toolName, cardName, path, newPath }),
+		exists: (path) => cfm.exists({ ...at, path }),
+		readFile: (path) => cfm.readFile({ ...at, path }),
+		readFileBytes: (path) => cfm.readFileBytes({ ...at, path }),
+		readFileRange: (path, offset, length) =>
+			cfm.readFileRange({ ...at, path, offset, length }),
+		writeFile: (path, contents) => cfm.writeFile({ ...at, path, contents }),
+		writeFileBytes: (path, contents) =>
+			cfm.writeFileBytes({ ...at, path, contents }),
+		mkdir: (path) => cfm.mkdir({ ...at, path }),
+		readDir: (path) => cfm.readDir({ ...at, path }),
+		walk: (path, options) => cfm.walk({ ...at, path, options }),
+		stat: (path) => cfm.stat({ ...at, path }),
+		scanFrontmatter: (path, query) =>
+			cfm.scanFrontmatter({ ...at, path, query }),
+		remove: (path) => cfm.remove({ ...at, path }),
+		rename: (path, newPath, options) =>
+			cfm.rename({ ...at, path, newPath, ...options }),
+		copy: (path, to, onCollision) =>
+			cfm.copy({
+				from: { toolName, cardName, path },
+				to: locate(to),
+				onCollision,
+				caller,
+			}),
+		move: (path, to, onCollision) =>
+			cfm.move({
+				from: { toolName, cardName, path },
+				to: locate(to),
+				onCollision,
+				caller,
+			}),
+		watch: (onChange) => cfm.watch(at, onChange),
+		links: (path) => cfm.links({ ...at, path }),
+		backlinks: (path) => cfm.backlinks({ ...at, path }),
+		orphanNotes: () => cfm.orphanNotes(at),
+		brokenLinks: () => cfm.brokenLinks(at),
+		listVersions: (path) => cfm.listVersions({ ...at, path }),
+		diffVersions: (path, from, to) =>
+			cfm.diffVersions({ ...at, path, from, to }),
+		restoreVersion: (path, version) =>
+			cfm.restoreVersion({ ...at, path, version }),
 	};
 	return cardFs;
 }
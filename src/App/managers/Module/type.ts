@@ -69,10 +69,18 @@ export type Events = { domain: "module" } & (
 
 export type ToolMethods = {
 	name: string;
+	/**
+	 * token a plugin's commands are made with, unset for core tools
+	 */
+	caller?: string;
 	onCreate(card: CardType): Promise<PluginResult>;
 	onDelete(card: CardType): Promise<PluginResult>;
 	onLoad(card: CardType): Promise<PluginResult>;
 	onUnload(name: string): Promise<PluginResult>;
+	/**
+	 * removes the frames a plugin runs in, unset for core tools
+	 */
+	close?: () => void;
 	toolEvent: ToolApi;
 };
 export type ToolMap = Map<string, ToolMethods>;
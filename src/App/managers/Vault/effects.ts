@@ -3,9 +3,8 @@ import { VaultItem } from "@type/VaultItem";
 import { Storage } from "@managers/Storage";
 import { join } from "@tauri-apps/api/path";
 import { hollow } from "../../../hollow";
-import { convertFileSrc } from "@tauri-apps/api/core";
 import { getCurrentRealm } from "@shared/managers/Realm";
-import { vault_add, vault_add_url, vault_remove } from "@rust";
+import { realmFileSrc, vault_add, vault_add_url, vault_remove } from "@rust";
 import { _dispatch } from "@shared/store/effects";
 
 let store: Storage | null = null;
@@ -23,10 +22,15 @@ export function setupVault(d: (action: any) => void) {
 			},
 		});
 
+		// urls are derived from paths, stored ones may use an older protocol
+		const items: VaultItem[] = store.get("__root__");
 		_dispatch({
 			domain: "vault",
 			type: "set-items",
-			items: store.get("__root__"),
+			items: items.map((item) => ({
+				...item,
+				url: realmFileSrc(item.path),
+			})),
 		});
 	});
 }
@@ -55,7 +59,7 @@ export async function vaultEffects(action: Events, state: VaultState) {
 				const path = await vault_add_url({
 					url: image,
 				});
-				const url = convertFileSrc(path);
+				const url = realmFileSrc(path);
 				const urlItem: VaultItem = {
 					path,
 					url,
@@ -132,7 +136,7 @@ export async function importFiles(images: string[]) {
 		const asVaultItems: VaultItem[] = addedImagesPaths.map((i) => {
 			return {
 				path: i,
-				url: convertFileSrc(i),
+				url: realmFileSrc(i),
 				name: "unamed",
 				type: i.split(".").pop() ?? "unknown",
 				uploadedAt: new Date(),
@@ -158,7 +162,7 @@ export async function importFileUrl(image: string, name?: string) {
 		const path = await this.managers?.rust.vault_add_url({
 			url: image,
 		});
-		const url = convertFileSrc(path);
+		const url = realmFileSrc(path);
 		const urlItem: VaultItem = {
 			path,
 			url,
@@ -0,0 +1,22 @@
+export type BackupSettings = {
+	enabled: boolean;
+	/**
+	 * absolute folder outside the realm, each realm gets a subfolder
+	 */
+	directory: string | null;
+	intervalMinutes: number;
+	/**
+	 * the newest backup of each of the last `keepDaily` days is kept
+	 */
+	keepDaily: number;
+	keepWeekly: number;
+};
+
+export type BackupInfo = {
+	/**
+	 * file name of the archive
+	 */
+	id: string;
+	created: number;
+	size: number;
+};
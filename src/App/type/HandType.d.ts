@@ -1,4 +1,5 @@
 import { CardType } from "./hollow";
+import { Permission } from "./PluginPermissions";
 
 export type HandType = {
 	version: string;
@@ -12,4 +13,16 @@ export type HandType = {
 	authorUrl: string;
 	cards: CardType[];
 	signed?: boolean;
+	/**
+	 * signed with a key the user trusts
+	 */
+	trusted?: boolean;
+	/**
+	 * minisign public key the plugin is signed with
+	 */
+	publicKey?: string;
+	/**
+	 * capabilities the plugin asks for, granted by the user per realm
+	 */
+	permissions?: Permission[];
 };
@@ -0,0 +1,15 @@
+export type Permission =
+	| "cards:read"
+	| "cards:write"
+	| "vault:read"
+	| "vault:write"
+	| "network"
+	| "notifications";
+
+export type PluginPermissions = {
+	/**
+	 * what the active version's manifest asks for
+	 */
+	requested: Permission[];
+	granted: Permission[];
+};
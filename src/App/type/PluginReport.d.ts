@@ -0,0 +1,52 @@
+import { HandType } from "./HandType";
+
+export type PluginDiagnostic = {
+	severity: "error" | "warning";
+	/**
+	 * manifest field the diagnostic is about
+	 */
+	field: string | null;
+	message: string;
+};
+
+export type PluginReport = {
+	/**
+	 * folder under plugins/
+	 */
+	folder: string;
+	/**
+	 * null unless the manifest passed validation
+	 */
+	manifest: HandType | null;
+	diagnostics: PluginDiagnostic[];
+	valid: boolean;
+	/**
+	 * package.sha256 carries a valid signature and every file matches it
+	 */
+	signed: boolean;
+	/**
+	 * the signature comes from a trusted key
+	 */
+	trusted: boolean;
+	/**
+	 * id of the key that made the signature
+	 */
+	signer: string | null;
+	/**
+	 * realm-relative folder of the active version
+	 */
+	path: string | null;
+};
+
+export type TrustedKey = {
+	/**
+	 * minisign key id
+	 */
+	id: string;
+	name: string;
+	key: string;
+	/**
+	 * the official key, can't be removed
+	 */
+	builtin: boolean;
+};
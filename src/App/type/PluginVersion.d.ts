@@ -0,0 +1,15 @@
+export type PluginVersion = {
+	version: string;
+	active: boolean;
+	/**
+	 * realm-relative folder of the version
+	 */
+	path: string;
+};
+
+export type PluginUpdate = {
+	name: string;
+	installed: string;
+	available: string;
+	repo: string | null;
+};
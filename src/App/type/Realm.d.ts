@@ -9,3 +9,12 @@ export type Realm = {
 		secondary: string;
 	};
 };
+
+export type RealmRegistry = {
+	/**
+	 * id of the realm entered last, null while selecting
+	 */
+	current: string | null;
+	realms: Realm[];
+	selectOnStartup: boolean;
+};
@@ -0,0 +1,23 @@
+export type ArchiveSummary = {
+	/**
+	 * the archive for exports, the new realm for imports
+	 */
+	path: string;
+	files: number;
+	bytes: number;
+};
+
+export type ArchiveProgress = {
+	operation: "export" | "import";
+	done: number;
+	total: number;
+	path: string;
+};
+
+export type ExportOptions = {
+	/**
+	 * globs matched against realm-relative paths like `main/notebook/**`
+	 */
+	include?: string[];
+	exclude?: string[];
+};
@@ -0,0 +1,31 @@
+export type FsckIssueKind =
+	| "untracked_vault_file"
+	| "missing_vault_file"
+	| "incomplete_plugin"
+	| "corrupt_store"
+	| "orphaned_tool_folder";
+
+export type FsckRepairAction =
+	| "move_to_trash"
+	| "remove_vault_entry"
+	| "restore_backup"
+	| "remove_index";
+
+export type FsckIssue = {
+	kind: FsckIssueKind;
+	/**
+	 * relative to the realm
+	 */
+	path: string;
+	message: string;
+	/**
+	 * null when there's no safe automatic fix
+	 */
+	repair: FsckRepairAction | null;
+	repaired: boolean;
+};
+
+export type FsckReport = {
+	issues: FsckIssue[];
+	repaired: number;
+};
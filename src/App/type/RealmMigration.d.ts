@@ -0,0 +1,9 @@
+export type MigrationProgress = {
+	step: number;
+	total: number;
+	/**
+	 * schema version of the realm once the step is done
+	 */
+	version: number;
+	description: string;
+};
@@ -0,0 +1,20 @@
+export type SearchHit = {
+	tool_name: string;
+	card_name: string;
+	/**
+	 * relative to the card root
+	 */
+	path: string;
+	score: number;
+	snippet: string;
+	/**
+	 * 1-based line of the snippet, null when only the file name matched
+	 */
+	line: number | null;
+};
+
+export type SearchOptions = {
+	toolName?: string;
+	cardName?: string;
+	limit?: number;
+};
@@ -0,0 +1,10 @@
+export type TrashItem = {
+	id: string;
+	name: string;
+	/**
+	 * realm-relative path the item was removed from
+	 */
+	origin: string;
+	is_dir: boolean;
+	deleted_at: number;
+};
@@ -10,6 +10,11 @@ export interface PluginResult {
 }
 
 // Represents a plugin with lifecycle methods that interact with cards and the app.
+// Plugins run in sandboxed frames: a card's frame holds a `div` with the card's
+// id, `window.hollow.assets` is the URL of the plugin's folder, and whatever they
+// call on the app or tool events answers with a promise. `fetch` needs the
+// `network` grant and `window.hollow.notify({ title, message })` the
+// `notifications` one.
 export interface IPlugin {
 	/**
 	 * Called when a card is created.
@@ -599,17 +604,223 @@ export interface IStore {
 	close(): Promise<void>;
 }
 
+export type HollowErrorCode =
+	| "realm_not_initialized"
+	| "realm_locked"
+	| "read_only"
+	| "not_found"
+	| "already_exists"
+	| "permission_denied"
+	| "not_granted"
+	| "unknown_caller"
+	| "invalid_path"
+	| "invalid_input"
+	| "parse"
+	| "network"
+	| "partial_failure"
+	| "io"
+	| "internal";
+
+/**
+ * Shape of every error rejected by a backend command.
+ */
+export type HollowError = {
+	code: HollowErrorCode;
+	message: string;
+	path: string | null;
+};
+
 export type DirEntry = {
 	name: string;
 	is_dir: boolean;
+	is_symlink: boolean;
+	size: number;
+	/**
+	 * milliseconds since the unix epoch, null where the platform doesn't track it
+	 */
+	created: number | null;
+	modified: number | null;
+	/**
+	 * guessed from the extension, null for directories
+	 */
+	mime: string | null;
+};
+
+export type WalkOptions = {
+	/**
+	 * 1 lists direct children only, unset walks everything
+	 */
+	maxDepth?: number;
+	/**
+	 * glob patterns like `**\/*.md`, matched against the relative path
+	 */
+	include?: string[];
+	exclude?: string[];
+	includeHidden?: boolean;
+};
+
+export type CardFsEvent = {
+	kind: "created" | "modified" | "removed" | "renamed";
+	/**
+	 * paths relative to the card root
+	 */
+	paths: string[];
 };
 
+export type CardVersion = {
+	id: string;
+	hash: string;
+	size: number;
+	created: number;
+};
+
+export type DiffLine = {
+	tag: "equal" | "insert" | "delete";
+	old_line: number | null;
+	new_line: number | null;
+	value: string;
+};
+
+export type FrontmatterQuery = {
+	/**
+	 * scan subfolders too
+	 */
+	recursive?: boolean;
+	includeBody?: boolean;
+	/**
+	 * attributes that must be set
+	 */
+	has?: string[];
+	/**
+	 * attribute values to match, lists and comma-separated values match any item
+	 */
+	equals?: Record<string, string>;
+};
+
+export type NoteFrontmatter = {
+	/**
+	 * relative to the scanned folder
+	 */
+	path: string;
+	/**
+	 * file name without `.md`
+	 */
+	title: string;
+	attributes: Record<string, any>;
+	frontmatter: string | null;
+	body_begin: number;
+	body_offset: number;
+	/**
+	 * only set with `includeBody`
+	 */
+	body: string | null;
+	/**
+	 * set when the frontmatter doesn't parse
+	 */
+	error: string | null;
+};
+
+export type NoteLink = {
+	/**
+	 * note containing the link
+	 */
+	source: string;
+	/**
+	 * as written, `Note Title` for `[[Note Title]]`
+	 */
+	target: string;
+	kind: "wiki" | "markdown";
+	line: number;
+	/**
+	 * path of the linked note, null when the link is broken
+	 */
+	resolved: string | null;
+};
+
+export type CardLocation = {
+	toolName: string;
+	cardName: string;
+	path: string;
+};
+
+/**
+ * What copy and move do when the target exists, `rename` picks `name (n)`.
+ */
+export type CollisionStrategy = "fail" | "overwrite" | "rename";
+
 export type CardFs = {
 	exists(path: string): Promise<boolean>;
 	readFile(path: string): Promise<string>;
+	readFileBytes(path: string): Promise<Uint8Array>;
+	readFileRange(
+		path: string,
+		offset: number,
+		length: number,
+	): Promise<Uint8Array>;
 	writeFile(path: string, contents: string): Promise<void>;
+	writeFileBytes(path: string, contents: Uint8Array): Promise<void>;
 	mkdir(path: string): Promise<void>;
 	readDir(path?: string): Promise<DirEntry[]>;
+	/**
+	 * Recursive listing, entry names are paths relative to `path`.
+	 */
+	walk(path?: string, options?: WalkOptions): Promise<DirEntry[]>;
+	stat(path: string): Promise<DirEntry>;
+	/**
+	 * Parsed frontmatter of every `.md` file under `path`, filtered by `query`.
+	 */
+	scanFrontmatter(
+		path?: string,
+		query?: FrontmatterQuery,
+	): Promise<NoteFrontmatter[]>;
 	remove(path: string): Promise<void>;
-	rename(path: string, newPath: string): Promise<void>;
+	/**
+	 * With `rewriteLinks`, links in other notes pointing at `path` follow the rename.
+	 */
+	rename(
+		path: string,
+		newPath: string,
+		options?: { rewriteLinks?: boolean },
+	): Promise<void>;
+	/**
+	 * Copies a file or folder. `to` is a path in this card or a location in
+	 * another card. Resolves to where the copy ended up.
+	 */
+	copy(
+		path: string,
+		to: string | CardLocation,
+		onCollision?: CollisionStrategy,
+	): Promise<CardLocation>;
+	move(
+		path: string,
+		to: string | CardLocation,
+		onCollision?: CollisionStrategy,
+	): Promise<CardLocation>;
+	/**
+	 * Subscribes to changes under the card directory.
+	 * Resolves to a function that stops watching.
+	 */
+	watch(onChange: (event: CardFsEvent) => void): Promise<() => Promise<void>>;
+	/**
+	 * `[[wiki]]` and markdown links going out of the note at `path`.
+	 */
+	links(path: string): Promise<NoteLink[]>;
+	/**
+	 * Links in other notes pointing at the note at `path`.
+	 */
+	backlinks(path: string): Promise<NoteLink[]>;
+	/**
+	 * Notes no other note links to.
+	 */
+	orphanNotes(): Promise<string[]>;
+	brokenLinks(): Promise<NoteLink[]>;
+	/**
+	 * Snapshots taken on every write, newest first.
+	 */
+	listVersions(path: string): Promise<CardVersion[]>;
+	/**
+	 * Compares version `from` with `to`, or with the current file when `to` is omitted.
+	 */
+	diffVersions(path: string, from: string, to?: string): Promise<DiffLine[]>;
+	restoreVersion(path: string, version: string): Promise<void>;
 };
@@ -1,11 +1,35 @@
-import { invoke } from "@tauri-apps/api/core";
-import { HandType } from "@type/HandType";
+import { convertFileSrc, invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import { TrashItem } from "@type/TrashItem";
+import { SearchHit, SearchOptions } from "@type/SearchHit";
+import {
+	ArchiveProgress,
+	ArchiveSummary,
+	ExportOptions,
+} from "@type/RealmArchive";
+import { BackupInfo, BackupSettings } from "@type/Backup";
+import { MigrationProgress } from "@type/RealmMigration";
+import { FsckReport } from "@type/RealmFsck";
+import { PluginReport, TrustedKey } from "@type/PluginReport";
+import { PluginUpdate, PluginVersion } from "@type/PluginVersion";
+import { Permission, PluginPermissions } from "@type/PluginPermissions";
+import { Realm, RealmRegistry } from "@type/Realm";
 import { getCurrentWindow } from "@tauri-apps/api/window";
-import { AppApi, CardType, IPlugin, PluginResult, ToolApi } from "@type/hollow";
+import {
+	AppApi,
+	CardType,
+	HollowError,
+	HollowEvent,
+	IPlugin,
+	PluginResult,
+	ToolApi,
+} from "@type/hollow";
+import { HandType } from "@type/HandType";
+import { join } from "@tauri-apps/api/path";
 import { hollow } from "../hollow";
-import { importFileUrl } from "../App/managers/Vault";
-
-const PLUGIN_FILES = ["index.js", "manifest.json", "icon.svg"] as const;
+import { getCurrentRealm } from "@shared/managers/Realm";
+import { PluginFrame } from "./sandbox";
+import { _dispatch } from "@shared/store/effects";
 
 type FetchProps = {
 	url: string;
@@ -18,32 +42,139 @@ type AddPluginProps = {
 	repo: string;
 };
 
+type AddPluginResult =
+	| { state: true; manifest: HandType; icon: string }
+	| { state: false };
+
 type RemovePluginProps = {
 	name: string;
 };
 
 type vaultAddProps = {
 	paths: string[];
+	/**
+	 * token of the plugin the call is made for, needs `vault:write`. The
+	 * app's own token when unset.
+	 */
+	caller?: string;
 };
 
 type vaultRemoveProps = {
 	names: string[];
+	caller?: string;
 };
 
 type startProps = {
 	location: string;
+	/**
+	 * nothing in the realm is written, mutating commands reject with `read_only`
+	 */
+	readOnly?: boolean;
 };
 
+let appCaller: Promise<string> | null = null;
+
+/**
+ * the app's `caller` token. The backend hands it out once per page load, so
+ * it's taken before any plugin code runs
+ */
+export function app_caller(): Promise<string> {
+	appCaller ??= invoke<string>("session_start");
+	return appCaller;
+}
+
+async function withCaller<T extends object>(
+	props: T & { caller?: string },
+): Promise<T & { caller: string }> {
+	return { ...props, caller: props.caller ?? (await app_caller()) };
+}
+
+export function isHollowError(error: unknown): error is HollowError {
+	return (
+		typeof error === "object" &&
+		error !== null &&
+		"code" in error &&
+		"message" in error
+	);
+}
+
 export async function dbg(): Promise<any> {
 	return await invoke("dbg");
 }
 
-export async function start_realm({ location }: startProps) {
+/**
+ * URL of a file in the open realm, served by the backend's `realm` protocol
+ */
+export function realmFileSrc(path: string) {
+	return convertFileSrc(path, "realm");
+}
+
+export async function start_realm({ location, readOnly }: startProps) {
 	return await invoke("start_realm", {
 		location,
+		readOnly,
+		caller: await app_caller(),
 	});
 }
 
+export async function realm_registry(): Promise<RealmRegistry> {
+	return await invoke("realm_registry", await withCaller({}));
+}
+
+export async function realm_add(props: { realm: Realm }): Promise<Realm> {
+	return await invoke("realm_add", await withCaller(props));
+}
+
+/**
+ * Only forgets the realm, its folder is left on disk.
+ */
+export async function realm_remove(props: { id: string }) {
+	return await invoke("realm_remove", await withCaller(props));
+}
+
+export async function realm_rename(props: {
+	id: string;
+	name: string;
+}): Promise<Realm> {
+	return await invoke("realm_rename", await withCaller(props));
+}
+
+/**
+ * Points a realm at the folder it was moved to. Fails for the open realm.
+ */
+export async function realm_relocate(props: {
+	id: string;
+	location: string;
+}): Promise<Realm> {
+	return await invoke("realm_relocate", await withCaller(props));
+}
+
+export async function realm_set_colors(props: {
+	id: string;
+	colors: Realm["colors"];
+}): Promise<Realm> {
+	return await invoke("realm_set_colors", await withCaller(props));
+}
+
+export async function realm_set_select_on_startup(props: { value: boolean }) {
+	return await invoke("realm_set_select_on_startup", await withCaller(props));
+}
+
+/**
+ * Makes the realm current. From the selector window, the main window takes
+ * its place and opens the realm.
+ */
+export async function realm_enter(props: { id: string }): Promise<Realm> {
+	return await invoke("realm_enter", await withCaller(props));
+}
+
+/**
+ * Closes the open realm and replaces the main window with the selector.
+ */
+export async function open_realm_selector() {
+	return await invoke("open_realm_selector", await withCaller({}));
+}
+
 export async function first_launch() {
 	invoke("first_launch");
 }
@@ -61,15 +192,45 @@ export async function get_platform(): Promise<string> {
 	return await invoke("get_platform");
 }
 
-export async function get_unsigned_plugins(): Promise<HandType[]> {
-	const result: HandType[] = await invoke("get_unsigned_plugins");
-	return [
-		...result.map((manifest) => ({
-			...manifest,
-			name: manifest.name.toLowerCase(),
-			signed: false,
-		})),
-	];
+function withManifestStatus(report: PluginReport): PluginReport {
+	return {
+		...report,
+		manifest: report.manifest && {
+			...report.manifest,
+			name: report.manifest.name.toLowerCase(),
+			// a valid signature from an untrusted key is no better than none
+			signed: report.trusted,
+			trusted: report.trusted,
+		},
+	};
+}
+
+export async function get_unsigned_plugins(): Promise<PluginReport[]> {
+	const result: PluginReport[] = await invoke(
+		"get_unsigned_plugins",
+		await withCaller({}),
+	);
+	return result.map(withManifestStatus);
+}
+
+export async function plugin_trusted_keys(): Promise<TrustedKey[]> {
+	return await invoke("plugin_trusted_keys", await withCaller({}));
+}
+
+/**
+ * @param key the public key line or the whole minisign `.pub` file
+ */
+export async function plugin_trust_key(props: {
+	name: string;
+	key: string;
+}): Promise<TrustedKey> {
+	return await invoke("plugin_trust_key", await withCaller(props));
+}
+
+export async function plugin_untrust_key(props: {
+	id: string;
+}): Promise<void> {
+	return await invoke("plugin_untrust_key", await withCaller(props));
 }
 
 export async function fetch({
@@ -91,106 +252,492 @@ export async function fetch({
 	}
 }
 
-export async function add_plugin({ name, repo }: AddPluginProps): Promise<any> {
-	const result = {
-		state: true,
-		manifest: "",
-		icon: "",
-	};
-	for (const file of PLUGIN_FILES) {
-		try {
-			const url = `https://raw.githubusercontent.com/${repo}/main/${file}`;
-			if (file === "icon.svg") {
-				result.icon = await importFileUrl(url);
-				continue;
-			}
-			const response = await globalThis.fetch(url, {
-				headers: {
-					Accept: "application/vnd.github.v3+json",
-				},
-			});
-
-			const data = await response.text();
-
-			// make file
-			if (file === "manifest.json") {
-				result.manifest = data;
-			} else if (file === "index.js") {
-				invoke("add_plugin", {
-					pluginName: name.toLowerCase(),
-					content: data,
-				});
-			}
-		} catch (error) {
-			console.error("An error occurred:", error);
-			return { state: false };
-		}
+/**
+ * installs the latest release of a plugin repo, its `<name>.hollowplugin`
+ * asset goes through the same checks as `plugin_install`
+ */
+export async function add_plugin({
+	name,
+	repo,
+}: AddPluginProps): Promise<AddPluginResult> {
+	try {
+		const report = await plugin_install({
+			source: `https://github.com/${repo}/releases/latest/download/${name.toLowerCase()}.hollowplugin`,
+		});
+		if (!report.manifest || !report.path) return { state: false };
+		const icon = realmFileSrc(
+			await join(getCurrentRealm().location, report.path, "icon.svg"),
+		);
+		return { state: true, manifest: report.manifest, icon };
+	} catch (error) {
+		console.error(`Could not install ${name}:`, error);
+		return { state: false };
 	}
-	return result;
+}
+
+/**
+ * installs a `.hollowplugin` package, every file in it ends up in
+ * `plugins/<name>/<version>/` or nothing changes
+ * @param source local path or http(s) URL of the package
+ */
+export async function plugin_install(props: {
+	source: string;
+}): Promise<PluginReport> {
+	return withManifestStatus(
+		await invoke("plugin_install", await withCaller(props)),
+	);
+}
+
+/**
+ * @returns realm-relative folder of the active version,
+ * e.g. `plugins/name/1.0.0`
+ */
+export async function plugin_active_dir(props: {
+	name: string;
+}): Promise<string> {
+	return await invoke("plugin_active_dir", await withCaller(props));
+}
+
+/**
+ * installed versions, newest first
+ */
+export async function plugin_versions(props: {
+	name: string;
+}): Promise<PluginVersion[]> {
+	return await invoke("plugin_versions", await withCaller(props));
+}
+
+export async function plugin_switch_version(props: {
+	name: string;
+	version: string;
+}): Promise<void> {
+	return await invoke("plugin_switch_version", await withCaller(props));
+}
+
+/**
+ * @returns the version now active
+ */
+export async function plugin_rollback(props: {
+	name: string;
+}): Promise<string> {
+	return await invoke("plugin_rollback", await withCaller(props));
+}
+
+/**
+ * removes old versions, never the active one or the rollback target
+ * @returns the removed versions
+ */
+export async function plugin_prune(props: {
+	name: string;
+	keep?: number;
+}): Promise<string[]> {
+	return await invoke("plugin_prune", await withCaller(props));
+}
+
+/**
+ * Compares the installed plugins with the official registry index
+ */
+export async function plugin_check_updates(): Promise<PluginUpdate[]> {
+	return await invoke("plugin_check_updates", await withCaller({}));
+}
+
+export async function plugin_permissions(props: {
+	name: string;
+}): Promise<PluginPermissions> {
+	return await invoke("plugin_permissions", await withCaller(props));
+}
+
+/**
+ * @param granted replaces the current grants, each must be requested
+ */
+export async function plugin_set_permissions(props: {
+	name: string;
+	granted: Permission[];
+}): Promise<PluginPermissions> {
+	return await invoke("plugin_set_permissions", await withCaller(props));
 }
 
 export async function remove_plugin({ name }: RemovePluginProps): Promise<any> {
-	return await invoke("remove_plugin", { name });
+	return await invoke("remove_plugin", await withCaller({ name }));
+}
+
+// app events plugins may emit, the rest belong to the app. `add-layout` isn't
+// one, it hands out an element of the app's page
+const PLUGIN_EMITS = new Set<string>([
+	"form",
+	"confirm",
+	"alert",
+	"insight",
+	"tool-settings",
+	"color-picker",
+	"date-picker",
+	"emoji-picker",
+	"show-vault",
+	"context-menu-extend",
+	"render-markdown",
+	"character-add-achievement",
+	"character-add-xp",
+]);
+// and the ones they may listen to
+const PLUGIN_LISTENS = new Set<string>(["network-state"]);
+
+/**
+ * the part of `hollow.events` a plugin gets, so it can't listen in on other
+ * plugins' forms or reach app-only events like `store`
+ */
+function pluginEvents(name: string): AppApi {
+	const events = hollow.events as any;
+	const allowed = (set: Set<string>, eventName: unknown) => {
+		if (typeof eventName === "string" && set.has(eventName)) return true;
+		console.warn(`${name} can't use the ${String(eventName)} event`);
+		return false;
+	};
+	return {
+		on: (eventName, listener) => {
+			allowed(PLUGIN_LISTENS, eventName) && events.on(eventName, listener);
+		},
+		off: (eventName, listener) => {
+			allowed(PLUGIN_LISTENS, eventName) &&
+				events.off(eventName, listener);
+		},
+		emit: (eventName, data) =>
+			allowed(PLUGIN_EMITS, eventName)
+				? events.emit(eventName, data)
+				: undefined,
+		getData: (eventName) =>
+			allowed(PLUGIN_LISTENS, eventName)
+				? events.getData?.(eventName)
+				: undefined,
+		// other listeners of the event aren't the plugin's to drop
+		clear: (eventName) => {
+			console.warn(`${name} can't clear the ${String(eventName)} event`);
+		},
+		toggle: (eventName) => {
+			allowed(PLUGIN_EMITS, eventName) && events.toggle?.(eventName);
+		},
+	};
+}
+
+/**
+ * rejects like the backend's `not_granted` unless the user granted it
+ */
+async function ensureGranted(name: string, permission: Permission) {
+	const { granted } = await plugin_permissions({ name });
+	if (!granted.includes(permission)) {
+		console.warn(`Plugin ${name} was refused ${permission}`);
+		const error: HollowError = {
+			code: "not_granted",
+			message: `Plugin ${name} has not been granted ${permission}`,
+			path: null,
+		};
+		throw error;
+	}
+}
+
+type FetchRequest = {
+	method: string;
+	headers: [string, string][];
+	/**
+	 * base64 encoded
+	 */
+	body?: string;
+};
+
+/**
+ * made by the backend for a plugin's `fetch`, needs `network`
+ */
+export async function plugin_fetch(props: {
+	url: string;
+	request: FetchRequest;
+	caller: string;
+}): Promise<{
+	url: string;
+	status: number;
+	statusText: string;
+	headers: [string, string][];
+	body: string;
+}> {
+	return await invoke("plugin_fetch", props);
+}
+
+/**
+ * what a plugin's frame may call: its filtered `hollow.events`, its tool's
+ * events, `fetch` and notifications. Listeners it added are dropped with the
+ * frame
+ */
+function frameApi(name: string, caller: string, toolEvent: ToolApi) {
+	const scopes = { app: pluginEvents(name), tool: toolEvent };
+	const listening: [HollowEvent, string, (data: any) => any][] = [];
+	const api: Record<string, Function> = {};
+	for (const [scope, events] of Object.entries(scopes)) {
+		api[`${scope}.on`] = (eventName: string, listener: () => any) => {
+			listening.push([events, eventName, listener]);
+			events.on(eventName, listener);
+		};
+		for (const method of ["off", "emit", "getData", "clear", "toggle"]) {
+			api[`${scope}.${method}`] = (...args: unknown[]) =>
+				events[method](...args);
+		}
+	}
+	api.fetch = (url: string, request: FetchRequest) =>
+		plugin_fetch({ url, request, caller });
+	api.notify = async (notification: { title: string; message: string }) => {
+		await ensureGranted(name, "notifications");
+		_dispatch({
+			domain: "notifications",
+			type: "add-notification",
+			notification: {
+				id: `${name}-${crypto.randomUUID()}`,
+				title: String(notification.title),
+				message: String(notification.message),
+				submitted_at: new Date().toISOString(),
+			},
+		});
+	};
+	const release = () => {
+		for (const [events, eventName, listener] of listening) {
+			events.off(eventName, listener);
+		}
+	};
+	return { api, release };
 }
 
+/**
+ * the backend checks the active version's signature before handing the code
+ * over, untrusted code only loads with `allowUntrusted`. The plugin runs in
+ * sandboxed frames: a hidden one for creating and deleting cards and one in
+ * each loaded card's container
+ * @returns the plugin and the `caller` token its commands are made with
+ */
 export async function load_plugin({
-	semiPath,
+	name,
+	allowUntrusted,
 	toolEvent,
 }: {
-	semiPath: string;
+	name: string;
+	allowUntrusted: boolean;
 	toolEvent: ToolApi;
-}): Promise<IPlugin | null> {
-	//
-	const indexJS: string = await invoke("read_file", {
-		path: semiPath,
+}): Promise<(IPlugin & { caller: string; close: () => void }) | null> {
+	let code: string;
+	let caller: string;
+	let assets: string;
+	try {
+		let token: string;
+		({ code, caller, assets: token } = await invoke<{
+			code: string;
+			caller: string;
+			assets: string;
+		}>("plugin_code", await withCaller({ name, allowUntrusted })));
+		// the realm protocol only serves the plugin's files by this token
+		assets = `${realmFileSrc("")}plugin/${token}/`;
+	} catch (error) {
+		console.error(`Refused to load ${name}:`, error);
+		return null;
+	}
+
+	const open = async (parent: HTMLElement, card?: string) => {
+		const { api, release } = frameApi(name, caller, toolEvent);
+		const frame = new PluginFrame(name, parent, api, release);
+		try {
+			await frame.start({ code, card, assets });
+		} catch (error) {
+			frame.destroy();
+			throw error;
+		}
+		return frame;
+	};
+	const failed = (error: any): PluginResult => ({
+		status: false,
+		message: error?.message ?? String(error),
 	});
-	if (indexJS !== "none") {
-		const pluginWrapper = new Function("exports", "module", indexJS);
-		const module = {
-			exports: {} as {
-				default: new (app: AppApi, toolEvent: ToolApi) => IPlugin;
-			},
-		};
-		pluginWrapper(module.exports, module);
-		const PluginClass = module.exports.default;
-		const instance: IPlugin = new PluginClass(hollow.events, toolEvent);
-		return {
-			onCreate: (card: CardType): Promise<PluginResult> => {
-				return instance.onCreate(card);
-			},
-			onDelete: (card: CardType): Promise<PluginResult> => {
-				return instance.onDelete(card);
-			},
-			onLoad: (card_info: CardType): Promise<PluginResult> => {
-				return instance.onLoad(card_info);
-			},
-			onUnload: (id: string): Promise<PluginResult> => {
-				return instance.onUnload(id);
-			},
-		};
+	const hook = (frame: PluginFrame, hook: keyof IPlugin, arg: unknown) =>
+		frame.call("hook", [hook, arg]).catch(failed) as Promise<PluginResult>;
+
+	const hidden = document.createElement("div");
+	hidden.hidden = true;
+	document.body.append(hidden);
+	let background: PluginFrame;
+	try {
+		background = await open(hidden);
+	} catch (error) {
+		hidden.remove();
+		console.error(`Failed to start ${name}:`, error);
+		return null;
 	}
-	return null;
+	const cards = new Map<string, PluginFrame>();
+
+	return {
+		caller,
+		onCreate: (card: CardType) => hook(background, "onCreate", card),
+		onDelete: (card: CardType) => hook(background, "onDelete", card),
+		onLoad: async (card: CardType): Promise<PluginResult> => {
+			const container = document.getElementById(card.id);
+			if (!container) {
+				return {
+					status: false,
+					message: `DOM container not found for card id: ${card.id}`,
+				};
+			}
+			if (!cards.has(card.id)) {
+				try {
+					cards.set(card.id, await open(container, card.id));
+				} catch (error) {
+					return failed(error);
+				}
+			}
+			return hook(cards.get(card.id), "onLoad", card);
+		},
+		onUnload: async (id: string): Promise<PluginResult> => {
+			const frame = cards.get(id);
+			if (!frame) return { status: true };
+			cards.delete(id);
+			const result = await hook(frame, "onUnload", id);
+			frame.destroy();
+			return result;
+		},
+		close: () => {
+			for (const frame of cards.values()) frame.destroy();
+			cards.clear();
+			background.destroy();
+			hidden.remove();
+		},
+	};
 }
 
 export async function vault_add(props: vaultAddProps): Promise<string[]> {
-	return await invoke("vault_add", props);
+	return await invoke("vault_add", await withCaller(props));
 }
 export async function vault_remove(props: vaultRemoveProps): Promise<string> {
-	return await invoke("vault_remove", props);
+	return await invoke("vault_remove", await withCaller(props));
+}
+
+export async function vault_add_url(props: {
+	url: string;
+	caller?: string;
+}): Promise<string> {
+	return await invoke("vault_add_url", await withCaller(props));
+}
+
+export async function trash_list(): Promise<TrashItem[]> {
+	return await invoke("trash_list", await withCaller({}));
+}
+
+export async function trash_restore(props: { id: string }): Promise<TrashItem> {
+	return await invoke("trash_restore", await withCaller(props));
+}
+
+export async function trash_empty(props: { ids?: string[] } = {}) {
+	await invoke("trash_empty", await withCaller(props));
+}
+
+export async function trash_set_retention(props: { days: number }) {
+	await invoke("trash_set_retention", await withCaller(props));
+}
+
+/**
+ * plugins without `cards:read` only find their own tool's cards
+ */
+export async function search_cards(props: {
+	query: string;
+	options?: SearchOptions;
+	caller?: string;
+}): Promise<SearchHit[]> {
+	return await invoke("search_cards", await withCaller(props));
+}
+
+export async function search_rebuild() {
+	await invoke("search_rebuild", await withCaller({}));
+}
+
+export async function realm_export(props: {
+	destination: string;
+	options?: ExportOptions;
+}): Promise<ArchiveSummary> {
+	return await invoke("realm_export", await withCaller(props));
+}
+
+/**
+ * The new realm isn't opened, pass its location to `start_realm` afterwards.
+ */
+export async function realm_import(props: {
+	archive: string;
+	location: string;
+}): Promise<ArchiveSummary> {
+	return await invoke("realm_import", await withCaller(props));
+}
+
+export async function onArchiveProgress(
+	callback: (progress: ArchiveProgress) => void,
+) {
+	return await listen<ArchiveProgress>("realm-archive-progress", (event) =>
+		callback(event.payload),
+	);
+}
+
+export async function onMigrationProgress(
+	callback: (progress: MigrationProgress) => void,
+) {
+	return await listen<MigrationProgress>(
+		"realm-migration-progress",
+		(event) => callback(event.payload),
+	);
+}
+
+/**
+ * Fires when another process took over the open realm's lock, writes from
+ * here may clobber its changes.
+ */
+export async function onRealmLockLost(callback: (lockPath: string) => void) {
+	return await listen<string>("realm-lock-lost", (event) =>
+		callback(event.payload),
+	);
+}
+
+export async function backup_get_settings(): Promise<BackupSettings> {
+	return await invoke("backup_get_settings", await withCaller({}));
+}
+
+export async function backup_set_settings(props: {
+	settings: BackupSettings;
+}) {
+	await invoke("backup_set_settings", await withCaller(props));
+}
+
+export async function backup_now(): Promise<BackupInfo> {
+	return await invoke("backup_now", await withCaller({}));
+}
+
+export async function backup_list(): Promise<BackupInfo[]> {
+	return await invoke("backup_list", await withCaller({}));
+}
+
+/**
+ * Restores into a new realm at `location`, the open realm is left as is.
+ */
+export async function backup_restore(props: {
+	id: string;
+	location: string;
+}): Promise<ArchiveSummary> {
+	return await invoke("backup_restore", await withCaller(props));
 }
 
-export async function vault_add_url(props: { url: string }): Promise<string> {
-	return await invoke("vault_add_url", props);
+/**
+ * Stores may change on disk when repairing, reload the realm afterwards.
+ */
+export async function realm_fsck(props?: {
+	repair?: boolean;
+}): Promise<FsckReport> {
+	return await invoke("realm_fsck", await withCaller({ ...props }));
 }
 
 export async function create_dir(path: string) {
 	const relativePath = getRelativePath(path);
-	await invoke("create_dir", { paths: [relativePath] });
+	await invoke("create_dir", await withCaller({ paths: [relativePath] }));
 }
 
 export async function remove_dir(path: string) {
 	const relativePath = getRelativePath(path);
-	await invoke("remove_dir", { path: relativePath });
+	await invoke("remove_dir", await withCaller({ path: relativePath }));
 }
 
 function getRelativePath(path: string): string {
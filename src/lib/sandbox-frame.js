@@ -0,0 +1,193 @@
+// Runs inside a plugin's sandboxed frame, before the plugin's code. It's
+// shipped as plain text into the frame's srcdoc, so it stays plain JS.
+(() => {
+	"use strict";
+	const FN = "__hollowFn";
+	const host = window.parent;
+
+	// the frame has no same-origin access to the app, drop whatever IPC
+	// globals the webview may still have injected into it
+	for (const name of [
+		"__TAURI_INTERNALS__",
+		"__TAURI__",
+		"__TAURI_IPC__",
+		"__TAURI_METADATA__",
+		"__TAURI_EVENT_PLUGIN_INTERNALS__",
+		"ipc",
+	]) {
+		try {
+			delete window[name];
+		} catch {}
+	}
+
+	const exposed = new Map();
+	const exposedIds = new WeakMap();
+	const proxies = new Map();
+	const pending = new Map();
+	let nextId = 0;
+
+	const post = (message) => host.postMessage(message, "*");
+
+	function expose(fn, id) {
+		let key = id ?? exposedIds.get(fn);
+		if (key === undefined) {
+			key = `frame:${nextId++}`;
+			exposedIds.set(fn, key);
+		}
+		exposed.set(key, fn);
+		return key;
+	}
+
+	function encode(value, seen = new Map()) {
+		if (typeof value === "function") return { [FN]: expose(value) };
+		if (value === null || typeof value !== "object") return value;
+		if (
+			value instanceof ArrayBuffer ||
+			ArrayBuffer.isView(value) ||
+			value instanceof Blob ||
+			value instanceof Date
+		) {
+			return value;
+		}
+		if (value instanceof Error) {
+			return { name: value.name, message: value.message };
+		}
+		if (value instanceof Node) return undefined;
+		if (seen.has(value)) return seen.get(value);
+		if (Array.isArray(value)) {
+			const out = [];
+			seen.set(value, out);
+			for (const item of value) out.push(encode(item, seen));
+			return out;
+		}
+		const out = {};
+		seen.set(value, out);
+		for (const key of Object.keys(value)) {
+			out[key] = encode(value[key], seen);
+		}
+		return out;
+	}
+
+	function decode(value) {
+		if (value === null || typeof value !== "object") return value;
+		if (Array.isArray(value)) return value.map(decode);
+		if (Object.getPrototypeOf(value) !== Object.prototype) return value;
+		if (typeof value[FN] === "string") {
+			const id = value[FN];
+			if (!proxies.has(id)) {
+				proxies.set(id, (...args) => call(id, args));
+			}
+			return proxies.get(id);
+		}
+		const out = {};
+		for (const key of Object.keys(value)) out[key] = decode(value[key]);
+		return out;
+	}
+
+	function call(fn, args) {
+		const id = nextId++;
+		return new Promise((resolve, reject) => {
+			pending.set(id, { resolve, reject });
+			post({ type: "call", id, fn, args: encode(args) });
+		});
+	}
+
+	window.addEventListener("message", (event) => {
+		if (event.source !== host) return;
+		const message = event.data;
+		if (message?.type === "call") {
+			const fn = exposed.get(message.fn);
+			Promise.resolve()
+				.then(() => {
+					if (!fn) throw new Error(`Unknown function ${message.fn}`);
+					return fn(...decode(message.args));
+				})
+				.then(
+					(value) =>
+						post({
+							type: "result",
+							id: message.id,
+							value: encode(value),
+						}),
+					(error) =>
+						post({
+							type: "result",
+							id: message.id,
+							error: encode(error),
+						}),
+				);
+		} else if (message?.type === "result") {
+			const call = pending.get(message.id);
+			if (!call) return;
+			pending.delete(message.id);
+			if ("error" in message) call.reject(decode(message.error));
+			else call.resolve(decode(message.value));
+		}
+	});
+
+	const events = (scope) =>
+		Object.fromEntries(
+			["on", "off", "emit", "getData", "clear", "toggle"].map(
+				(method) => [
+					method,
+					(...args) => call(`${scope}.${method}`, args),
+				],
+			),
+		);
+
+	const toBase64 = (buffer) => {
+		const bytes = new Uint8Array(buffer);
+		let binary = "";
+		for (let i = 0; i < bytes.length; i += 0x8000) {
+			binary += String.fromCharCode(...bytes.subarray(i, i + 0x8000));
+		}
+		return btoa(binary);
+	};
+	const fromBase64 = (text) =>
+		Uint8Array.from(atob(text), (char) => char.charCodeAt(0));
+
+	// the frame can't reach the network, the backend makes the request once
+	// the plugin has the `network` grant
+	window.fetch = async (input, init) => {
+		const request = new Request(input, init);
+		const body = ["GET", "HEAD"].includes(request.method)
+			? undefined
+			: toBase64(await request.arrayBuffer());
+		const response = await call("fetch", [
+			request.url,
+			{ method: request.method, headers: [...request.headers], body },
+		]);
+		const empty = [101, 204, 205, 304].includes(response.status);
+		return new Response(empty ? null : fromBase64(response.body), {
+			status: response.status,
+			statusText: response.statusText,
+			headers: response.headers,
+		});
+	};
+
+	let plugin = null;
+
+	expose(({ code, card, assets, theme }) => {
+		for (const [name, value] of Object.entries(theme)) {
+			document.documentElement.style.setProperty(name, value);
+		}
+		if (card) {
+			const root = document.createElement("div");
+			root.id = card;
+			document.body.append(root);
+		}
+		window.hollow = {
+			assets,
+			// needs the `notifications` grant
+			notify: (notification) => call("notify", [notification]),
+		};
+		const module = { exports: {} };
+		new Function("exports", "module", code)(module.exports, module);
+		const Plugin = module.exports.default;
+		plugin = new Plugin(events("app"), events("tool"));
+	}, "init");
+
+	expose((hook, arg) => plugin[hook](arg), "hook");
+
+	post({ type: "ready" });
+})();
@@ -0,0 +1,275 @@
+import FRAME_RUNTIME from "./sandbox-frame.js?raw";
+import { Storage } from "@managers/Storage";
+import { convertFileSrc } from "@tauri-apps/api/core";
+
+// marks a function passed by reference, the other side calls it by its id
+const FN = "__hollowFn";
+// the `IStore` methods a plugin gets of its tool's stores
+const STORE_METHODS = [
+	"getData",
+	"get",
+	"set",
+	"setMany",
+	"remove",
+	"keys",
+	"save",
+	"reload",
+	"close",
+] as const;
+
+const FRAME_STYLE =
+	"html,body,body>div{margin:0;width:100%;height:100%}" +
+	"html,body{background:transparent}";
+
+type Message =
+	| { type: "ready" }
+	| { type: "call"; id: number; fn: string; args: unknown[] }
+	| { type: "result"; id: number; value?: unknown; error?: unknown };
+
+const frames = new Map<MessageEventSource, PluginFrame>();
+
+window.addEventListener("message", (event) => {
+	const frame = event.source && frames.get(event.source);
+	frame?.receive(event.data);
+});
+
+/**
+ * the frame's CSP: no requests besides realm files and no way back into the
+ * app, everything else goes through the functions the frame is handed
+ */
+function policy() {
+	const realm = new URL(convertFileSrc("", "realm"));
+	const files = `${realm.protocol}//${realm.host}`;
+	return [
+		"default-src 'none'",
+		"script-src 'unsafe-inline' 'unsafe-eval'",
+		`style-src 'unsafe-inline' ${files}`,
+		`img-src data: blob: ${files}`,
+		`font-src data: ${files}`,
+		`media-src blob: ${files}`,
+	].join("; ");
+}
+
+/**
+ * CSS variables of the app's theme, so plugin UIs can keep following it
+ */
+function theme(): Record<string, string> {
+	const style = getComputedStyle(document.documentElement);
+	const vars: Record<string, string> = {};
+	for (const name of Array.from(style)) {
+		if (name.startsWith("--")) {
+			vars[name] = style.getPropertyValue(name);
+		}
+	}
+	return vars;
+}
+
+/**
+ * one plugin instance in a sandboxed frame. The frame has an opaque origin,
+ * so the plugin can't reach the app's page, its IPC or the app's token; it
+ * only gets plain data and the functions it's handed in `api`
+ */
+export class PluginFrame {
+	private iframe: HTMLIFrameElement;
+	private loaded: Promise<void>;
+	private onReady: () => void;
+	private exposed = new Map<string, Function>();
+	private exposedIds = new WeakMap<Function, string>();
+	private proxies = new Map<string, Function>();
+	private pending = new Map<
+		number,
+		{ resolve: (value: unknown) => void; reject: (error: unknown) => void }
+	>();
+	private nextId = 0;
+	private destroyed = false;
+
+	/**
+	 * @param api functions the frame may call, by name
+	 * @param release undoes what the frame did through `api`, on `destroy`
+	 */
+	constructor(
+		private name: string,
+		parent: HTMLElement,
+		api: Record<string, Function>,
+		private release: () => void,
+	) {
+		for (const [id, fn] of Object.entries(api)) {
+			this.exposed.set(id, fn);
+		}
+		this.loaded = new Promise((resolve) => (this.onReady = resolve));
+		this.iframe = document.createElement("iframe");
+		this.iframe.setAttribute("sandbox", "allow-scripts");
+		this.iframe.srcdoc = [
+			"<!doctype html>",
+			'<meta charset="utf-8">',
+			`<meta http-equiv="Content-Security-Policy" content="${policy()}">`,
+			`<style>${FRAME_STYLE}</style>`,
+			`<script>${FRAME_RUNTIME}</script>`,
+		].join("");
+		this.iframe.style.cssText =
+			"width:100%;height:100%;border:0;background:transparent";
+		// only the srcdoc loads, a frame that navigates away is no longer the
+		// plugin's and mustn't keep its functions
+		let loads = 0;
+		this.iframe.addEventListener("load", () => {
+			if (++loads > 1) {
+				console.warn(`${name} navigated its frame away, dropped it`);
+				this.destroy();
+			}
+		});
+		parent.append(this.iframe);
+		frames.set(this.iframe.contentWindow, this);
+	}
+
+	/**
+	 * runs the plugin's code in the frame
+	 * @param card id of the container the plugin renders the card into
+	 */
+	async start(props: { code: string; card?: string; assets: string }) {
+		await this.loaded;
+		await this.call("init", [{ ...props, theme: theme() }]);
+	}
+
+	call(fn: string, args: unknown[]): Promise<any> {
+		if (this.destroyed) {
+			return Promise.reject(new Error(`${this.name} was unloaded`));
+		}
+		const id = this.nextId++;
+		return new Promise((resolve, reject) => {
+			this.pending.set(id, { resolve, reject });
+			this.iframe.contentWindow?.postMessage(
+				{ type: "call", id, fn, args: this.encode(args) },
+				"*",
+			);
+		});
+	}
+
+	destroy() {
+		if (this.destroyed) return;
+		this.destroyed = true;
+		this.release();
+		frames.delete(this.iframe.contentWindow);
+		this.exposed.clear();
+		this.iframe.remove();
+		for (const { reject } of this.pending.values()) {
+			reject(new Error(`${this.name} was unloaded`));
+		}
+		this.pending.clear();
+	}
+
+	receive(message: Message) {
+		switch (message?.type) {
+			case "ready":
+				this.onReady();
+				break;
+			case "call": {
+				const fn = this.exposed.get(message.fn);
+				Promise.resolve()
+					.then(() => {
+						if (!fn) {
+							throw new Error(`Unknown function ${message.fn}`);
+						}
+						return fn(...this.decode(message.args));
+					})
+					.then(
+						(value) => this.reply(message.id, { value }),
+						(error) => this.reply(message.id, { error }),
+					);
+				break;
+			}
+			case "result": {
+				const call = this.pending.get(message.id);
+				if (!call) return;
+				this.pending.delete(message.id);
+				if ("error" in message) {
+					call.reject(this.decode(message.error));
+				} else {
+					call.resolve(this.decode(message.value));
+				}
+				break;
+			}
+		}
+	}
+
+	private reply(id: number, result: { value?: unknown; error?: unknown }) {
+		const message =
+			"error" in result
+				? { type: "result", id, error: this.encode(result.error) }
+				: { type: "result", id, value: this.encode(result.value) };
+		this.iframe.contentWindow?.postMessage(message, "*");
+	}
+
+	private expose(fn: Function): string {
+		let id = this.exposedIds.get(fn);
+		if (id === undefined) {
+			id = `app:${this.nextId++}`;
+			this.exposedIds.set(fn, id);
+			this.exposed.set(id, fn);
+		}
+		return id;
+	}
+
+	/**
+	 * plain data and functions cross into the frame. Of the app's objects only
+	 * stores are handed over, as their `IStore` methods; elements of the
+	 * app's page and anything else are dropped
+	 */
+	private encode(value: unknown, seen = new Map<object, unknown>()): unknown {
+		if (typeof value === "function") return { [FN]: this.expose(value) };
+		if (value === null || typeof value !== "object") return value;
+		if (
+			value instanceof ArrayBuffer ||
+			ArrayBuffer.isView(value) ||
+			value instanceof Blob ||
+			value instanceof Date
+		) {
+			return value;
+		}
+		if (value instanceof Error) {
+			return { name: value.name, message: value.message };
+		}
+		if (seen.has(value)) return seen.get(value);
+		if (value instanceof Storage) {
+			const store = {};
+			seen.set(value, store);
+			for (const method of STORE_METHODS) {
+				store[method] = this.encode(value[method].bind(value), seen);
+			}
+			return store;
+		}
+		if (Array.isArray(value)) {
+			const out: unknown[] = [];
+			seen.set(value, out);
+			for (const item of value) out.push(this.encode(item, seen));
+			return out;
+		}
+		const proto = Object.getPrototypeOf(value);
+		if (proto !== Object.prototype && proto !== null) return undefined;
+		const out = {};
+		seen.set(value, out);
+		for (const key of Object.keys(value)) {
+			out[key] = this.encode(value[key], seen);
+		}
+		return out;
+	}
+
+	private decode(value: unknown): any {
+		if (value === null || typeof value !== "object") return value;
+		if (Array.isArray(value)) return value.map((item) => this.decode(item));
+		if (Object.getPrototypeOf(value) !== Object.prototype) return value;
+		const id = value[FN];
+		if (typeof id === "string") {
+			if (!this.proxies.has(id)) {
+				this.proxies.set(id, (...args: unknown[]) =>
+					this.call(id, args),
+				);
+			}
+			return this.proxies.get(id);
+		}
+		const out = {};
+		for (const key of Object.keys(value)) {
+			out[key] = this.decode(value[key]);
+		}
+		return out;
+	}
+}
@@ -1,35 +1,20 @@
 import { Events, RealmState } from "./types";
-import { appConfigDir, join } from "@tauri-apps/api/path";
-import { invoke } from "@tauri-apps/api/core";
-import { getCurrentWindow } from "@tauri-apps/api/window";
 import { Realm } from "@type/Realm";
-import { Storage } from "@managers/Storage";
 import { useColor } from "@hooks/useColor";
 import { hollow } from "../../../hollow";
-import { _dispatch } from "@shared/store/effects";
+import { _dispatch, _state } from "@shared/store/effects";
+import {
+	open_realm_selector,
+	realm_add,
+	realm_enter,
+	realm_registry,
+	realm_remove,
+	realm_set_colors,
+	realm_set_select_on_startup,
+} from "@rust";
 
-type RealmsConfig = {
-	current: string | null;
-	realms: Realm[];
-	selectOnStartup: boolean;
-};
-
-let store: Storage | null = null;
-
-export async function setupRealm(d: (action: any) => void) {
-	const path = await join(...[await appConfigDir(), "realms.json"]);
-	store = await Storage.create({
-		path,
-		options: {
-			defaults: {
-				current: null,
-				realms: [],
-				selectOnStartup: false,
-			},
-		},
-	});
-
-	const data = store.getData() as RealmsConfig;
+async function loadRegistry() {
+	const data = await realm_registry();
 	_dispatch({
 		domain: "realm",
 		type: "load-realms",
@@ -37,24 +22,44 @@ export async function setupRealm(d: (action: any) => void) {
 	});
 }
 
+export async function setupRealm(d: (action: any) => void) {
+	await loadRegistry();
+}
+
+async function persist(action: Events, state: RealmState) {
+	switch (action.type) {
+		case "add-realm":
+			await realm_add({ realm: action.realm });
+			break;
+		case "remove-realm":
+			await realm_remove({ id: action.realmId });
+			break;
+		case "enter-realm":
+			await realm_enter({ id: action.realmId });
+			break;
+		case "update-colors":
+			if (!state.current) return;
+			await realm_set_colors({
+				id: state.current.id,
+				colors: state.current.colors,
+			});
+			break;
+		case "set-select-on-startup":
+			await realm_set_select_on_startup({ value: action.value });
+			break;
+	}
+}
+
 export async function realmEffects(action: Events, state: RealmState) {
 	if (action.domain !== "realm") return;
 
-	// Sync to storage for relevant actions
-	if (
-		[
-			"set-select-on-startup",
-			"enter-realm",
-			"add-realm",
-			"remove-realm",
-			"update-colors",
-		].includes(action.type)
-	) {
-		store?.setMany({
-			current: state.current?.id || null,
-			realms: state.realms,
-			selectOnStartup: state.selectOnStartup,
-		});
+	// The registry lives in the backend, reload it when it rejects a change
+	try {
+		await persist(action, state);
+	} catch (e) {
+		console.error("Realm registry update failed:", e);
+		await loadRegistry();
+		return;
 	}
 
 	switch (action.type) {
@@ -68,10 +73,6 @@ export async function realmEffects(action: Events, state: RealmState) {
 			});
 			break;
 		}
-		case "enter-realm":
-			store.set("current", action.realmId);
-			// Open main window and close selector
-			break;
 		case "remove-realm":
 			// Clean up localStorage for removed realm
 			localStorage.removeItem(`${action.realmId}-color-primary`);
@@ -82,9 +83,7 @@ export async function realmEffects(action: Events, state: RealmState) {
 
 // Helper functions for convenience
 export function getCurrentRealm(): Realm | null {
-	if (!store) return;
-	const list: Realm[] = store.get("realms");
-	return list.find((i) => i.id === store.get("current"));
+	return _state?.().realm.current ?? null;
 }
 
 export function getRealmList(state: RealmState): Realm[] {
@@ -93,24 +92,23 @@ export function getRealmList(state: RealmState): Realm[] {
 
 export function toggleRealm() {
 	hollow.events.emit("confirm", {
-		title: "Warning",
+		title: "Switch Realm",
 		message:
-			"Switching realms requires a restart of the application.\\nWould you like to proceed?",
+			"This closes the current realm and opens the realm selector.\nWould you like to proceed?",
 		onAccept: async () => {
-			// Clear current realm
-			store?.set("current", null);
-			// Open selector window
-			//	await invoke("open_realm_selector");
-			// Close current window
-			//	await getCurrentWindow().close();
+			await open_realm_selector();
 		},
 	});
 }
 
 export function selectRealmOnStartup(): boolean {
-	return store?.get("select_on_startup") ?? false;
+	return _state?.().realm.selectOnStartup ?? false;
 }
 
 export function setSelectRealmOnStartup(value: boolean) {
-	store?.set("select_on_startup", value);
+	_dispatch({
+		domain: "realm",
+		type: "set-select-on-startup",
+		value,
+	});
 }